@@ -2,6 +2,10 @@ use nalgebra_glm as glm;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+// Presses released within this many pixels of where they started are clicks
+// rather than drags
+const CLICK_DISTANCE: f32 = 4.0;
+
 lazy_static! {
     static ref APP_STATE: Mutex<Arc<AppState>> = Mutex::new(Arc::new(AppState::new()));
 }
@@ -10,9 +14,9 @@ pub fn update_app_state(time: f32, canvas_width: f32, canvas_height: f32) {
     let mut data = APP_STATE.lock().unwrap();
 
     *data = Arc::new(AppState {
-        time: time,
-        canvas_width: canvas_width,
-        canvas_height: canvas_height,
+        time,
+        canvas_width,
+        canvas_height,
         ..*data.clone()
     })
 }
@@ -23,10 +27,16 @@ pub fn get_current_app_state() -> Arc<AppState> {
 
 pub fn update_mouse_down(x: f32, y: f32, is_down: bool) {
     let mut data = APP_STATE.lock().unwrap();
+    let inverted_y = data.canvas_height - y;
+    let (press_x, press_y) = if is_down { (x, inverted_y) } else { (data.press_x, data.press_y) };
+    let clicked = !is_down && data.mouse_down && (x - press_x).hypot(inverted_y - press_y) <= CLICK_DISTANCE;
     *data = Arc::new(AppState {
         mouse_down: is_down,
         mouse_x: x,
-        mouse_y: data.canvas_height - y,
+        mouse_y: inverted_y,
+        press_x,
+        press_y,
+        clicks: data.clicks.wrapping_add(clicked as u32),
         ..*data.clone()
     })
 }
@@ -43,6 +53,7 @@ pub fn update_mouse_position(x: f32, y: f32) {
         mouse_y: inverted_y,
         rotation_x: data.rotation_x + rotate_x_delta,
         rotation_y: data.rotation_y - rotate_y_delta,
+        pointer_moves: data.pointer_moves.wrapping_add(1),
         ..*data.clone()
     })
 }
@@ -56,6 +67,13 @@ pub struct AppState {
     pub mouse_y: f32,
     pub rotation_x: f32,
     pub rotation_y: f32,
+    // Where the mouse was last pressed
+    pub press_x: f32,
+    pub press_y: f32,
+    // Counts of pointer events so far, so entities can tell when there are new
+    // ones to respond to
+    pub pointer_moves: u32,
+    pub clicks: u32,
 }
 
 impl AppState {
@@ -69,17 +87,28 @@ impl AppState {
             mouse_y: -1.0,
             rotation_x: 2.8,
             rotation_y: 0.8,
+            press_x: -1.0,
+            press_y: -1.0,
+            pointer_moves: 0,
+            clicks: 0,
         }
     }
 
+    pub fn get_camera_position(&self) -> glm::Vec3 {
+        glm::vec3(0.0, 4.0, 0.0)
+    }
+
+    pub fn get_view_matrix(&self) -> glm::Mat4 {
+        glm::look_at(
+            &self.get_camera_position(), // Camera is at (0,4,0), in World Space
+            &glm::vec3(0.0, 0.0, 0.0),   // and looks at the origin
+            &glm::vec3(0.0, 0.0, 1.0),   // Head is up
+        )
+    }
+
     pub fn get_projection_matrix(&self) -> glm::Mat4 {
         let aspect_ratio = self.canvas_width / self.canvas_height;
         //glm::ortho(-aspect_ratio, aspect_ratio, -1.0, 1.0, -1.0, 1.0)
-        let view = glm::look_at(
-            &glm::vec3(0.0, 4.0, 0.0), // Camera is at (0,5,0), in World Space
-            &glm::vec3(0.0, 0.0, 0.0), // and looks at the origin
-            &glm::vec3(0.0, 0.0, 1.0), // Head is up
-        );
-        glm::perspective(aspect_ratio, 45.0 * std::f32::consts::PI / 180.0, 0.1, 100.0) * view
+        glm::perspective(aspect_ratio, 45.0 * std::f32::consts::PI / 180.0, 0.1, 100.0) * self.get_view_matrix()
     }
 }
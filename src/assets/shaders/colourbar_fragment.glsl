@@ -0,0 +1,26 @@
+precision mediump float;
+
+// One pixel high lookup texture, COLOURMAP_WIDTH texels wide
+uniform sampler2D uColourmap;
+// Number of discrete colour bands, or 0 for a continuous gradient
+uniform float uColourBands;
+// Used for the frame and ticks, whose values are negative
+uniform vec4 uFrameColour;
+
+varying highp float vValue;
+
+#define COLOURMAP_WIDTH 256.0
+
+void main() {
+    if (vValue < 0.0) {
+        gl_FragColor = uFrameColour;
+        return;
+    }
+    float t = clamp(vValue, 0.0, 1.0);
+    if (uColourBands > 0.0) {
+        t = (min(floor(t * uColourBands), uColourBands - 1.0) + 0.5) / uColourBands;
+    }
+    // Keep lookups between the first and last texel centres
+    float u = (t * (COLOURMAP_WIDTH - 1.0) + 0.5) / COLOURMAP_WIDTH;
+    gl_FragColor = vec4(texture2D(uColourmap, vec2(u, 0.5)).rgb, 1.0);
+}
\ No newline at end of file
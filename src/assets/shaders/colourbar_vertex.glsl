@@ -0,0 +1,12 @@
+attribute vec2 aPosition;
+attribute float aValue;
+
+// Positions are canvas pixels from the top left
+uniform vec2 uScreenSize;
+
+varying highp float vValue;
+
+void main() {
+    gl_Position = vec4(aPosition.x / uScreenSize.x * 2.0 - 1.0, 1.0 - aPosition.y / uScreenSize.y * 2.0, 0.0, 1.0);
+    vValue = aValue;
+}
\ No newline at end of file
@@ -1,7 +1,56 @@
+#ifdef GL_OES_standard_derivatives
+#extension GL_OES_standard_derivatives : enable
+#endif
+
 precision mediump float;
 
-varying lowp vec4 vColour;
+// 0 = solid, 1 = wireframe, 2 = solid with grid lines
+uniform int uRenderMode;
+// Grid cells along x and z
+uniform vec2 uGridLines;
+uniform vec4 uGridLineColour;
+
+// One pixel high lookup texture, COLOURMAP_WIDTH texels wide
+uniform sampler2D uColourmap;
+// Number of discrete colour bands, or 0 for a continuous gradient
+uniform float uColourBands;
+
+varying lowp vec3 vLighting;
+varying highp float vColourPosition;
+varying highp vec2 vGridPosition;
+
+#define COLOURMAP_WIDTH 256.0
 
 void main() {
-    gl_FragColor = vec4(vColour.r, vColour.g, vColour.b, vColour.a);
+    float t = clamp(vColourPosition, 0.0, 1.0);
+    if (uColourBands > 0.0) {
+        t = (min(floor(t * uColourBands), uColourBands - 1.0) + 0.5) / uColourBands;
+    }
+    // Keep lookups between the first and last texel centres
+    float u = (t * (COLOURMAP_WIDTH - 1.0) + 0.5) / COLOURMAP_WIDTH;
+    vec4 colour = vec4(texture2D(uColourmap, vec2(u, 0.5)).rgb * vLighting, 1.0);
+
+    if (uRenderMode == 0) {
+        gl_FragColor = colour;
+        return;
+    }
+
+    // Lines are drawn procedurally so their density doesn't depend on the mesh
+    highp vec2 coord = vGridPosition * uGridLines;
+#ifdef GL_OES_standard_derivatives
+    vec2 lineWidth = fwidth(coord);
+#else
+    vec2 lineWidth = vec2(0.05, 0.05);
+#endif
+    vec2 distance = abs(fract(coord - 0.5) - 0.5) / lineWidth;
+    float line = 1.0 - min(min(distance.x, distance.y), 1.0);
+
+    if (uRenderMode == 1) {
+        if (line <= 0.0) {
+            discard;
+        }
+        gl_FragColor = vec4(uGridLineColour.rgb, uGridLineColour.a * line);
+    } else {
+        gl_FragColor = vec4(mix(colour.rgb, uGridLineColour.rgb, line * uGridLineColour.a), colour.a);
+    }
 }
\ No newline at end of file
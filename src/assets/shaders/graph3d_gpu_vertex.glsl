@@ -0,0 +1,106 @@
+attribute vec4 aPosition;
+// Heights and normals are found here instead, but these keep aScalar at the
+// location the grid mesh gives it
+attribute float aY;
+attribute vec3 aVertexNormal;
+attribute float aScalar;
+
+uniform mat4 uNormalsRotation;
+uniform mat4 uViewProjection;
+uniform mat4 uModel;
+
+// Maps the [-1, 1] grid and data heights into the display box
+uniform vec3 uDisplayScale;
+uniform float uHeightOrigin;
+
+// x_min, x_max, z_min, z_max of the domain the grid covers
+uniform vec4 uExtent;
+// Spacing of the grid's samples in data units
+uniform vec2 uGridStep;
+uniform float uTime;
+// MAX_GPU_PARAMETERS long
+uniform float uParameters[16];
+
+// Colour by aScalar instead of height, mapping uColourRange onto the colourmap
+uniform bool uUseScalars;
+uniform vec2 uColourRange;
+
+varying lowp vec3 vLighting;
+varying highp float vColourPosition;
+varying highp vec2 vGridPosition;
+
+// Functions GLSL ES 1.0 lacks, or leaves undefined where Rust's aren't
+float hSinh(float a) {
+    return 0.5 * (exp(a) - exp(-a));
+}
+
+float hCosh(float a) {
+    return 0.5 * (exp(a) + exp(-a));
+}
+
+float hTanh(float a) {
+    float e = exp(2.0 * clamp(a, -10.0, 10.0));
+    return (e - 1.0) / (e + 1.0);
+}
+
+float hLog10(float a) {
+    return log(a) * 0.4342944819;
+}
+
+// Halves round away from zero
+float hRound(float a) {
+    return sign(a) * floor(abs(a) + 0.5);
+}
+
+float hHypot(float a, float b) {
+    return length(vec2(a, b));
+}
+
+// Negative bases are allowed with whole exponents
+float hPow(float a, float b) {
+    if (b == 0.0) {
+        return 1.0;
+    }
+    if (a >= 0.0 || b != floor(b)) {
+        return pow(a, b);
+    }
+    float magnitude = pow(-a, b);
+    return mod(b, 2.0) == 0.0 ? magnitude : -magnitude;
+}
+
+// Truncated like Rust's %, where mod() floors
+float hRem(float a, float b) {
+    float quotient = a / b;
+    return a - b * sign(quotient) * floor(abs(quotient));
+}
+
+float height(float x, float z, float t) {
+    return HEIGHT_EXPRESSION;
+}
+
+void main() {
+    vec2 point = mix(uExtent.xz, uExtent.yw, (aPosition.xz + 1.0) * 0.5);
+    float y = height(point.x, point.y, uTime);
+
+    vec3 position = vec3(aPosition.x, y - uHeightOrigin, aPosition.z) * uDisplayScale;
+    gl_Position = uViewProjection * uModel * vec4(position, 1.0);
+    vGridPosition = (aPosition.xz + 1.0) * 0.5;
+
+    // Central differences a grid step either side, as slopes across the
+    // [-1, 1] grid like the CPU's normals
+    vec2 halfSpan = (uExtent.yw - uExtent.xz) * 0.5;
+    float dx = (height(point.x + uGridStep.x, point.y, uTime) - height(point.x - uGridStep.x, point.y, uTime)) / (2.0 * uGridStep.x);
+    float dz = (height(point.x, point.y + uGridStep.y, uTime) - height(point.x, point.y - uGridStep.y, uTime)) / (2.0 * uGridStep.y);
+
+    vec3 ambientLightColour = vec3(0.5, 0.5, 0.5);
+    vec3 directionalLightColour = vec3(1.0, 1.0, 1.0);
+    vec3 directionalVector = normalize(vec3(-0.8, 0.8, 0.75));
+
+    vec3 normal = normalize(vec3(-dx * halfSpan.x, 1.0, -dz * halfSpan.y) / uDisplayScale);
+    vec4 transformedNormal = uNormalsRotation * vec4(normal, 1.0);
+    float directional = max(dot(transformedNormal.xyz, directionalVector), 0.0);
+    vLighting = ambientLightColour + (directionalLightColour * directional);
+
+    float value = uUseScalars ? aScalar : y;
+    vColourPosition = (value - uColourRange.x) / (uColourRange.y - uColourRange.x);
+}
\ No newline at end of file
@@ -1,28 +1,39 @@
 attribute vec4 aPosition;
 attribute float aY;
 attribute vec3 aVertexNormal;
+attribute float aScalar;
 
 uniform mat4 uNormalsRotation;
 uniform mat4 uViewProjection;
 uniform mat4 uModel;
 
-varying lowp vec4 vColour;
+// Maps the [-1, 1] grid and data heights into the display box
+uniform vec3 uDisplayScale;
+uniform float uHeightOrigin;
+
+// Colour by aScalar instead of height, mapping uColourRange onto the colourmap
+uniform bool uUseScalars;
+uniform vec2 uColourRange;
+
+varying lowp vec3 vLighting;
+varying highp float vColourPosition;
+varying highp vec2 vGridPosition;
 
 void main() {
-    gl_Position = uViewProjection * uModel * vec4(aPosition.x, aY, aPosition.z, 1.0);
+    vec3 position = vec3(aPosition.x, aY - uHeightOrigin, aPosition.z) * uDisplayScale;
+    gl_Position = uViewProjection * uModel * vec4(position, 1.0);
+    vGridPosition = (aPosition.xz + 1.0) * 0.5;
 
     vec3 ambientLightColour = vec3(0.5, 0.5, 0.5);
     vec3 directionalLightColour = vec3(1.0, 1.0, 1.0);
     vec3 directionalVector = normalize(vec3(-0.8, 0.8, 0.75));
 
-    vec4 transformedNormal = uNormalsRotation * vec4(aVertexNormal, 1.0);
+    // Normals are found on the unscaled grid, so they stretch the opposite way
+    vec3 normal = normalize(aVertexNormal / uDisplayScale);
+    vec4 transformedNormal = uNormalsRotation * vec4(normal, 1.0);
     float directional = max(dot(transformedNormal.xyz, directionalVector), 0.0);
-    vec3 vLighting = ambientLightColour + (directionalLightColour * directional);
-    
-    vec3 baseColour = vec3(0.2, 0.3, 0.8);
-
-
-    vColour = vec4(baseColour * vLighting, 1.0);
+    vLighting = ambientLightColour + (directionalLightColour * directional);
 
-    
+    float value = uUseScalars ? aScalar : aY;
+    vColourPosition = (value - uColourRange.x) / (uColourRange.y - uColourRange.x);
 }
\ No newline at end of file
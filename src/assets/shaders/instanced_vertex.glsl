@@ -0,0 +1,25 @@
+attribute vec3 aPosition;
+attribute vec3 aVertexNormal;
+attribute mat4 aInstanceTransform;
+attribute vec4 aInstanceColour;
+
+uniform mat4 uNormalMatrix;
+uniform mat4 uViewProjection;
+uniform mat4 uModel;
+
+varying lowp vec4 vColour;
+
+void main() {
+    gl_Position = uViewProjection * uModel * aInstanceTransform * vec4(aPosition, 1.0);
+
+    vec3 ambientLightColour = vec3(0.5, 0.5, 0.5);
+    vec3 directionalLightColour = vec3(1.0, 1.0, 1.0);
+    vec3 directionalVector = normalize(vec3(-0.8, 0.8, 0.75));
+
+    // Exact for instance transforms made of rotations and uniform scales
+    vec3 transformedNormal = normalize((uNormalMatrix * aInstanceTransform * vec4(aVertexNormal, 0.0)).xyz);
+    float directional = max(dot(transformedNormal, directionalVector), 0.0);
+    vec3 vLighting = ambientLightColour + (directionalLightColour * directional);
+
+    vColour = vec4(aInstanceColour.rgb * vLighting, aInstanceColour.a);
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+attribute vec2 aPosition;
+
+// Data range on each axis, mapped onto uRect (left, bottom, right, top in clip space)
+uniform vec2 uDataMin;
+uniform vec2 uDataMax;
+uniform vec4 uRect;
+uniform vec4 uColour;
+
+varying lowp vec4 vColour;
+
+void main() {
+    vec2 fraction = (aPosition - uDataMin) / (uDataMax - uDataMin);
+    gl_Position = vec4(mix(uRect.xy, uRect.zw, fraction), 0.0, 1.0);
+    vColour = uColour;
+}
\ No newline at end of file
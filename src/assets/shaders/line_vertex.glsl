@@ -0,0 +1,12 @@
+attribute vec3 aPosition;
+attribute vec4 aColour;
+
+uniform mat4 uViewProjection;
+uniform mat4 uModel;
+
+varying lowp vec4 vColour;
+
+void main() {
+    gl_Position = uViewProjection * uModel * vec4(aPosition, 1.0);
+    vColour = aColour;
+}
\ No newline at end of file
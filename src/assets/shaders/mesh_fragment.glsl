@@ -0,0 +1,16 @@
+precision mediump float;
+
+uniform vec4 uBaseColour;
+uniform sampler2D uTexture;
+uniform bool uUseTexture;
+
+varying highp vec2 vTexCoord;
+varying lowp vec3 vLighting;
+
+void main() {
+    vec4 colour = uBaseColour;
+    if (uUseTexture) {
+        colour *= texture2D(uTexture, vTexCoord);
+    }
+    gl_FragColor = vec4(colour.rgb * vLighting, colour.a);
+}
\ No newline at end of file
@@ -0,0 +1,26 @@
+attribute vec3 aPosition;
+attribute vec3 aVertexNormal;
+attribute vec2 aTexCoord;
+
+uniform mat4 uNormalMatrix;
+uniform mat4 uViewProjection;
+uniform mat4 uModel;
+// Light both sides of open surfaces, whichever way their normals face
+uniform bool uTwoSided;
+
+varying highp vec2 vTexCoord;
+varying lowp vec3 vLighting;
+
+void main() {
+    gl_Position = uViewProjection * uModel * vec4(aPosition, 1.0);
+
+    vec3 ambientLightColour = vec3(0.5, 0.5, 0.5);
+    vec3 directionalLightColour = vec3(1.0, 1.0, 1.0);
+    vec3 directionalVector = normalize(vec3(-0.8, 0.8, 0.75));
+
+    vec3 transformedNormal = normalize((uNormalMatrix * vec4(aVertexNormal, 0.0)).xyz);
+    float facing = dot(transformedNormal, directionalVector);
+    float directional = uTwoSided ? abs(facing) : max(facing, 0.0);
+    vLighting = ambientLightColour + (directionalLightColour * directional);
+    vTexCoord = aTexCoord;
+}
\ No newline at end of file
@@ -0,0 +1,11 @@
+precision mediump float;
+
+varying lowp vec3 vColour;
+
+void main() {
+    vec2 offset = gl_PointCoord - vec2(0.5, 0.5);
+    if (dot(offset, offset) > 0.25) {
+        discard;
+    }
+    gl_FragColor = vec4(vColour, 1.0);
+}
\ No newline at end of file
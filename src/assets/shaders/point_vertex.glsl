@@ -0,0 +1,19 @@
+attribute vec3 aPosition;
+attribute vec3 aColour;
+
+uniform mat4 uViewProjection;
+uniform mat4 uModel;
+uniform float uPointSize;
+uniform bool uSizeAttenuation;
+uniform float uAttenuationDistance;
+
+varying lowp vec3 vColour;
+
+void main() {
+    gl_Position = uViewProjection * uModel * vec4(aPosition, 1.0);
+
+    // With a perspective projection w is the depth along the view direction,
+    // so points at uAttenuationDistance are drawn at exactly uPointSize
+    gl_PointSize = uSizeAttenuation ? uPointSize * uAttenuationDistance / gl_Position.w : uPointSize;
+    vColour = aColour;
+}
\ No newline at end of file
@@ -0,0 +1,35 @@
+precision mediump float;
+
+// One pixel high lookup texture, COLOURMAP_WIDTH texels wide
+uniform sampler2D uColourmap;
+uniform bool uUseScalars;
+
+varying lowp vec4 vColour;
+varying highp float vColourPosition;
+// 0 = circle, 1 = square, 2 = cross
+varying lowp float vShape;
+
+#define COLOURMAP_WIDTH 256.0
+// Half the width of each arm of a cross, as a fraction of the sprite
+#define CROSS_HALF_WIDTH 0.12
+
+void main() {
+    vec2 offset = gl_PointCoord - vec2(0.5, 0.5);
+    if (vShape < 0.5) {
+        if (dot(offset, offset) > 0.25) {
+            discard;
+        }
+    } else if (vShape > 1.5) {
+        if (abs(offset.x) > CROSS_HALF_WIDTH && abs(offset.y) > CROSS_HALF_WIDTH) {
+            discard;
+        }
+    }
+
+    vec4 colour = vColour;
+    if (uUseScalars) {
+        // Keep lookups between the first and last texel centres
+        float u = (clamp(vColourPosition, 0.0, 1.0) * (COLOURMAP_WIDTH - 1.0) + 0.5) / COLOURMAP_WIDTH;
+        colour.rgb = texture2D(uColourmap, vec2(u, 0.5)).rgb;
+    }
+    gl_FragColor = colour;
+}
\ No newline at end of file
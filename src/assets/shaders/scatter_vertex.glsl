@@ -0,0 +1,27 @@
+attribute vec3 aPosition;
+attribute float aSize;
+attribute vec4 aColour;
+attribute float aScalar;
+attribute float aShape;
+
+uniform mat4 uViewProjection;
+uniform mat4 uModel;
+uniform bool uSizeAttenuation;
+uniform float uAttenuationDistance;
+
+// Maps aScalar onto the colourmap when uUseScalars is set
+uniform bool uUseScalars;
+uniform vec2 uColourRange;
+
+varying lowp vec4 vColour;
+varying highp float vColourPosition;
+varying lowp float vShape;
+
+void main() {
+    gl_Position = uViewProjection * uModel * vec4(aPosition, 1.0);
+    gl_PointSize = uSizeAttenuation ? aSize * uAttenuationDistance / gl_Position.w : aSize;
+
+    vColour = aColour;
+    vColourPosition = (aScalar - uColourRange.x) / (uColourRange.y - uColourRange.x);
+    vShape = aShape;
+}
\ No newline at end of file
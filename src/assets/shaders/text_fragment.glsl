@@ -0,0 +1,38 @@
+#ifdef GL_OES_standard_derivatives
+#extension GL_OES_standard_derivatives : enable
+#endif
+
+precision mediump float;
+
+uniform sampler2D uAtlas;
+uniform vec2 uAtlasSize;
+// Atlas pixels the distance field spans
+uniform float uDistanceRange;
+uniform bool uMsdf;
+
+varying highp vec2 vTexCoord;
+varying lowp vec4 vColour;
+
+float median(float r, float g, float b) {
+    return max(min(r, g), min(max(r, g), b));
+}
+
+void main() {
+    vec3 texel = texture2D(uAtlas, vTexCoord).rgb;
+    float signedDistance = uMsdf ? median(texel.r, texel.g, texel.b) : texel.r;
+
+#ifdef GL_OES_standard_derivatives
+    // Scale the edge to about one screen pixel wide at any text size
+    vec2 unitRange = vec2(uDistanceRange) / uAtlasSize;
+    vec2 screenTexSize = vec2(1.0) / fwidth(vTexCoord);
+    float screenPxRange = max(0.5 * dot(unitRange, screenTexSize), 1.0);
+    float alpha = clamp(screenPxRange * (signedDistance - 0.5) + 0.5, 0.0, 1.0);
+#else
+    float alpha = smoothstep(0.4, 0.6, signedDistance);
+#endif
+
+    if (alpha <= 0.0) {
+        discard;
+    }
+    gl_FragColor = vec4(vColour.rgb, vColour.a * alpha);
+}
\ No newline at end of file
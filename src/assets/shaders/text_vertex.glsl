@@ -0,0 +1,30 @@
+attribute vec3 aAnchor;
+attribute vec2 aOffset;
+attribute vec2 aTexCoord;
+attribute vec4 aColour;
+
+uniform mat4 uViewProjection;
+uniform mat4 uModel;
+// Screen text anchors are canvas pixels from the top left and offsets are
+// pixels. Billboard anchors are scene positions and offsets are scene units
+// along the camera's right and up directions.
+uniform bool uScreenSpace;
+uniform vec2 uScreenSize;
+uniform vec3 uCameraRight;
+uniform vec3 uCameraUp;
+
+varying highp vec2 vTexCoord;
+varying lowp vec4 vColour;
+
+void main() {
+    if (uScreenSpace) {
+        vec2 pixel = aAnchor.xy + vec2(aOffset.x, -aOffset.y);
+        gl_Position = vec4(pixel.x / uScreenSize.x * 2.0 - 1.0, 1.0 - pixel.y / uScreenSize.y * 2.0, 0.0, 1.0);
+    } else {
+        vec4 anchor = uModel * vec4(aAnchor, 1.0);
+        vec3 position = anchor.xyz / anchor.w + uCameraRight * aOffset.x + uCameraUp * aOffset.y;
+        gl_Position = uViewProjection * vec4(position, 1.0);
+    }
+    vTexCoord = aTexCoord;
+    vColour = aColour;
+}
\ No newline at end of file
@@ -0,0 +1,193 @@
+use wasm_bindgen::prelude::*;
+
+// Resolution of the lookup textures colourmaps are sampled from on the GPU
+pub const COLOURMAP_TEXTURE_WIDTH: usize = 256;
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ColourmapPreset {
+    Viridis = 0,
+    Magma = 1,
+    Plasma = 2,
+    Jet = 3,
+    Grayscale = 4,
+    // Blue through light grey to red, for values either side of a midpoint
+    Diverging = 5,
+}
+
+// Evenly spaced samples of the matplotlib colourmaps
+const VIRIDIS: [u32; 10] = [0x440154, 0x482878, 0x3e4989, 0x31688e, 0x26828e, 0x1f9e89, 0x35b779, 0x6ece58, 0xb5de2b, 0xfde725];
+const MAGMA: [u32; 10] = [0x000004, 0x180f3d, 0x440f76, 0x721f81, 0x9e2f7f, 0xcd4071, 0xf1605d, 0xfd9668, 0xfeca8d, 0xfcfdbf];
+const PLASMA: [u32; 10] = [0x0d0887, 0x47039f, 0x7301a8, 0x9c179e, 0xbd3786, 0xd8576b, 0xed7953, 0xfa9e3b, 0xfdc926, 0xf0f921];
+
+// A piecewise linear gradient. Stop positions are in [0, 1] and increasing.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Colourmap {
+    stops: Vec<(f32, [f32; 3])>,
+}
+
+impl Colourmap {
+    pub fn preset(preset: ColourmapPreset) -> Self {
+        match preset {
+            ColourmapPreset::Viridis => Self::from_hex(&VIRIDIS),
+            ColourmapPreset::Magma => Self::from_hex(&MAGMA),
+            ColourmapPreset::Plasma => Self::from_hex(&PLASMA),
+            ColourmapPreset::Jet => Self {
+                stops: vec![
+                    (0.0, [0.0, 0.0, 0.5]),
+                    (0.125, [0.0, 0.0, 1.0]),
+                    (0.375, [0.0, 1.0, 1.0]),
+                    (0.625, [1.0, 1.0, 0.0]),
+                    (0.875, [1.0, 0.0, 0.0]),
+                    (1.0, [0.5, 0.0, 0.0]),
+                ],
+            },
+            ColourmapPreset::Grayscale => Self {
+                stops: vec![(0.0, [0.0, 0.0, 0.0]), (1.0, [1.0, 1.0, 1.0])],
+            },
+            ColourmapPreset::Diverging => Self {
+                stops: vec![(0.0, [0.23, 0.3, 0.75]), (0.5, [0.87, 0.87, 0.87]), (1.0, [0.71, 0.02, 0.15])],
+            },
+        }
+    }
+
+    // `colours` holds an RGB triple, each channel in [0, 1], for every
+    // position in `positions`
+    pub fn from_stops(positions: &[f32], colours: &[f32]) -> Result<Self, String> {
+        if positions.is_empty() {
+            return Err(String::from("A colour gradient needs at least one stop"));
+        }
+        if colours.len() != 3 * positions.len() {
+            return Err(format!(
+                "{} gradient stops need {} colour values but were given {}",
+                positions.len(),
+                3 * positions.len(),
+                colours.len()
+            ));
+        }
+        if positions.iter().any(|p| !(0.0..=1.0).contains(p)) {
+            return Err(String::from("Gradient stop positions must be between 0 and 1"));
+        }
+        if positions.windows(2).any(|pair| pair[1] < pair[0]) {
+            return Err(String::from("Gradient stop positions must be in increasing order"));
+        }
+
+        let stops = positions.iter().zip(colours.chunks(3)).map(|(&p, c)| (p, [c[0], c[1], c[2]])).collect();
+        Ok(Self { stops })
+    }
+
+    fn from_hex(colours: &[u32]) -> Self {
+        let last = (colours.len() - 1) as f32;
+        let stops = colours
+            .iter()
+            .enumerate()
+            .map(|(i, &hex)| {
+                let channel = |shift: u32| ((hex >> shift) & 0xff) as f32 / 255.0;
+                (i as f32 / last, [channel(16), channel(8), channel(0)])
+            })
+            .collect();
+        Self { stops }
+    }
+
+    // The colour at `t`, which is clamped to [0, 1]
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+            if t <= p1 {
+                let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+                return [c0[0] + (c1[0] - c0[0]) * f, c0[1] + (c1[1] - c0[1]) * f, c0[2] + (c1[2] - c0[2]) * f];
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+
+    // COLOURMAP_TEXTURE_WIDTH RGBA pixels running from 0 to 1
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let last = (COLOURMAP_TEXTURE_WIDTH - 1) as f32;
+        (0..COLOURMAP_TEXTURE_WIDTH)
+            .flat_map(|i| {
+                let [r, g, b] = self.sample(i as f32 / last);
+                vec![(r * 255.0).round() as u8, (g * 255.0).round() as u8, (b * 255.0).round() as u8, 255]
+            })
+            .collect()
+    }
+}
+
+impl Default for Colourmap {
+    fn default() -> Self {
+        Self::preset(ColourmapPreset::Viridis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-5)
+    }
+
+    #[test]
+    fn stops_are_validated() {
+        assert!(Colourmap::from_stops(&[], &[]).is_err());
+        assert!(Colourmap::from_stops(&[0.0, 1.0], &[0.0, 0.0, 0.0, 1.0, 1.0]).is_err());
+        assert!(Colourmap::from_stops(&[-0.1, 1.0], &[0.0; 6]).is_err());
+        assert!(Colourmap::from_stops(&[0.0, 1.5], &[0.0; 6]).is_err());
+        assert!(Colourmap::from_stops(&[0.0, f32::NAN], &[0.0; 6]).is_err());
+        assert!(Colourmap::from_stops(&[0.6, 0.4], &[0.0; 6]).is_err());
+        assert!(Colourmap::from_stops(&[0.5], &[1.0, 0.0, 0.0]).is_ok());
+    }
+
+    #[test]
+    fn samples_are_interpolated_and_clamped() {
+        let map = Colourmap::from_stops(&[0.2, 0.6], &[0.0, 0.0, 1.0, 1.0, 0.5, 0.0]).unwrap();
+        assert!(close(map.sample(0.4), [0.5, 0.25, 0.5]));
+        assert!(close(map.sample(0.5), [0.75, 0.375, 0.25]));
+        // Before the first stop and after the last the end colours carry on
+        assert!(close(map.sample(0.0), [0.0, 0.0, 1.0]));
+        assert!(close(map.sample(0.9), [1.0, 0.5, 0.0]));
+        assert!(close(map.sample(-3.0), [0.0, 0.0, 1.0]));
+        assert!(close(map.sample(7.0), [1.0, 0.5, 0.0]));
+
+        let single = Colourmap::from_stops(&[0.5], &[0.1, 0.2, 0.3]).unwrap();
+        assert!(close(single.sample(0.0), [0.1, 0.2, 0.3]));
+        assert!(close(single.sample(1.0), [0.1, 0.2, 0.3]));
+    }
+
+    #[test]
+    fn repeated_positions_make_a_hard_step() {
+        let map = Colourmap::from_stops(&[0.0, 0.5, 0.5, 1.0], &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]).unwrap();
+        assert!(close(map.sample(0.25), [0.0, 0.0, 0.0]));
+        assert!(close(map.sample(0.5), [0.0, 0.0, 0.0]));
+        assert!(close(map.sample(0.5001), [1.0, 1.0, 1.0]));
+        assert!(close(map.sample(0.75), [1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn hex_stops_are_evenly_spaced() {
+        let map = Colourmap::from_hex(&[0xff0000, 0x00ff00, 0x0000ff]);
+        assert!(close(map.sample(0.0), [1.0, 0.0, 0.0]));
+        assert!(close(map.sample(0.25), [0.5, 0.5, 0.0]));
+        assert!(close(map.sample(0.5), [0.0, 1.0, 0.0]));
+        assert!(close(map.sample(1.0), [0.0, 0.0, 1.0]));
+
+        let viridis = Colourmap::preset(ColourmapPreset::Viridis);
+        assert!(close(viridis.sample(0.0), [68.0 / 255.0, 1.0 / 255.0, 84.0 / 255.0]));
+        assert!(close(viridis.sample(1.0), [253.0 / 255.0, 231.0 / 255.0, 37.0 / 255.0]));
+    }
+
+    #[test]
+    fn texture_runs_from_the_first_stop_to_the_last() {
+        let map = Colourmap::from_hex(&[0x102030, 0xa0b0c0]);
+        let rgba = map.to_rgba();
+        assert_eq!(rgba.len(), 4 * COLOURMAP_TEXTURE_WIDTH);
+        assert_eq!(rgba[..4], [0x10, 0x20, 0x30, 255]);
+        assert_eq!(rgba[rgba.len() - 4..], [0xa0, 0xb0, 0xc0, 255]);
+        assert!(rgba.chunks(4).all(|texel| texel[3] == 255));
+    }
+}
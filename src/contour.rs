@@ -0,0 +1,155 @@
+// Marching squares over a row-major height grid, `width` samples per row and
+// `depth` rows. Points are in grid units: (column, row), fractional along the
+// cell edges they cross.
+pub type Segment = [[f32; 2]; 2];
+
+// Every segment of the iso-line at `level`. Corners at or above the level
+// count as inside, and cells with a non-finite corner are skipped.
+pub fn contour_segments(heights: &[f32], width: usize, depth: usize, level: f32) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for row in 0..depth.saturating_sub(1) {
+        for column in 0..width.saturating_sub(1) {
+            let top_left = row * width + column;
+            // Corners clockwise from the top left, as (column, row, height)
+            let corners = [
+                (column as f32, row as f32, heights[top_left]),
+                (column as f32 + 1.0, row as f32, heights[top_left + 1]),
+                (column as f32 + 1.0, row as f32 + 1.0, heights[top_left + width + 1]),
+                (column as f32, row as f32 + 1.0, heights[top_left + width]),
+            ];
+            if corners.iter().any(|corner| !corner.2.is_finite()) {
+                continue;
+            }
+            cell_segments(&corners, level, &mut segments);
+        }
+    }
+    segments
+}
+
+// `count` levels spaced evenly between `min` and `max`, leaving out the ends
+// themselves as they only ever touch single points
+pub fn even_levels(min: f32, max: f32, count: usize) -> Vec<f32> {
+    let step = (max - min) / (count + 1) as f32;
+    (1..=count).map(|i| min + i as f32 * step).collect()
+}
+
+fn cell_segments(corners: &[(f32, f32, f32); 4], level: f32, segments: &mut Vec<Segment>) {
+    let case = corners
+        .iter()
+        .enumerate()
+        .fold(0, |case, (i, corner)| if corner.2 >= level { case | (1 << i) } else { case });
+
+    // Where the level crosses edge i, which runs from corner i to the next one
+    let crossing = |i: usize| {
+        let (x0, z0, h0) = corners[i];
+        let (x1, z1, h1) = corners[(i + 1) % 4];
+        let t = (level - h0) / (h1 - h0);
+        [x0 + t * (x1 - x0), z0 + t * (z1 - z0)]
+    };
+    let mut push = |a: usize, b: usize| segments.push([crossing(a), crossing(b)]);
+
+    // Edges are 0 top, 1 right, 2 bottom and 3 left
+    match case {
+        0 | 15 => {}
+        1 | 14 => push(3, 0),
+        2 | 13 => push(0, 1),
+        3 | 12 => push(3, 1),
+        4 | 11 => push(1, 2),
+        6 | 9 => push(0, 2),
+        7 | 8 => push(2, 3),
+        // Saddles: opposite corners are inside, and the average of all four
+        // decides whether the middle of the cell joins them
+        5 | 10 => {
+            let centre_inside = corners.iter().map(|corner| corner.2).sum::<f32>() / 4.0 >= level;
+            if (case == 5) == centre_inside {
+                push(0, 1);
+                push(2, 3);
+            } else {
+                push(3, 0);
+                push(1, 2);
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-5 && (a[1] - b[1]).abs() < 1e-5
+    }
+
+    fn has_segment(segments: &[Segment], a: [f32; 2], b: [f32; 2]) -> bool {
+        segments.iter().any(|s| (close(s[0], a) && close(s[1], b)) || (close(s[0], b) && close(s[1], a)))
+    }
+
+    #[test]
+    fn flat_grid_has_no_contours() {
+        let heights = vec![1.0; 9];
+        assert!(contour_segments(&heights, 3, 3, 0.5).is_empty());
+        assert!(contour_segments(&heights, 3, 3, 1.5).is_empty());
+    }
+
+    #[test]
+    fn single_corner_is_cut_off() {
+        // Only the bottom right corner is above the level
+        let heights = vec![0.0, 0.0, 0.0, 2.0];
+        let segments = contour_segments(&heights, 2, 2, 1.0);
+        assert_eq!(segments.len(), 1);
+        assert!(has_segment(&segments, [1.0, 0.5], [0.5, 1.0]));
+    }
+
+    #[test]
+    fn ramp_gives_a_straight_line_across_every_row() {
+        // Height is the column index, so the 1.5 contour sits halfway along x
+        let (width, depth) = (4, 3);
+        let heights: Vec<f32> = (0..width * depth).map(|i| (i % width) as f32).collect();
+        let segments = contour_segments(&heights, width, depth, 1.5);
+        assert_eq!(segments.len(), depth - 1);
+        for row in 0..depth - 1 {
+            assert!(has_segment(&segments, [1.5, row as f32], [1.5, row as f32 + 1.0]));
+        }
+    }
+
+    #[test]
+    fn crossings_are_interpolated() {
+        let heights = vec![0.0, 4.0, 0.0, 4.0];
+        let segments = contour_segments(&heights, 2, 2, 1.0);
+        assert!(has_segment(&segments, [0.25, 0.0], [0.25, 1.0]));
+    }
+
+    #[test]
+    fn saddles_follow_the_cell_average() {
+        // Top left and bottom right are high
+        let joined = contour_segments(&[2.0, 0.0, 0.5, 2.0], 2, 2, 1.0);
+        assert_eq!(joined.len(), 2);
+        // The high corners are joined through the middle, so each segment cuts
+        // off one of the low corners
+        assert!(has_segment(&joined, [0.5, 0.0], [1.0, 0.5]));
+        assert!(has_segment(&joined, [1.0 / 3.0, 1.0], [0.0, 2.0 / 3.0]));
+
+        let separate = contour_segments(&[1.2, 0.0, 0.0, 1.2], 2, 2, 1.0);
+        assert_eq!(separate.len(), 2);
+        let cut = 0.2 / 1.2;
+        // Each high corner is cut off on its own
+        assert!(has_segment(&separate, [0.0, cut], [cut, 0.0]));
+        assert!(has_segment(&separate, [1.0, 1.0 - cut], [1.0 - cut, 1.0]));
+    }
+
+    #[test]
+    fn cells_with_missing_heights_are_skipped() {
+        let heights = vec![0.0, 2.0, 4.0, 0.0, f32::NAN, 4.0];
+        let segments = contour_segments(&heights, 3, 2, 1.0);
+        assert!(segments.is_empty());
+        let segments = contour_segments(&heights, 3, 2, 3.0);
+        assert!(segments.is_empty());
+    }
+
+    #[test]
+    fn even_levels_leave_out_the_ends() {
+        assert_eq!(even_levels(0.0, 4.0, 3), vec![1.0, 2.0, 3.0]);
+        assert!(even_levels(0.0, 1.0, 0).is_empty());
+    }
+}
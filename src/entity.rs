@@ -1,3 +1,19 @@
+pub mod axes;
+pub mod buffers;
+pub mod colourbar;
+pub mod contours;
+#[allow(clippy::module_inception)]
 pub mod entity;
 pub mod graph3d;
+pub mod instanced_mesh;
+pub mod isosurface;
+pub mod line_plot;
+pub mod mesh;
+pub mod parametric_surface;
+pub mod point_cloud;
 pub mod quad;
+pub mod quiver;
+pub mod scatter;
+pub mod scene;
+pub mod text;
+pub mod vertex_array;
@@ -0,0 +1,390 @@
+use super::buffers::*;
+use super::entity::*;
+use super::graph3d::SurfaceBounds;
+use super::text::{Text, TextLabel, TextSpace};
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use crate::text_layout::{TextAlign, TextLayoutOptions, VerticalAlign};
+use nalgebra_glm as glm;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// Roughly how many ticks each axis aims for
+const TARGET_TICKS: usize = 5;
+// In model space, out from the edge the ticks sit on
+const TICK_LENGTH: f32 = 0.05;
+const TICK_LABEL_OFFSET: f32 = 0.12;
+const TITLE_OFFSET: f32 = 0.3;
+
+// A tick value or axis title and where it lands on the canvas, in pixels from
+// the top left
+pub struct AxisLabel {
+    pub text: String,
+    // 0 for x, 1 for y (height) and 2 for z
+    pub axis: usize,
+    pub is_title: bool,
+    pub x: f32,
+    pub y: f32,
+}
+
+// A bounding box with grids on the walls behind a Graph3d surface, drawn with
+// its transform. Tick labels and titles are drawn once it's given a font with
+// `set_font`; `labels` also gives their positions for pages that place their
+// own text.
+pub struct Axes {
+    shader_type: ShaderType,
+    vertex_array: VertexArray,
+    positions_array_buffer: WebGlBuffer,
+    colours_array_buffer: WebGlBuffer,
+    bounds: Rc<Cell<SurfaceBounds>>,
+    titles: [String; 3],
+    box_colour: [f32; 4],
+    grid_colour: [f32; 4],
+    // Kept up to date with `labels` as the axes are drawn
+    text: Option<RefCell<Text>>,
+    text_size: f32,
+    text_colour: [f32; 4],
+}
+
+// The box in model space, which walls face away from the camera, and where
+// each axis's ticks fall
+struct Layout {
+    min: glm::Vec3,
+    max: glm::Vec3,
+    back: glm::Vec3,
+    front: glm::Vec3,
+    // (value, model space position along the axis)
+    ticks: [Vec<(f32, f32)>; 3],
+    decimals: [usize; 3],
+}
+
+impl Entity for Axes {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+            let model = get_model_matrix(&current_state, position, rotation, scale);
+            let layout = self.layout(&current_state, &model);
+            let (positions, colours) = self.build_lines(&layout);
+
+            shader_controller.use_shader(gl, self.shader_type);
+            self.vertex_array.bind(gl);
+
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.positions_array_buffer));
+            upload_f32_buffer(gl, GL::ARRAY_BUFFER, &positions, GL::DYNAMIC_DRAW);
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.colours_array_buffer));
+            upload_f32_buffer(gl, GL::ARRAY_BUFFER, &colours, GL::DYNAMIC_DRAW);
+
+            let projection_matrix = current_state.get_projection_matrix();
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            gl.draw_arrays(GL::LINES, 0, (positions.len() / 3) as i32);
+            self.vertex_array.unbind();
+
+            if let Some(text) = &self.text {
+                let labels = self
+                    .layout_labels(&current_state, &model, &layout)
+                    .into_iter()
+                    .map(|label| TextLabel {
+                        text: label.text,
+                        space: TextSpace::Screen,
+                        position: [label.x, label.y, 0.0],
+                        colour: self.text_colour,
+                        options: TextLayoutOptions {
+                            size: self.text_size,
+                            align: TextAlign::Centre,
+                            vertical_align: VerticalAlign::Middle,
+                            ..Default::default()
+                        },
+                    })
+                    .collect();
+                text.borrow_mut().set_labels(gl, labels);
+                text.borrow().render(gl, shader_controller, position, rotation, scale);
+            }
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Axes {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, bounds: Rc<Cell<SurfaceBounds>>) -> Result<Self, String> {
+        let positions_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let colours_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, positions_array_buffer.clone(), 3),
+                VertexAttribute::buffer(1, colours_array_buffer.clone(), 4),
+            ],
+            None,
+        );
+
+        Ok(Self {
+            shader_type,
+            vertex_array,
+            positions_array_buffer,
+            colours_array_buffer,
+            bounds,
+            titles: [String::from("x"), String::from("y"), String::from("z")],
+            box_colour: [0.8, 0.8, 0.8, 1.0],
+            grid_colour: [0.5, 0.5, 0.5, 0.5],
+            text: None,
+            text_size: 14.0,
+            text_colour: [0.8, 0.8, 0.8, 1.0],
+        })
+    }
+
+    // Empty titles are left out of `labels`
+    pub fn set_titles(&mut self, titles: [String; 3]) {
+        self.titles = titles;
+    }
+
+    // Draws the tick labels and titles with `text`, an empty text entity
+    // sharing a loaded font, at `size` pixels, centred on their positions
+    pub fn set_font(&mut self, text: Text, size: f32, colour: [f32; 4]) -> Result<(), String> {
+        if !(size.is_finite() && size > 0.0) {
+            return Err(String::from("Axes text size must be positive"));
+        }
+        self.text = Some(RefCell::new(text));
+        self.text_size = size;
+        self.text_colour = colour;
+        Ok(())
+    }
+
+    // Where the tick labels and titles go for the current view
+    pub fn labels(&self, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) -> Vec<AxisLabel> {
+        let current_state = get_current_app_state();
+        let model = get_model_matrix(&current_state, position, rotation, scale);
+        let layout = self.layout(&current_state, &model);
+        self.layout_labels(&current_state, &model, &layout)
+    }
+
+    fn layout_labels(&self, current_state: &AppState, model: &glm::Mat4, layout: &Layout) -> Vec<AxisLabel> {
+        let model_view_projection = current_state.get_projection_matrix() * model;
+        let mut labels = Vec::new();
+        for axis in 0..3 {
+            let (edge, outward) = label_edge(layout, axis);
+            for &(value, along) in layout.ticks[axis].iter() {
+                let mut point = edge + outward * TICK_LABEL_OFFSET;
+                point[axis] = along;
+                if let Some((x, y)) = project(current_state, &model_view_projection, &point) {
+                    labels.push(AxisLabel {
+                        text: format!("{:.*}", layout.decimals[axis], value),
+                        axis,
+                        is_title: false,
+                        x,
+                        y,
+                    });
+                }
+            }
+
+            if !self.titles[axis].is_empty() {
+                let mut point = edge + outward * TITLE_OFFSET;
+                point[axis] = (layout.min[axis] + layout.max[axis]) * 0.5;
+                if let Some((x, y)) = project(current_state, &model_view_projection, &point) {
+                    labels.push(AxisLabel {
+                        text: self.titles[axis].clone(),
+                        axis,
+                        is_title: true,
+                        x,
+                        y,
+                    });
+                }
+            }
+        }
+        labels
+    }
+
+    fn layout(&self, state: &AppState, model: &glm::Mat4) -> Layout {
+        let bounds = self.bounds.get();
+        let [x_min, x_max, z_min, z_max] = bounds.extent;
+        let (y_min, y_max) = bounds.height_range;
+        let ranges = [(x_min, x_max), (y_min, y_max), (z_min, z_max)];
+        let (min, max) = bounds.model_box();
+
+        let camera = state.get_camera_position();
+        let eye = model.try_inverse().unwrap_or_else(glm::Mat4::identity) * glm::vec4(camera.x, camera.y, camera.z, 1.0);
+
+        let mut back = min;
+        let mut front = max;
+        let mut ticks: [Vec<(f32, f32)>; 3] = Default::default();
+        let mut decimals = [0; 3];
+        for axis in 0..3 {
+            if eye[axis] < (min[axis] + max[axis]) * 0.5 {
+                back[axis] = max[axis];
+                front[axis] = min[axis];
+            }
+
+            let (low, high) = ranges[axis];
+            let (values, places) = nice_ticks(low, high, TARGET_TICKS);
+            ticks[axis] = values
+                .into_iter()
+                .map(|value| {
+                    let fraction = if high != low { (value - low) / (high - low) } else { 0.5 };
+                    (value, min[axis] + (max[axis] - min[axis]) * fraction)
+                })
+                .collect();
+            decimals[axis] = places;
+        }
+
+        Layout {
+            min,
+            max,
+            back,
+            front,
+            ticks,
+            decimals,
+        }
+    }
+
+    // Line list positions and colours for the wall grids, box and tick marks
+    fn build_lines(&self, layout: &Layout) -> (Vec<f32>, Vec<f32>) {
+        let mut positions = Vec::new();
+        let mut colours = Vec::new();
+        let mut line = |a: glm::Vec3, b: glm::Vec3, colour: &[f32; 4]| {
+            positions.extend_from_slice(&[a.x, a.y, a.z, b.x, b.y, b.z]);
+            colours.extend_from_slice(colour);
+            colours.extend_from_slice(colour);
+        };
+
+        // Each wall is perpendicular to `axis` and gets lines at the ticks of
+        // the other two
+        for axis in 0..3 {
+            for &(u, v) in [((axis + 1) % 3, (axis + 2) % 3), ((axis + 2) % 3, (axis + 1) % 3)].iter() {
+                for &(_, along) in layout.ticks[u].iter() {
+                    let mut a = layout.min;
+                    a[axis] = layout.back[axis];
+                    a[u] = along;
+                    let mut b = a;
+                    b[v] = layout.max[v];
+                    line(a, b, &self.grid_colour);
+                }
+            }
+        }
+
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for corner in 0..4 {
+                let mut a = layout.min;
+                if corner & 1 != 0 {
+                    a[u] = layout.max[u];
+                }
+                if corner & 2 != 0 {
+                    a[v] = layout.max[v];
+                }
+                let mut b = a;
+                b[axis] = layout.max[axis];
+                line(a, b, &self.box_colour);
+            }
+        }
+
+        for axis in 0..3 {
+            let (edge, outward) = label_edge(layout, axis);
+            for &(_, along) in layout.ticks[axis].iter() {
+                let mut a = edge;
+                a[axis] = along;
+                line(a, a + outward * TICK_LENGTH, &self.box_colour);
+            }
+        }
+
+        (positions, colours)
+    }
+}
+
+// The box edge an axis's ticks are labelled along, as a point on it, and the
+// direction pointing away from the box. Labels go on front edges so the
+// surface doesn't hide them.
+fn label_edge(layout: &Layout, axis: usize) -> (glm::Vec3, glm::Vec3) {
+    let away_x = glm::vec3((layout.front.x - layout.back.x).signum(), 0.0, 0.0);
+    let away_z = glm::vec3(0.0, 0.0, (layout.front.z - layout.back.z).signum());
+    match axis {
+        0 => (glm::vec3(0.0, layout.back.y, layout.front.z), away_z),
+        1 => (glm::vec3(layout.front.x, 0.0, layout.back.z), away_x),
+        _ => (glm::vec3(layout.front.x, layout.back.y, 0.0), away_x),
+    }
+}
+
+fn project(state: &AppState, model_view_projection: &glm::Mat4, point: &glm::Vec3) -> Option<(f32, f32)> {
+    let clip = model_view_projection * glm::vec4(point.x, point.y, point.z, 1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let x = (clip.x / clip.w + 1.0) * 0.5 * state.canvas_width;
+    let y = (1.0 - clip.y / clip.w) * 0.5 * state.canvas_height;
+    Some((x, y))
+}
+
+// Ticks at 1, 2 or 5 times a power of ten, aiming for about `target` of them
+// between `min` and `max`. Also returns how many decimal places the step needs.
+pub fn nice_ticks(min: f32, max: f32, target: usize) -> (Vec<f32>, usize) {
+    let (low, high) = if min <= max { (min as f64, max as f64) } else { (max as f64, min as f64) };
+    let span = high - low;
+    if !span.is_finite() || span <= 0.0 {
+        return (vec![low as f32], 0);
+    }
+
+    let rough_step = span / (target.max(2) - 1) as f64;
+    let magnitude = 10f64.powf(rough_step.log10().floor());
+    // The ends come from f32s, so the span and the ends are only trusted to
+    // f32 rounding when picking the step and the ticks on it
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&s| s >= rough_step * (1.0 - 1e-6))
+        .unwrap_or(10.0 * magnitude);
+
+    let first = (low / step - 1e-6).ceil() as i64;
+    let last = (high / step + 1e-6).floor() as i64;
+    let ticks = (first..=last).map(|i| (i as f64 * step) as f32).collect();
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    (ticks, decimals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_ticks(min: f32, max: f32, target: usize, expected: &[f32], expected_decimals: usize) {
+        let (ticks, decimals) = nice_ticks(min, max, target);
+        assert_eq!(ticks.len(), expected.len(), "{:?} vs {:?}", ticks, expected);
+        for (tick, value) in ticks.iter().zip(expected.iter()) {
+            assert!((tick - value).abs() < 1e-6, "{:?} vs {:?}", ticks, expected);
+        }
+        assert_eq!(decimals, expected_decimals);
+    }
+
+    #[test]
+    fn steps_are_one_two_or_five_times_a_power_of_ten() {
+        assert_ticks(0.0, 1.0, 5, &[0.0, 0.5, 1.0], 1);
+        assert_ticks(0.0, 100.0, 5, &[0.0, 50.0, 100.0], 0);
+        assert_ticks(0.0, 8.0, 5, &[0.0, 2.0, 4.0, 6.0, 8.0], 0);
+        assert_ticks(0.0, 0.01, 5, &[0.0, 0.005, 0.01], 3);
+        assert_ticks(-40.0, 160.0, 5, &[0.0, 50.0, 100.0, 150.0], 0);
+    }
+
+    #[test]
+    fn ticks_stay_inside_the_range() {
+        assert_ticks(-1.3, 2.7, 5, &[-1.0, 0.0, 1.0, 2.0], 0);
+        assert_ticks(0.15, 0.95, 5, &[0.2, 0.4, 0.6, 0.8], 1);
+        // Ends that are multiples of the step up to rounding still count
+        assert_ticks(0.1, 0.3, 3, &[0.1, 0.2, 0.3], 1);
+    }
+
+    #[test]
+    fn reversed_and_empty_spans() {
+        assert_ticks(1.0, 0.0, 5, &[0.0, 0.5, 1.0], 1);
+        assert_ticks(3.0, 3.0, 5, &[3.0], 0);
+        assert_ticks(0.0, f32::INFINITY, 5, &[0.0], 0);
+        // Fewer than two ticks can't be asked for
+        assert_ticks(0.0, 10.0, 0, &[0.0, 10.0], 0);
+    }
+}
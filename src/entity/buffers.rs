@@ -0,0 +1,117 @@
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// The views below alias wasm memory directly, so nothing may allocate between
+// creating a view and handing it to GL. Each is a single typed array made
+// straight over the slice, rather than a view of all of memory cut down to it,
+// as uploads happen every frame.
+
+pub fn create_f32_buffer(gl: &WebGlRenderingContext, target: u32, data: &[f32], usage: u32) -> Result<WebGlBuffer, String> {
+    let buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+    gl.bind_buffer(target, Some(&buffer));
+    upload_f32_buffer(gl, target, data, usage);
+    Ok(buffer)
+}
+
+pub fn upload_f32_buffer(gl: &WebGlRenderingContext, target: u32, data: &[f32], usage: u32) {
+    let array = unsafe { js_sys::Float32Array::view(data) };
+    gl.buffer_data_with_array_buffer_view(target, &array, usage);
+}
+
+// Overwrites part of the buffer bound to `target`, starting `offset` floats in
+pub fn upload_f32_sub_buffer(gl: &WebGlRenderingContext, target: u32, offset: usize, data: &[f32]) {
+    let array = unsafe { js_sys::Float32Array::view(data) };
+    gl.buffer_sub_data_with_i32_and_array_buffer_view(target, (offset * 4) as i32, &array);
+}
+
+pub fn create_u16_buffer(gl: &WebGlRenderingContext, target: u32, data: &[u16], usage: u32) -> Result<WebGlBuffer, String> {
+    let buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+    gl.bind_buffer(target, Some(&buffer));
+    let array = unsafe { js_sys::Uint16Array::view(data) };
+    gl.buffer_data_with_array_buffer_view(target, &array, usage);
+    Ok(buffer)
+}
+
+pub fn create_u32_buffer(gl: &WebGlRenderingContext, target: u32, data: &[u32], usage: u32) -> Result<WebGlBuffer, String> {
+    let buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+    gl.bind_buffer(target, Some(&buffer));
+    let array = unsafe { js_sys::Uint32Array::view(data) };
+    gl.buffer_data_with_array_buffer_view(target, &array, usage);
+    Ok(buffer)
+}
+
+// Picks the smallest index type the data fits in. 32-bit indices need the
+// OES_element_index_uint extension under WebGL 1.
+pub fn create_index_buffer(gl: &WebGlRenderingContext, indices: &[u32]) -> Result<(WebGlBuffer, u32), String> {
+    if indices.iter().all(|&i| i <= u16::MAX as u32) {
+        let indices: Vec<u16> = indices.iter().map(|&i| i as u16).collect();
+        Ok((create_u16_buffer(gl, GL::ELEMENT_ARRAY_BUFFER, &indices, GL::STATIC_DRAW)?, GL::UNSIGNED_SHORT))
+    } else {
+        gl.get_extension("OES_element_index_uint")
+            .ok()
+            .flatten()
+            .ok_or("Mesh needs 32-bit indices but OES_element_index_uint is not available")?;
+        Ok((create_u32_buffer(gl, GL::ELEMENT_ARRAY_BUFFER, indices, GL::STATIC_DRAW)?, GL::UNSIGNED_INT))
+    }
+}
+
+pub fn create_rgba_texture(gl: &WebGlRenderingContext, width: u32, height: u32, rgba: &[u8]) -> Result<WebGlTexture, String> {
+    let texture = gl.create_texture().ok_or("Failed to create texture")?;
+    gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        GL::TEXTURE_2D,
+        0,
+        GL::RGBA as i32,
+        width as i32,
+        height as i32,
+        0,
+        GL::RGBA,
+        GL::UNSIGNED_BYTE,
+        Some(rgba),
+    )
+    .map_err(|_| String::from("Failed to upload texture"))?;
+
+    // WebGL 1 can only mipmap and repeat power-of-two textures
+    if width.is_power_of_two() && height.is_power_of_two() {
+        gl.generate_mipmap(GL::TEXTURE_2D);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR_MIPMAP_LINEAR as i32);
+    } else {
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+    }
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+
+    Ok(texture)
+}
+
+// A one pixel high texture for looking values up along its width, such as a
+// colourmap. Filtering is linear and lookups outside [0, 1] clamp to the ends.
+pub fn create_lookup_texture(gl: &WebGlRenderingContext, rgba: &[u8]) -> Result<WebGlTexture, String> {
+    let texture = gl.create_texture().ok_or("Failed to create texture")?;
+    upload_lookup_texture(gl, &texture, rgba)?;
+
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+
+    Ok(texture)
+}
+
+// Replaces a lookup texture's contents, leaving it bound
+pub fn upload_lookup_texture(gl: &WebGlRenderingContext, texture: &WebGlTexture, rgba: &[u8]) -> Result<(), String> {
+    gl.bind_texture(GL::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        GL::TEXTURE_2D,
+        0,
+        GL::RGBA as i32,
+        (rgba.len() / 4) as i32,
+        1,
+        0,
+        GL::RGBA,
+        GL::UNSIGNED_BYTE,
+        Some(rgba),
+    )
+    .map_err(|_| String::from("Failed to upload texture"))
+}
@@ -0,0 +1,320 @@
+use super::axes::nice_ticks;
+use super::buffers::*;
+use super::entity::Entity;
+use super::graph3d::SurfaceBounds;
+use super::text::{Text, TextLabel, TextSpace};
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use crate::text_layout::{TextAlign, TextLayoutOptions, VerticalAlign};
+use nalgebra_glm as glm;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// Roughly how many ticks the bar aims for
+const TARGET_TICKS: usize = 5;
+// In pixels
+const TICK_LENGTH: f32 = 5.0;
+const LABEL_GAP: f32 = 4.0;
+const TITLE_GAP: f32 = 8.0;
+// Ticks closer than this to either end are left to the min and max labels
+const END_CLEARANCE: f32 = 12.0;
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Corner {
+    TopLeft = 0,
+    TopRight = 1,
+    BottomLeft = 2,
+    BottomRight = 3,
+}
+
+// A tick value, end value or title and where it goes on the canvas, in pixels
+// from the top left, aligned the way text placed there should be
+pub struct ColourbarLabel {
+    pub text: String,
+    pub is_title: bool,
+    pub x: f32,
+    pub y: f32,
+    pub align: TextAlign,
+    pub vertical_align: VerticalAlign,
+}
+
+// A vertical legend for a Graph3d's colourmap, drawn over the scene in a
+// corner of the canvas. It reads the graph's colour range and colourmap
+// texture as they are when drawn, so it always matches the surface. Its
+// labels and title are drawn once it's given a font with `set_font`; `labels`
+// also gives their positions for pages that place their own text.
+pub struct Colourbar {
+    shader_type: ShaderType,
+    vertex_array: VertexArray,
+    positions_array_buffer: WebGlBuffer,
+    values_array_buffer: WebGlBuffer,
+    bounds: Rc<Cell<SurfaceBounds>>,
+    colourmap_texture: WebGlTexture,
+    corner: Corner,
+    // Pixels between the bar and the canvas edges at its corner. The title
+    // sits above the bar, so top corners need room for it.
+    margin: f32,
+    // Width and height of the gradient in pixels
+    size: [f32; 2],
+    title: String,
+    frame_colour: [f32; 4],
+    // Kept up to date with `labels` as the bar is drawn
+    text: Option<RefCell<Text>>,
+    text_size: f32,
+    text_colour: [f32; 4],
+}
+
+// The bar's edges in pixels from the canvas's top left, and its ticks
+struct Layout {
+    left: f32,
+    top: f32,
+    right: f32,
+    bottom: f32,
+    // Ticks and labels face into the canvas, so bars in the right corners
+    // have them on their left
+    labels_left: bool,
+    // (label, y)
+    ticks: Vec<(String, f32)>,
+}
+
+impl Entity for Colourbar {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+            let layout = self.layout(&current_state);
+            let (positions, values) = build_geometry(&layout);
+
+            shader_controller.use_shader(gl, self.shader_type);
+            self.vertex_array.bind(gl);
+
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.positions_array_buffer));
+            upload_f32_buffer(gl, GL::ARRAY_BUFFER, &positions, GL::DYNAMIC_DRAW);
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.values_array_buffer));
+            upload_f32_buffer(gl, GL::ARRAY_BUFFER, &values, GL::DYNAMIC_DRAW);
+
+            let [r, g, b, a] = self.frame_colour;
+            gl.uniform2f(
+                shader.get_uniform_location(gl, "uScreenSize").as_ref(),
+                current_state.canvas_width,
+                current_state.canvas_height,
+            );
+            gl.uniform4f(shader.get_uniform_location(gl, "uFrameColour").as_ref(), r, g, b, a);
+            gl.uniform1f(shader.get_uniform_location(gl, "uColourBands").as_ref(), self.bounds.get().colour_bands as f32);
+            gl.active_texture(GL::TEXTURE0);
+            gl.bind_texture(GL::TEXTURE_2D, Some(&self.colourmap_texture));
+            gl.uniform1i(shader.get_uniform_location(gl, "uColourmap").as_ref(), 0);
+
+            // Drawn over the scene: the gradient first, then its frame and ticks
+            gl.disable(GL::DEPTH_TEST);
+            gl.draw_arrays(GL::TRIANGLES, 0, 6);
+            gl.draw_arrays(GL::LINES, 6, (values.len() - 6) as i32);
+            gl.enable(GL::DEPTH_TEST);
+            self.vertex_array.unbind();
+
+            if let Some(text) = &self.text {
+                let labels = self
+                    .layout_labels(layout)
+                    .into_iter()
+                    .map(|label| TextLabel {
+                        text: label.text,
+                        space: TextSpace::Screen,
+                        position: [label.x, label.y, 0.0],
+                        colour: self.text_colour,
+                        options: TextLayoutOptions {
+                            size: self.text_size,
+                            align: label.align,
+                            vertical_align: label.vertical_align,
+                            ..Default::default()
+                        },
+                    })
+                    .collect();
+                text.borrow_mut().set_labels(gl, labels);
+                text.borrow().render(gl, shader_controller, position, rotation, scale);
+            }
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Colourbar {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, bounds: Rc<Cell<SurfaceBounds>>, colourmap_texture: WebGlTexture) -> Result<Self, String> {
+        let positions_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let values_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, positions_array_buffer.clone(), 2),
+                VertexAttribute::buffer(1, values_array_buffer.clone(), 1),
+            ],
+            None,
+        );
+
+        Ok(Self {
+            shader_type,
+            vertex_array,
+            positions_array_buffer,
+            values_array_buffer,
+            bounds,
+            colourmap_texture,
+            corner: Corner::TopRight,
+            margin: 40.0,
+            size: [20.0, 200.0],
+            title: String::new(),
+            frame_colour: [0.8, 0.8, 0.8, 1.0],
+            text: None,
+            text_size: 14.0,
+            text_colour: [0.8, 0.8, 0.8, 1.0],
+        })
+    }
+
+    pub fn set_corner(&mut self, corner: Corner) {
+        self.corner = corner;
+    }
+
+    pub fn set_margin(&mut self, margin: f32) -> Result<(), String> {
+        if !(margin.is_finite() && margin >= 0.0) {
+            return Err(String::from("Colourbar margin must be finite and not negative"));
+        }
+        self.margin = margin;
+        Ok(())
+    }
+
+    pub fn set_size(&mut self, width: f32, height: f32) -> Result<(), String> {
+        if !(width.is_finite() && height.is_finite() && width > 0.0 && height > 0.0) {
+            return Err(String::from("Colourbar width and height must be positive"));
+        }
+        self.size = [width, height];
+        Ok(())
+    }
+
+    // An empty title is left out of `labels`
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
+    pub fn set_frame_colour(&mut self, frame_colour: [f32; 4]) {
+        self.frame_colour = frame_colour;
+    }
+
+    // Draws the labels and title with `text`, an empty text entity sharing a
+    // loaded font, at `size` pixels
+    pub fn set_font(&mut self, text: Text, size: f32, colour: [f32; 4]) -> Result<(), String> {
+        if !(size.is_finite() && size > 0.0) {
+            return Err(String::from("Colourbar text size must be positive"));
+        }
+        self.text = Some(RefCell::new(text));
+        self.text_size = size;
+        self.text_colour = colour;
+        Ok(())
+    }
+
+    // Where the min, max and tick labels and the title go for the current
+    // canvas size and colour range
+    pub fn labels(&self) -> Vec<ColourbarLabel> {
+        self.layout_labels(self.layout(&get_current_app_state()))
+    }
+
+    fn layout_labels(&self, layout: Layout) -> Vec<ColourbarLabel> {
+        let (x, align) = if layout.labels_left {
+            (layout.left - TICK_LENGTH - LABEL_GAP, TextAlign::Right)
+        } else {
+            (layout.right + TICK_LENGTH + LABEL_GAP, TextAlign::Left)
+        };
+
+        let mut labels: Vec<ColourbarLabel> = layout
+            .ticks
+            .into_iter()
+            .map(|(text, y)| ColourbarLabel {
+                text,
+                is_title: false,
+                x,
+                y,
+                align,
+                vertical_align: VerticalAlign::Middle,
+            })
+            .collect();
+        if !self.title.is_empty() {
+            labels.push(ColourbarLabel {
+                text: self.title.clone(),
+                is_title: true,
+                x: (layout.left + layout.right) * 0.5,
+                y: layout.top - TITLE_GAP,
+                align: TextAlign::Centre,
+                vertical_align: VerticalAlign::Bottom,
+            });
+        }
+        labels
+    }
+
+    fn layout(&self, state: &AppState) -> Layout {
+        let [width, height] = self.size;
+        let labels_left = self.corner == Corner::TopRight || self.corner == Corner::BottomRight;
+        let left = if labels_left { state.canvas_width - self.margin - width } else { self.margin };
+        let top = if self.corner == Corner::TopLeft || self.corner == Corner::TopRight {
+            self.margin
+        } else {
+            state.canvas_height - self.margin - height
+        };
+        let bottom = top + height;
+
+        // The colour range's min is at the bottom, even when it's the larger
+        let (min, max) = self.bounds.get().colour_range;
+        let y = |value: f32| {
+            if max != min {
+                bottom - (value - min) / (max - min) * height
+            } else {
+                top + height * 0.5
+            }
+        };
+        let (values, decimals) = nice_ticks(min, max, TARGET_TICKS);
+        let label = |value: f32| format!("{:.*}", decimals, value);
+
+        let mut ticks = vec![(label(min), y(min))];
+        if max != min {
+            ticks.extend(
+                values
+                    .into_iter()
+                    .map(|value| (label(value), y(value)))
+                    .filter(|&(_, tick_y)| tick_y - top >= END_CLEARANCE && bottom - tick_y >= END_CLEARANCE),
+            );
+            ticks.push((label(max), y(max)));
+        }
+
+        Layout {
+            left,
+            top,
+            right: left + width,
+            bottom,
+            labels_left,
+            ticks,
+        }
+    }
+}
+
+// Pixel positions and colourmap values for the gradient's two triangles, then
+// the frame and ticks in pairs as lines. Lines get a value of -1 so that they
+// take the frame colour.
+fn build_geometry(layout: &Layout) -> (Vec<f32>, Vec<f32>) {
+    let Layout { left, top, right, bottom, .. } = *layout;
+    let mut positions = vec![left, bottom, right, bottom, right, top, left, bottom, right, top, left, top];
+    let mut values = vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0];
+
+    positions.extend_from_slice(&[left, top, right, top, right, top, right, bottom, right, bottom, left, bottom, left, bottom, left, top]);
+    let (edge, outward) = if layout.labels_left { (left, -TICK_LENGTH) } else { (right, TICK_LENGTH) };
+    for &(_, y) in layout.ticks.iter() {
+        positions.extend_from_slice(&[edge, y, edge + outward, y]);
+    }
+    values.resize(positions.len() / 2, -1.0);
+    (positions, values)
+}
@@ -0,0 +1,145 @@
+use super::buffers::*;
+use super::graph3d::SurfaceBounds;
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::contour::{contour_segments, even_levels};
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::cell::RefCell;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+pub enum ContourLevels {
+    // Heights chosen by the caller
+    Explicit(Vec<f32>),
+    // This many levels spread evenly over the surface's current height range
+    Count(usize),
+}
+
+// Iso-lines drawn over a Graph3d surface, and optionally again on the floor of
+// its box. They're re-extracted only when the heights or bounds they were
+// built from change, which for height functions is every frame.
+pub struct ContourOverlay {
+    shader_type: ShaderType,
+    vertex_array: VertexArray,
+    positions_array_buffer: WebGlBuffer,
+    colours_array_buffer: WebGlBuffer,
+    levels: ContourLevels,
+    colour: [f32; 4],
+    project_to_floor: bool,
+    cache: RefCell<Option<LineCache>>,
+}
+
+// What the uploaded lines were built from, and how many vertices they have
+struct LineCache {
+    heights: Vec<f32>,
+    width: usize,
+    depth: usize,
+    bounds: SurfaceBounds,
+    vertex_count: usize,
+}
+
+impl LineCache {
+    // Heights are compared bit for bit, so that NaN holes match themselves
+    fn matches(&self, heights: &[f32], width: usize, depth: usize, bounds: &SurfaceBounds) -> bool {
+        self.width == width
+            && self.depth == depth
+            && self.bounds == *bounds
+            && self.heights.len() == heights.len()
+            && self.heights.iter().zip(heights.iter()).all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+}
+
+impl ContourOverlay {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, levels: ContourLevels, colour: [f32; 4], project_to_floor: bool) -> Result<Self, String> {
+        let positions_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let colours_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, positions_array_buffer.clone(), 3),
+                VertexAttribute::buffer(1, colours_array_buffer.clone(), 4),
+            ],
+            None,
+        );
+
+        Ok(Self {
+            shader_type,
+            vertex_array,
+            positions_array_buffer,
+            colours_array_buffer,
+            levels,
+            colour,
+            project_to_floor,
+            cache: RefCell::new(None),
+        })
+    }
+
+    // `heights` is the surface's grid and `model` its model matrix, so the
+    // lines pass through the same display mapping as the surface
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, model: &glm::Mat4, heights: &[f32], width: usize, depth: usize, bounds: &SurfaceBounds) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let mut cache = self.cache.borrow_mut();
+            let rebuild = !cache.as_ref().is_some_and(|cache| cache.matches(heights, width, depth, bounds));
+            if rebuild {
+                let positions = self.build_lines(heights, width, depth, bounds);
+                let colours: Vec<f32> = self.colour.iter().cloned().cycle().take(4 * positions.len() / 3).collect();
+                gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.positions_array_buffer));
+                upload_f32_buffer(gl, GL::ARRAY_BUFFER, &positions, GL::DYNAMIC_DRAW);
+                gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.colours_array_buffer));
+                upload_f32_buffer(gl, GL::ARRAY_BUFFER, &colours, GL::DYNAMIC_DRAW);
+
+                *cache = Some(LineCache {
+                    heights: heights.to_vec(),
+                    width,
+                    depth,
+                    bounds: *bounds,
+                    vertex_count: positions.len() / 3,
+                });
+            }
+            let vertex_count = cache.as_ref().map_or(0, |cache| cache.vertex_count);
+            if vertex_count == 0 {
+                return;
+            }
+
+            shader_controller.use_shader(gl, self.shader_type);
+            self.vertex_array.bind(gl);
+
+            let projection_matrix = get_current_app_state().get_projection_matrix();
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            gl.draw_arrays(GL::LINES, 0, vertex_count as i32);
+            self.vertex_array.unbind();
+        }
+    }
+
+    // Model space line vertices, in pairs
+    fn build_lines(&self, heights: &[f32], width: usize, depth: usize, bounds: &SurfaceBounds) -> Vec<f32> {
+        let levels = match &self.levels {
+            ContourLevels::Explicit(levels) => levels.clone(),
+            ContourLevels::Count(count) => even_levels(bounds.height_range.0, bounds.height_range.1, *count),
+        };
+        let [x_scale, y_scale, z_scale] = bounds.display_scale;
+        let floor = bounds.model_box().0.y;
+        let x_step = 2.0 / (width - 1) as f32;
+        let z_step = 2.0 / (depth - 1) as f32;
+
+        let mut positions = Vec::new();
+        for level in levels {
+            let y = (level - bounds.height_origin) * y_scale;
+            for segment in contour_segments(heights, width, depth, level) {
+                for &[column, row] in segment.iter() {
+                    positions.extend_from_slice(&[(-1.0 + column * x_step) * x_scale, y, (-1.0 + row * z_step) * z_scale]);
+                }
+                if self.project_to_floor {
+                    for &[column, row] in segment.iter() {
+                        positions.extend_from_slice(&[(-1.0 + column * x_step) * x_scale, floor, (-1.0 + row * z_step) * z_scale]);
+                    }
+                }
+            }
+        }
+        positions
+    }
+}
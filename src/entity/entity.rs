@@ -1,8 +1,28 @@
+use crate::app_state::*;
 use crate::shader::shader_controller::ShaderController;
 use nalgebra_glm as glm;
+use std::any::Any;
 use web_sys::*;
 
 pub trait Entity {
     fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3);
     fn update(&self, _time: f32);
+
+    // Lets the client downcast an entity it handed out an index for
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+// Model matrix for entities that follow the mouse-driven scene rotation. Every
+// entity in the scene uses it, so that things placed on a surface stay there.
+pub fn get_model_matrix(state: &AppState, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) -> glm::Mat4 {
+    let translate = glm::translate(&glm::Mat4::identity(), position);
+    let scale = glm::scale(&glm::Mat4::identity(), scale);
+    translate * get_rotation_matrix(state, rotation) * scale
+}
+
+// The rotation part of `get_model_matrix`, for turning normals
+pub fn get_rotation_matrix(state: &AppState, rotation: &glm::Vec3) -> glm::Mat4 {
+    let rotate_x = glm::rotate_x(&glm::Mat4::identity(), rotation.y + state.rotation_y);
+    let rotate_y = glm::rotate_y(&glm::Mat4::identity(), rotation.x + state.rotation_x);
+    rotate_x * rotate_y
 }
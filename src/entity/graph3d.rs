@@ -1,10 +1,20 @@
-use super::entity::Entity;
+use super::buffers::*;
+use super::contours::ContourOverlay;
+use super::entity::*;
+use super::vertex_array::{VertexArray, VertexAttribute};
 use crate::app_state::*;
-use crate::shader::shader_controller::{ShaderController, ShaderType};
-use js_sys::WebAssembly;
+use crate::colourmap::Colourmap;
+use crate::expression::compiled::MAX_GPU_PARAMETERS;
+use crate::height_function::{HeightFunction, Ripple};
+use crate::picking::{intersect_height_field, Ray};
+use crate::shader::shader::Shader;
+use crate::shader::shader_controller::{create_graph3d_gpu_shader, ShaderController, ShaderType};
 use nalgebra_glm as glm;
+use std::any::Any;
+use std::cell::{Cell, Ref, RefCell};
+use std::ops::Range;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
 use web_sys::WebGlRenderingContext as GL;
 use web_sys::*;
 
@@ -14,181 +24,961 @@ extern "C" {
     fn log(s: &str);
 }
 
+// Samples along each side of the grid used to find a GPU-evaluated function's
+// height range
+const RANGE_SAMPLES: usize = 17;
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum RenderMode {
+    Solid = 0,
+    Wireframe = 1,
+    SolidWithGridLines = 2,
+}
+
+// What the surface currently covers, shared with anything drawn around it
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct SurfaceBounds {
+    // x_min, x_max, z_min, z_max of the domain
+    pub extent: [f32; 4],
+    pub height_range: (f32, f32),
+    // How data units map into the model space the surface is drawn in: x and z
+    // are centred and stretched by the first and last values, and heights are
+    // offset by `height_origin` then stretched by the middle one
+    pub display_scale: [f32; 3],
+    pub height_origin: f32,
+    // Values at either end of the colourmap, and its number of bands
+    pub colour_range: (f32, f32),
+    pub colour_bands: u32,
+}
+
+impl SurfaceBounds {
+    // Corners of the box the surface fills, in model space
+    pub fn model_box(&self) -> (glm::Vec3, glm::Vec3) {
+        let [x_scale, y_scale, z_scale] = self.display_scale;
+        let (low, high) = self.height_range;
+        let (y_min, y_max) = ((low - self.height_origin) * y_scale, (high - self.height_origin) * y_scale);
+        (glm::vec3(-x_scale, y_min.min(y_max), -z_scale), glm::vec3(x_scale, y_min.max(y_max), z_scale))
+    }
+}
+
 pub struct Graph3d {
     shader_type: ShaderType,
-    rect_vertex_array_buffer: WebGlBuffer,
-    rect_index_array_length: usize,
+    render_mode: RenderMode,
+    // None puts a line on every mesh edge
+    grid_lines: Option<f32>,
+    grid_line_colour: [f32; 4],
+    grid: GridMesh,
     y_values_array_buffer: WebGlBuffer,
     normals_array_buffer: WebGlBuffer,
-    n: usize,
+    scalars_array_buffer: WebGlBuffer,
+    source: HeightSource,
+    // Range of the data grid's heights, kept up to date as it changes
+    data_range: (f32, f32),
+    // Range of the per-sample scalars when colouring by them instead of height
+    scalar_range: Option<(f32, f32)>,
+    colourmap_texture: WebGlTexture,
+    // Values mapped to either end of the colourmap. None fits the current data.
+    colour_range: Option<(f32, f32)>,
+    colour_bands: u32,
+    // Number of samples along x and z
+    width: usize,
+    depth: usize,
+    // x_min, x_max, z_min, z_max of the domain the grid covers
+    extent: [f32; 4],
+    vertical_exaggeration: f32,
+    bounds: Rc<Cell<SurfaceBounds>>,
+    contours: Option<ContourOverlay>,
+    // Whether height functions should be evaluated in the vertex shader
+    gpu_evaluation: bool,
+    // The current function compiled into the graph's own shader, or None to
+    // evaluate on the CPU
+    gpu_shader: Option<Shader>,
+    frame: RefCell<FunctionFrame>,
+    pick_callback: Option<js_sys::Function>,
+    // AppState's pointer move and click counts when they were last reported
+    pointer_events_seen: Cell<(u32, u32)>,
+}
+
+// The latest heights and normals of a height function evaluated on the CPU.
+// They're kept between frames and overwritten in place, so drawing doesn't
+// allocate, and left alone while the time stays the same.
+struct FunctionFrame {
+    // When the heights were evaluated, or None once they're out of date
+    time: Option<f32>,
+    // Whether the GL buffers have been sized for the current grid
+    allocated: bool,
+    heights: Vec<f32>,
+    normals: Vec<f32>,
+    height_range: (f32, f32),
+}
+
+impl FunctionFrame {
+    fn new(width: usize, depth: usize) -> Self {
+        Self {
+            time: None,
+            allocated: false,
+            heights: vec![0.0; width * depth],
+            normals: vec![0.0; 3 * width * depth],
+            height_range: (-0.5, 0.5),
+        }
+    }
+}
+
+enum HeightSource {
+    // Re-evaluated every frame
+    Function(Box<dyn HeightFunction>),
+    // Caller-supplied samples, row-major with `width` samples per row. They're
+    // only uploaded when they change.
+    Data(Vec<f32>),
+}
+
+// The static part of the surface: x/z positions and triangle indices, which
+// only need rebuilding when the grid size changes
+struct GridMesh {
+    vertex_array: VertexArray,
+    vertex_array_buffer: WebGlBuffer,
+    index_array_buffer: WebGlBuffer,
+    index_array_length: usize,
+    index_type: u32,
+}
+
+impl GridMesh {
+    fn new(
+        gl: &WebGlRenderingContext,
+        width: usize,
+        depth: usize,
+        y_values_array_buffer: &WebGlBuffer,
+        normals_array_buffer: &WebGlBuffer,
+        scalars_array_buffer: &WebGlBuffer,
+    ) -> Result<Self, String> {
+        let mut vertices: Vec<f32> = vec![0.0; 3 * width * depth];
+        let indices = grid_indices(width, depth, false, false);
+
+        let x_step = 2.0 / (width - 1) as f32;
+        let z_step = 2.0 / (depth - 1) as f32;
+
+        for z in 0..depth {
+            for x in 0..width {
+                let start_pos_i = 3 * (z * width + x);
+                vertices[start_pos_i] = -1.0 + (x as f32) * x_step;
+                vertices[start_pos_i + 2] = -1.0 + (z as f32) * z_step;
+            }
+        }
+
+        let vertex_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, &vertices, GL::STATIC_DRAW)?;
+        let (index_array_buffer, index_type) = create_index_buffer(gl, &indices)?;
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, vertex_array_buffer.clone(), 3),
+                VertexAttribute::buffer(1, y_values_array_buffer.clone(), 1),
+                VertexAttribute::buffer(2, normals_array_buffer.clone(), 3),
+                VertexAttribute::buffer(3, scalars_array_buffer.clone(), 1),
+            ],
+            Some(index_array_buffer.clone()),
+        );
+
+        Ok(Self {
+            vertex_array,
+            vertex_array_buffer,
+            index_array_buffer,
+            index_array_length: indices.len(),
+            index_type,
+        })
+    }
+
+    fn delete(self, gl: &WebGlRenderingContext) {
+        gl.delete_buffer(Some(&self.vertex_array_buffer));
+        gl.delete_buffer(Some(&self.index_array_buffer));
+    }
 }
 
 impl Entity for Graph3d {
     fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
-        let shader = shader_controller.get_shader(&self.shader_type);
-        match shader {
-            Some(shader) => {
-                let current_state = get_current_app_state();
-
-                shader_controller.use_shader(gl, self.shader_type);
-
-                gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.rect_vertex_array_buffer));
-                gl.vertex_attrib_pointer_with_i32(0, 3, GL::FLOAT, false, 0, 0);
-                gl.enable_vertex_attrib_array(0);
-
-                gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.y_values_array_buffer));
-                gl.vertex_attrib_pointer_with_i32(1, 1, GL::FLOAT, false, 0, 0);
-                gl.enable_vertex_attrib_array(1);
-
-                let y_vals = self.get_updated_3d_y_values(current_state.time);
-                let y_values_array_memory_buffer = wasm_bindgen::memory().dyn_into::<WebAssembly::Memory>().unwrap().buffer();
-                let y_values_location = y_vals.as_ptr() as u32 / 4;
-                let y_values_array = js_sys::Float32Array::new(&y_values_array_memory_buffer).subarray(y_values_location, y_values_location + y_vals.len() as u32);
-                gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &y_values_array, GL::DYNAMIC_DRAW);
-
-                gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.normals_array_buffer));
-                gl.vertex_attrib_pointer_with_i32(2, 3, GL::FLOAT, false, 0, 0);
-                gl.enable_vertex_attrib_array(2);
-
-                let normals = self.get_grid_normals(&y_vals);
-                let normals_array_memory_buffer = wasm_bindgen::memory().dyn_into::<WebAssembly::Memory>().unwrap().buffer();
-                let normals_location = normals.as_ptr() as u32 / 4;
-                let normals_array = js_sys::Float32Array::new(&normals_array_memory_buffer).subarray(normals_location, normals_location + normals.len() as u32);
-                gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &normals_array, GL::DYNAMIC_DRAW);
-
-                let translate = glm::translate(&glm::Mat4::identity(), position);
-                let rotate_x = glm::rotate_x(&glm::Mat4::identity(), rotation.y + current_state.rotation_y);
-                let rotate_y = glm::rotate_y(&glm::Mat4::identity(), rotation.x + current_state.rotation_x);
-                let rotate = rotate_x * rotate_y;
-                let scale = glm::scale(&rotate_y, scale);
-                let transformation_matrix = translate * rotate * scale;
-                let normals_rotation = rotate.try_inverse().unwrap();
-                let projection_matrix = current_state.get_projection_matrix();
-
-                gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(&gl, "uNormalsRotation").as_ref(), false, &normals_rotation.as_slice());
-                gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(&gl, "uModel").as_ref(), false, &transformation_matrix.as_slice());
-                gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(&gl, "uViewProjection").as_ref(), false, &projection_matrix.as_slice());
-
-                gl.draw_elements_with_i32(GL::TRIANGLES, self.rect_index_array_length as i32, GL::UNSIGNED_SHORT, 0);
+        let shader = match &self.gpu_shader {
+            Some(gpu_shader) => {
+                shader_controller.use_custom_shader(gl, gpu_shader);
+                Some(gpu_shader)
+            }
+            None => shader_controller
+                .get_shader(&self.shader_type)
+                .inspect(|_| shader_controller.use_shader(gl, self.shader_type)),
+        };
+        if let Some(shader) = shader {
+            let current_state = get_current_app_state();
+
+            self.grid.vertex_array.bind(gl);
+
+            let frame;
+            let (heights, height_range) = match &self.source {
+                // Heights never come back from the GPU, so the range is taken
+                // from a coarse sample and there are none to contour
+                HeightSource::Function(height_function) if self.gpu_shader.is_some() => {
+                    self.set_gpu_uniforms(gl, shader, height_function.as_ref(), current_state.time);
+                    (&[][..], self.sampled_height_range(height_function.as_ref(), current_state.time))
+                }
+                HeightSource::Function(height_function) => {
+                    frame = self.update_frame(gl, height_function.as_ref(), current_state.time);
+                    (&frame.heights[..], frame.height_range)
+                }
+                HeightSource::Data(data) => (&data[..], self.data_range),
+            };
+            let (colour_min, colour_max) = self.colour_range.or(self.scalar_range).unwrap_or(height_range);
+
+            let bounds = SurfaceBounds {
+                extent: self.extent,
+                height_range,
+                display_scale: self.display_scale(),
+                height_origin: self.height_origin(),
+                colour_range: (colour_min, colour_max),
+                colour_bands: self.colour_bands,
+            };
+            self.bounds.set(bounds);
+
+            let transformation_matrix = get_model_matrix(&current_state, position, rotation, scale);
+            let normals_rotation = get_rotation_matrix(&current_state, rotation).try_inverse().unwrap();
+            let projection_matrix = current_state.get_projection_matrix();
+
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uNormalsRotation").as_ref(), false, normals_rotation.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, transformation_matrix.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            let [r, g, b, a] = self.grid_line_colour;
+            gl.uniform1i(shader.get_uniform_location(gl, "uRenderMode").as_ref(), self.render_mode as i32);
+            let [x_scale, y_scale, z_scale] = bounds.display_scale;
+            gl.uniform3f(shader.get_uniform_location(gl, "uDisplayScale").as_ref(), x_scale, y_scale, z_scale);
+            gl.uniform1f(shader.get_uniform_location(gl, "uHeightOrigin").as_ref(), bounds.height_origin);
+
+            let (x_lines, z_lines) = match self.grid_lines {
+                Some(grid_lines) => (grid_lines, grid_lines),
+                None => ((self.width - 1) as f32, (self.depth - 1) as f32),
+            };
+            gl.uniform2f(shader.get_uniform_location(gl, "uGridLines").as_ref(), x_lines, z_lines);
+            gl.uniform4f(shader.get_uniform_location(gl, "uGridLineColour").as_ref(), r, g, b, a);
+
+            gl.active_texture(GL::TEXTURE0);
+            gl.bind_texture(GL::TEXTURE_2D, Some(&self.colourmap_texture));
+            gl.uniform1i(shader.get_uniform_location(gl, "uColourmap").as_ref(), 0);
+            gl.uniform1f(shader.get_uniform_location(gl, "uColourBands").as_ref(), self.colour_bands as f32);
+            gl.uniform1i(shader.get_uniform_location(gl, "uUseScalars").as_ref(), self.scalar_range.is_some() as i32);
+            gl.uniform2f(shader.get_uniform_location(gl, "uColourRange").as_ref(), colour_min, colour_max);
+
+            // Pushed back slightly so that contour lines lying on it win the depth test
+            if self.contours.is_some() {
+                gl.enable(GL::POLYGON_OFFSET_FILL);
+                gl.polygon_offset(1.0, 1.0);
+            }
+            gl.draw_elements_with_i32(GL::TRIANGLES, self.grid.index_array_length as i32, self.grid.index_type, 0);
+            gl.disable(GL::POLYGON_OFFSET_FILL);
+            self.grid.vertex_array.unbind();
+
+            if let (Some(contours), false) = (&self.contours, heights.is_empty()) {
+                contours.render(gl, shader_controller, &transformation_matrix, heights, self.width, self.depth, &bounds);
+            }
+
+            if let Some(callback) = &self.pick_callback {
+                self.report_pointer_events(callback, &current_state, &transformation_matrix);
             }
-            None => {}
         }
     }
 
     fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 impl Graph3d {
     pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, n: usize) -> Self {
-        let mut vertices: Vec<f32> = vec![0.0; 3 * (n + 1) * (n + 1)];
-        let mut indices: Vec<u16> = vec![0; 6 * n * n];
-
-        let square_size = 2.0 / n as f32;
-
-        for z in 0..(n + 1) {
-            for x in 0..(n + 1) {
-                let start_pos_i = 3 * (z * (n + 1) + x);
-                vertices[start_pos_i] = -1.0 + (x as f32) * square_size;
-                vertices[start_pos_i + 2] = -1.0 + (z as f32) * square_size;
-
-                if z < n && x < n {
-                    let start_ind_i = 6 * (z * n + x);
-
-                    let vertex_index_top_left = (z * (n + 1) + x) as u16;
-                    let vertex_index_bottom_left = vertex_index_top_left + (n + 1) as u16;
-                    let vertex_index_top_right = vertex_index_top_left + 1 as u16;
-                    let vertex_index_bottom_right = vertex_index_bottom_left + 1 as u16;
-
-                    indices[start_ind_i] = vertex_index_top_left;
-                    indices[start_ind_i + 1] = vertex_index_bottom_left;
-                    indices[start_ind_i + 2] = vertex_index_bottom_right;
-                    indices[start_ind_i + 3] = vertex_index_top_left;
-                    indices[start_ind_i + 4] = vertex_index_bottom_right;
-                    indices[start_ind_i + 5] = vertex_index_top_right;
-                }
+        let y_values_array_buffer = gl.create_buffer().ok_or("Failed to create buffer").unwrap();
+        let normals_array_buffer = gl.create_buffer().ok_or("Failed to create buffer").unwrap();
+        let scalars_array_buffer = gl.create_buffer().ok_or("Failed to create buffer").unwrap();
+        let grid = GridMesh::new(gl, n + 1, n + 1, &y_values_array_buffer, &normals_array_buffer, &scalars_array_buffer).unwrap();
+        let colourmap_texture = create_lookup_texture(gl, &Colourmap::default().to_rgba()).unwrap();
+
+        let graph = Self {
+            shader_type,
+            render_mode: RenderMode::Solid,
+            grid_lines: None,
+            grid_line_colour: [0.9, 0.9, 0.9, 1.0],
+            grid,
+            y_values_array_buffer,
+            normals_array_buffer,
+            scalars_array_buffer,
+            source: HeightSource::Function(Box::new(Ripple::default())),
+            data_range: (0.0, 0.0),
+            scalar_range: None,
+            colourmap_texture,
+            colour_range: None,
+            colour_bands: 0,
+            width: n + 1,
+            depth: n + 1,
+            extent: [-1.0, 1.0, -1.0, 1.0],
+            vertical_exaggeration: 1.0,
+            bounds: Rc::new(Cell::new(SurfaceBounds {
+                extent: [-1.0, 1.0, -1.0, 1.0],
+                height_range: (-0.5, 0.5),
+                display_scale: [1.0, 1.0, 1.0],
+                height_origin: 0.0,
+                colour_range: (-0.5, 0.5),
+                colour_bands: 0,
+            })),
+            contours: None,
+            gpu_evaluation: false,
+            gpu_shader: None,
+            frame: RefCell::new(FunctionFrame::new(n + 1, n + 1)),
+            pick_callback: None,
+            pointer_events_seen: Cell::new((0, 0)),
+        };
+        graph.clear_scalar_buffer(gl);
+        graph
+    }
+
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    // Number of grid cells the lines divide each axis into. By default there's
+    // a line on every mesh edge.
+    pub fn set_grid_lines(&mut self, grid_lines: f32) {
+        self.grid_lines = Some(grid_lines.max(1.0));
+    }
+
+    pub fn set_grid_line_colour(&mut self, grid_line_colour: [f32; 4]) {
+        self.grid_line_colour = grid_line_colour;
+    }
+
+    // With GPU evaluation on, functions that can't be written in GLSL, such as
+    // JS callbacks, are evaluated on the CPU instead
+    pub fn set_height_function(&mut self, gl: &WebGlRenderingContext, height_function: Box<dyn HeightFunction>) {
+        self.source = HeightSource::Function(height_function);
+        self.frame.get_mut().time = None;
+        let _ = self.rebuild_gpu_shader(gl);
+    }
+
+    // Evaluates heights and normals in the vertex shader, so large grids
+    // animate without per-frame work here or uploads. The function's
+    // parameters are passed as uniforms and can still be changed.
+    pub fn set_gpu_evaluation(&mut self, gl: &WebGlRenderingContext, gpu_evaluation: bool) -> Result<(), String> {
+        if gpu_evaluation {
+            if let HeightSource::Data(_) = self.source {
+                return Err(String::from("Graph3d is showing a data grid, not a height function"));
             }
         }
+        self.gpu_evaluation = gpu_evaluation;
+        self.frame.get_mut().time = None;
+        let result = self.rebuild_gpu_shader(gl);
+        if result.is_err() {
+            self.gpu_evaluation = false;
+        }
+        result
+    }
 
-        let vertex_array_memory_buffer = wasm_bindgen::memory().dyn_into::<WebAssembly::Memory>().unwrap().buffer();
-        let vertices_location = vertices.as_ptr() as u32 / 4;
-        let vert_array = js_sys::Float32Array::new(&vertex_array_memory_buffer).subarray(vertices_location, vertices_location + vertices.len() as u32);
-        let vertex_array_buffer = gl.create_buffer().ok_or("Failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&vertex_array_buffer));
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &vert_array, GL::STATIC_DRAW);
+    fn rebuild_gpu_shader(&mut self, gl: &WebGlRenderingContext) -> Result<(), String> {
+        if let Some(shader) = self.gpu_shader.take() {
+            gl.delete_program(Some(shader.get_program()));
+        }
+        if let (true, HeightSource::Function(height_function)) = (self.gpu_evaluation, &self.source) {
+            self.gpu_shader = Some(create_graph3d_gpu_shader(gl, &height_function.glsl()?)?);
+        }
+        Ok(())
+    }
 
-        let index_array_memory_buffer = wasm_bindgen::memory().dyn_into::<WebAssembly::Memory>().unwrap().buffer();
-        let indices_location = indices.as_ptr() as u32 / 2;
-        let ind_array = js_sys::Uint16Array::new(&index_array_memory_buffer).subarray(indices_location, indices_location + indices.len() as u32);
-        let index_array_buffer = gl.create_buffer().ok_or("Failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&index_array_buffer));
-        gl.buffer_data_with_array_buffer_view(GL::ELEMENT_ARRAY_BUFFER, &ind_array, GL::STATIC_DRAW);
+    // Heights are evaluated again on the next frame, as the function may change
+    pub fn height_function_mut(&mut self) -> Result<&mut dyn HeightFunction, String> {
+        self.frame.get_mut().time = None;
+        match &mut self.source {
+            HeightSource::Function(height_function) => Ok(height_function.as_mut()),
+            HeightSource::Data(_) => Err(String::from("Graph3d is showing a data grid, not a height function")),
+        }
+    }
 
-        Self {
-            shader_type: shader_type,
-            rect_vertex_array_buffer: vertex_array_buffer,
-            rect_index_array_length: indices.len(),
-            y_values_array_buffer: gl.create_buffer().ok_or("Failed to create buffer").unwrap(),
-            normals_array_buffer: gl.create_buffer().ok_or("Failed to create buffer").unwrap(),
-            n: n,
-        }
-    }
-
-    fn get_updated_3d_y_values(&self, curr_time: f32) -> Vec<f32> {
-        let point_count_per_row = self.n + 1;
-        let mut y_vals: Vec<f32> = vec![0.0; point_count_per_row * point_count_per_row];
-
-        let half_grid = point_count_per_row as f32 / 2.0;
-        let frequency_scale = 3.0 * std::f32::consts::PI;
-        let y_scale = 0.15;
-        let sin_offset = curr_time / 1000.0;
-
-        for z in 0..point_count_per_row {
-            for x in 0..point_count_per_row {
-                let use_y_index = z * point_count_per_row + x;
-                let scaled_x = frequency_scale * (x as f32 - half_grid) / half_grid;
-                let scaled_z = frequency_scale * (z as f32 - half_grid) / half_grid;
-                y_vals[use_y_index] = y_scale * ((scaled_x * scaled_x + scaled_z * scaled_z).sqrt() + sin_offset).sin();
+    // Shows `heights`, `width` samples per row and `depth` rows, spread over
+    // `extent`. The grid mesh is only rebuilt when the size changes.
+    pub fn set_data(&mut self, gl: &WebGlRenderingContext, width: usize, depth: usize, heights: &[f32], extent: [f32; 4]) -> Result<(), String> {
+        if width < 2 || depth < 2 {
+            return Err(String::from("A data grid needs at least 2x2 samples"));
+        }
+        if heights.len() != width * depth {
+            return Err(format!("A {}x{} data grid needs {} heights but was given {}", width, depth, width * depth, heights.len()));
+        }
+        validate_extent(&extent)?;
+
+        self.resize_grid(gl, width, depth)?;
+        self.extent = extent;
+
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.y_values_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, heights, GL::DYNAMIC_DRAW);
+
+        let normals = get_grid_normals(heights, width, depth, 0..depth);
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.normals_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, &normals, GL::DYNAMIC_DRAW);
+
+        self.data_range = value_range(heights);
+        self.source = HeightSource::Data(heights.to_vec());
+        self.rebuild_gpu_shader(gl)
+    }
+
+    // The x and z range, in data units, that the grid covers. Height functions
+    // are evaluated across it.
+    pub fn set_extent(&mut self, extent: [f32; 4]) -> Result<(), String> {
+        validate_extent(&extent)?;
+        self.extent = extent;
+        self.frame.get_mut().time = None;
+        Ok(())
+    }
+
+    // Samples along x and z for height functions. Data grids always use their
+    // own size.
+    pub fn set_resolution(&mut self, gl: &WebGlRenderingContext, width: usize, depth: usize) -> Result<(), String> {
+        if let HeightSource::Data(_) = self.source {
+            return Err(String::from("A data grid's resolution is set by its data"));
+        }
+        if width < 2 || depth < 2 {
+            return Err(String::from("Graph3d needs at least 2x2 samples"));
+        }
+        self.resize_grid(gl, width, depth)
+    }
+
+    // Stretches heights on top of the display mapping, without changing the
+    // values used for colouring or picking
+    pub fn set_vertical_exaggeration(&mut self, vertical_exaggeration: f32) {
+        self.vertical_exaggeration = vertical_exaggeration;
+    }
+
+    fn resize_grid(&mut self, gl: &WebGlRenderingContext, width: usize, depth: usize) -> Result<(), String> {
+        if width == self.width && depth == self.depth {
+            return Ok(());
+        }
+        let grid = GridMesh::new(gl, width, depth, &self.y_values_array_buffer, &self.normals_array_buffer, &self.scalars_array_buffer)?;
+        std::mem::replace(&mut self.grid, grid).delete(gl);
+        self.width = width;
+        self.depth = depth;
+        self.frame = RefCell::new(FunctionFrame::new(width, depth));
+        // Scalars for the old size no longer line up with the samples
+        self.scalar_range = None;
+        self.clear_scalar_buffer(gl);
+        Ok(())
+    }
+
+    // The longer side of the domain spans [-1, 1] and the other keeps its
+    // proportion. Heights use the same scale as x and z, times the vertical
+    // exaggeration.
+    fn display_scale(&self) -> [f32; 3] {
+        let [x_min, x_max, z_min, z_max] = self.extent;
+        let (x_span, z_span) = ((x_max - x_min).abs(), (z_max - z_min).abs());
+        let unit = 2.0 / x_span.max(z_span);
+        [x_span * unit / 2.0, unit * self.vertical_exaggeration, z_span * unit / 2.0]
+    }
+
+    // Data grids are centred on their height range. Height functions keep zero
+    // at the centre, as their range changes from frame to frame.
+    fn height_origin(&self) -> f32 {
+        match self.source {
+            HeightSource::Function(_) => 0.0,
+            HeightSource::Data(_) => (self.data_range.0 + self.data_range.1) * 0.5,
+        }
+    }
+
+    // Replaces whole rows of the current data grid, starting at `start_row`.
+    // Only those rows and the normals that depend on them are re-uploaded.
+    pub fn update_data_rows(&mut self, gl: &WebGlRenderingContext, start_row: usize, heights: &[f32]) -> Result<(), String> {
+        let (width, depth) = (self.width, self.depth);
+        let data = match &mut self.source {
+            HeightSource::Data(data) => data,
+            HeightSource::Function(_) => return Err(String::from("Graph3d has no data grid to update")),
+        };
+        let end_row = data_row_range(start_row, heights.len(), width, depth)?.end;
+        data[start_row * width..end_row * width].copy_from_slice(heights);
+        self.data_range = value_range(data);
+
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.y_values_array_buffer));
+        upload_f32_sub_buffer(gl, GL::ARRAY_BUFFER, start_row * width, heights);
+
+        // Normals on the rows either side also use these heights
+        let normal_rows = start_row.saturating_sub(1)..(end_row + 1).min(depth);
+        let normals = get_grid_normals(data, width, depth, normal_rows.clone());
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.normals_array_buffer));
+        upload_f32_sub_buffer(gl, GL::ARRAY_BUFFER, 3 * normal_rows.start * width, &normals);
+        Ok(())
+    }
+
+    // None removes the contour lines. They aren't drawn while heights are
+    // evaluated on the GPU.
+    pub fn set_contours(&mut self, contours: Option<ContourOverlay>) {
+        self.contours = contours;
+    }
+
+    // The data point (x, z, height) under a point on the canvas, in pixels from
+    // the top left, or None if the surface isn't there
+    pub fn pick(&self, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3, canvas_x: f32, canvas_y: f32) -> Option<[f32; 3]> {
+        let current_state = get_current_app_state();
+        let model = get_model_matrix(&current_state, position, rotation, scale);
+        self.pick_with_model(&current_state, &model, canvas_x, canvas_y)
+    }
+
+    // Called as `callback(type, point)` after the pointer moves over the canvas
+    // or clicks it, with type "hover" or "click" and point [x, z, height], or
+    // null off the surface. Hovers aren't reported while dragging.
+    pub fn set_pick_callback(&mut self, callback: Option<js_sys::Function>) {
+        let current_state = get_current_app_state();
+        self.pointer_events_seen.set((current_state.pointer_moves, current_state.clicks));
+        self.pick_callback = callback;
+    }
+
+    fn report_pointer_events(&self, callback: &js_sys::Function, state: &AppState, model: &glm::Mat4) {
+        let (moves_seen, clicks_seen) = self.pointer_events_seen.replace((state.pointer_moves, state.clicks));
+        let event = if state.clicks != clicks_seen {
+            "click"
+        } else if state.pointer_moves != moves_seen && !state.mouse_down {
+            "hover"
+        } else {
+            return;
+        };
+
+        // The mouse position is kept from the bottom of the canvas
+        let point = match self.pick_with_model(state, model, state.mouse_x, state.canvas_height - state.mouse_y) {
+            Some([x, z, height]) => js_sys::Array::of3(&x.into(), &z.into(), &height.into()).into(),
+            None => JsValue::NULL,
+        };
+        let _ = callback.call2(&JsValue::NULL, &JsValue::from_str(event), &point);
+    }
+
+    fn pick_with_model(&self, state: &AppState, model: &glm::Mat4, canvas_x: f32, canvas_y: f32) -> Option<[f32; 3]> {
+        let ndc_x = 2.0 * canvas_x / state.canvas_width - 1.0;
+        let ndc_y = 1.0 - 2.0 * canvas_y / state.canvas_height;
+        let ray = Ray::from_screen(&(state.get_projection_matrix() * model).try_inverse()?, ndc_x, ndc_y)?;
+
+        // Into grid units, undoing the display mapping the surface was drawn with
+        let bounds = self.bounds.get();
+        let [x_scale, y_scale, z_scale] = bounds.display_scale;
+        let (column_scale, row_scale) = ((self.width - 1) as f32 / 2.0, (self.depth - 1) as f32 / 2.0);
+        let to_grid = |point: glm::Vec3| {
+            glm::vec3(
+                (point.x / x_scale + 1.0) * column_scale,
+                point.y / y_scale + bounds.height_origin,
+                (point.z / z_scale + 1.0) * row_scale,
+            )
+        };
+        let origin = to_grid(ray.origin);
+        let grid_ray = Ray {
+            origin,
+            direction: to_grid(ray.origin + ray.direction) - origin,
+        };
+
+        let hit = self.with_current_heights(state.time, |heights| intersect_height_field(&grid_ray, heights, self.width, self.depth))?;
+
+        let [x_min, x_max, z_min, z_max] = self.extent;
+        Some([
+            x_min + hit.x / (self.width - 1) as f32 * (x_max - x_min),
+            z_min + hit.z / (self.depth - 1) as f32 * (z_max - z_min),
+            hit.y,
+        ])
+    }
+
+    // The surface as it's drawn, in its model space before the scene's
+    // transform: x, y, z positions and normals, and triangle indices into them.
+    // Missing heights are left as NaN.
+    pub fn export_mesh(&self) -> (Vec<f32>, Vec<f32>, Vec<u32>) {
+        let curr_time = get_current_app_state().time;
+        let (width, depth) = (self.width, self.depth);
+        let [x_scale, y_scale, z_scale] = self.display_scale();
+        let height_origin = self.height_origin();
+
+        let mut positions = Vec::with_capacity(3 * width * depth);
+        let mut normals = vec![0.0; 3 * width * depth];
+        self.with_current_heights(curr_time, |heights| {
+            for (i, height) in heights.iter().enumerate() {
+                let x = -1.0 + (i % width) as f32 * 2.0 / (width - 1) as f32;
+                let z = -1.0 + (i / width) as f32 * 2.0 / (depth - 1) as f32;
+                positions.extend_from_slice(&[x * x_scale, (height - height_origin) * y_scale, z * z_scale]);
+            }
+            let analytic = match &self.source {
+                HeightSource::Function(height_function) => self.fill_analytic_normals(height_function.as_ref(), curr_time, &mut normals),
+                HeightSource::Data(_) => false,
+            };
+            if !analytic {
+                write_grid_normals(heights, width, depth, 0..depth, &mut normals);
             }
+        });
+
+        // Normals are found on the unscaled grid, so they stretch the opposite
+        // way, as in the vertex shader
+        for normal in normals.chunks_mut(3) {
+            let unit = glm::normalize(&glm::vec3(normal[0] / x_scale, normal[1] / y_scale, normal[2] / z_scale));
+            normal.copy_from_slice(&[unit.x, unit.y, unit.z]);
         }
+        (positions, normals, grid_indices(width, depth, false, false))
+    }
 
-        y_vals
+    // Calls `f` with the heights last drawn. Functions on the GPU have none
+    // here, so they're evaluated for the purpose.
+    fn with_current_heights<R>(&self, curr_time: f32, f: impl FnOnce(&[f32]) -> R) -> R {
+        let frame = self.frame.borrow();
+        match &self.source {
+            HeightSource::Data(data) => f(data),
+            HeightSource::Function(_) if frame.time.is_some() && self.gpu_shader.is_none() => f(&frame.heights),
+            HeightSource::Function(height_function) => {
+                let mut heights = vec![0.0; self.width * self.depth];
+                self.fill_heights(height_function.as_ref(), curr_time, &mut heights);
+                f(&heights)
+            }
+        }
+    }
+
+    // Updated every time the surface is drawn
+    pub fn bounds(&self) -> Rc<Cell<SurfaceBounds>> {
+        self.bounds.clone()
+    }
+
+    pub fn set_colourmap(&mut self, gl: &WebGlRenderingContext, colourmap: &Colourmap) -> Result<(), String> {
+        upload_lookup_texture(gl, &self.colourmap_texture, &colourmap.to_rgba())
     }
 
-    fn get_grid_normals(&self, y_vals: &Vec<f32>) -> Vec<f32> {
-        let point_count_per_row = self.n + 1;
-        let graph_layout_width = 2.0;
-        let square_size: f32 = graph_layout_width / self.n as f32;
-        let mut normals: Vec<f32> = vec![0.0; 3 * point_count_per_row * point_count_per_row];
+    // Colourmaps are uploaded into this same texture, so anything holding it
+    // always shows the current one
+    pub fn colourmap_texture(&self) -> WebGlTexture {
+        self.colourmap_texture.clone()
+    }
 
-        for z in 0..point_count_per_row {
-            for x in 0..point_count_per_row {
-                let y_val_index_a = z * point_count_per_row + x;
-                let normals_start_index = 3 * y_val_index_a;
+    pub fn set_colour_range(&mut self, colour_range: Option<(f32, f32)>) {
+        self.colour_range = colour_range;
+    }
+
+    // 0 gives a continuous gradient
+    pub fn set_colour_bands(&mut self, colour_bands: u32) {
+        self.colour_bands = colour_bands;
+    }
 
-                if z == self.n || x == self.n {
-                    normals[normals_start_index + 1] = 1.0;
-                } else {
-                    let y_val_index_b = y_val_index_a + point_count_per_row;
-                    let y_val_index_c = y_val_index_a + 1;
+    // Colours the surface by one scalar per sample instead of by height
+    pub fn set_scalars(&mut self, gl: &WebGlRenderingContext, scalars: &[f32]) -> Result<(), String> {
+        if scalars.len() != self.width * self.depth {
+            return Err(format!(
+                "A {}x{} grid needs {} scalars but was given {}",
+                self.width,
+                self.depth,
+                self.width * self.depth,
+                scalars.len()
+            ));
+        }
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.scalars_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, scalars, GL::DYNAMIC_DRAW);
+        self.scalar_range = Some(value_range(scalars));
+        Ok(())
+    }
 
-                    let x_val_1 = square_size * x as f32;
-                    let x_val_2 = x_val_1 + square_size;
+    pub fn clear_scalars(&mut self) {
+        self.scalar_range = None;
+    }
 
-                    let z_val_1 = square_size * z as f32;
-                    let z_val_2 = z_val_1 + square_size;
+    // The scalar attribute is read by the shader even when colouring by
+    // height, so its buffer always has to cover the whole grid
+    fn clear_scalar_buffer(&self, gl: &WebGlRenderingContext) {
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.scalars_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, &vec![0.0; self.width * self.depth], GL::DYNAMIC_DRAW);
+    }
 
-                    let a = glm::vec3(x_val_1, y_vals[y_val_index_a], z_val_1);
-                    let b = glm::vec3(x_val_1, y_vals[y_val_index_b], z_val_2);
-                    let c = glm::vec3(x_val_2, y_vals[y_val_index_c], z_val_2);
+    // Uniforms only the GPU shader has. The time and parameters are left out
+    // of the program when the function doesn't use them.
+    fn set_gpu_uniforms(&self, gl: &WebGlRenderingContext, shader: &Shader, height_function: &dyn HeightFunction, curr_time: f32) {
+        let [x_min, x_max, z_min, z_max] = self.extent;
+        gl.uniform4f(shader.get_uniform_location(gl, "uExtent").as_ref(), x_min, x_max, z_min, z_max);
+        gl.uniform2f(
+            shader.get_uniform_location(gl, "uGridStep").as_ref(),
+            (x_max - x_min) / (self.width - 1) as f32,
+            (z_max - z_min) / (self.depth - 1) as f32,
+        );
+        gl.uniform1f(shader.find_uniform_location(gl, "uTime").as_ref(), curr_time / 1000.0);
+
+        let mut parameters = [0.0; MAX_GPU_PARAMETERS];
+        height_function.glsl_parameters(&mut parameters);
+        gl.uniform1fv_with_f32_array(shader.find_uniform_location(gl, "uParameters").as_ref(), &parameters);
+    }
 
-                    let normal = glm::triangle_normal(&a, &b, &c);
+    // Heights on a small fixed grid, so the cost doesn't grow with the
+    // resolution
+    fn sampled_height_range(&self, height_function: &dyn HeightFunction, curr_time: f32) -> (f32, f32) {
+        let [x_min, x_max, z_min, z_max] = self.extent;
+        let t = curr_time / 1000.0;
+        let last = (RANGE_SAMPLES - 1) as f32;
+        let mut heights = [0.0; RANGE_SAMPLES * RANGE_SAMPLES];
+        for (i, height) in heights.iter_mut().enumerate() {
+            let x_pos = x_min + (i % RANGE_SAMPLES) as f32 / last * (x_max - x_min);
+            let z_pos = z_min + (i / RANGE_SAMPLES) as f32 / last * (z_max - z_min);
+            *height = height_function.height(x_pos, z_pos, t);
+        }
+        value_range(&heights)
+    }
 
-                    normals[normals_start_index] = normal.x;
-                    normals[normals_start_index + 1] = normal.y;
-                    normals[normals_start_index + 2] = normal.z;
+    // Evaluates the height function into the frame and uploads it, unless it
+    // was already evaluated at this time
+    fn update_frame(&self, gl: &WebGlRenderingContext, height_function: &dyn HeightFunction, curr_time: f32) -> Ref<'_, FunctionFrame> {
+        {
+            let mut frame = self.frame.borrow_mut();
+            if frame.time != Some(curr_time) {
+                let frame = &mut *frame;
+                self.fill_heights(height_function, curr_time, &mut frame.heights);
+                if !self.fill_analytic_normals(height_function, curr_time, &mut frame.normals) {
+                    write_grid_normals(&frame.heights, self.width, self.depth, 0..self.depth, &mut frame.normals);
+                }
+                frame.height_range = value_range(&frame.heights);
+
+                // After the first upload the buffers are the right size and
+                // only their contents are replaced
+                for (buffer, values) in [(&self.y_values_array_buffer, &frame.heights), (&self.normals_array_buffer, &frame.normals)].iter() {
+                    gl.bind_buffer(GL::ARRAY_BUFFER, Some(buffer));
+                    if frame.allocated {
+                        upload_f32_sub_buffer(gl, GL::ARRAY_BUFFER, 0, values);
+                    } else {
+                        upload_f32_buffer(gl, GL::ARRAY_BUFFER, values, GL::DYNAMIC_DRAW);
+                    }
                 }
+                frame.allocated = true;
+                frame.time = Some(curr_time);
             }
         }
-        normals
+        self.frame.borrow()
+    }
+
+    // Normals from the height function's own gradient, if it has one
+    fn fill_analytic_normals(&self, height_function: &dyn HeightFunction, curr_time: f32, normals: &mut [f32]) -> bool {
+        let [x_min, x_max, z_min, z_max] = self.extent;
+        let t = curr_time / 1000.0;
+        if height_function.gradient(x_min, z_min, t).is_none() {
+            return false;
+        }
+
+        let x_step = (x_max - x_min) / (self.width - 1) as f32;
+        let z_step = (z_max - z_min) / (self.depth - 1) as f32;
+        // The mesh spans [-1, 1] whatever the extent, so the slopes are
+        // stretched by how much the domain is squashed
+        let x_scale = (x_max - x_min) / 2.0;
+        let z_scale = (z_max - z_min) / 2.0;
+
+        for (i, normal) in normals.chunks_mut(3).enumerate() {
+            let x_pos = x_min + (i % self.width) as f32 * x_step;
+            let z_pos = z_min + (i / self.width) as f32 * z_step;
+            let (dx, dz) = height_function.gradient(x_pos, z_pos, t).unwrap_or((0.0, 0.0));
+            let unit = glm::normalize(&glm::vec3(-dx * x_scale, 1.0, -dz * z_scale));
+            normal.copy_from_slice(&[unit.x, unit.y, unit.z]);
+        }
+        true
+    }
+
+    fn fill_heights(&self, height_function: &dyn HeightFunction, curr_time: f32, heights: &mut [f32]) {
+        let [x_min, x_max, z_min, z_max] = self.extent;
+        let x_step = (x_max - x_min) / (self.width - 1) as f32;
+        let z_step = (z_max - z_min) / (self.depth - 1) as f32;
+        let t = curr_time / 1000.0;
+
+        for (i, height) in heights.iter_mut().enumerate() {
+            let x_pos = x_min + (i % self.width) as f32 * x_step;
+            let z_pos = z_min + (i / self.width) as f32 * z_step;
+            *height = height_function.height(x_pos, z_pos, t);
+        }
+    }
+}
+
+// Two triangles for each cell of a row-major grid, `width` vertices per row
+// and `depth` rows. Wrapping an axis adds a row of cells joining its last
+// vertices back to its first.
+pub fn grid_indices(width: usize, depth: usize, wrap_x: bool, wrap_z: bool) -> Vec<u32> {
+    let x_cells = if wrap_x { width } else { width - 1 };
+    let z_cells = if wrap_z { depth } else { depth - 1 };
+    let mut indices: Vec<u32> = Vec::with_capacity(6 * x_cells * z_cells);
+
+    for z in 0..z_cells {
+        for x in 0..x_cells {
+            let (next_x, next_z) = ((x + 1) % width, (z + 1) % depth);
+
+            let vertex_index_top_left = (z * width + x) as u32;
+            let vertex_index_bottom_left = (next_z * width + x) as u32;
+            let vertex_index_top_right = (z * width + next_x) as u32;
+            let vertex_index_bottom_right = (next_z * width + next_x) as u32;
+
+            indices.extend_from_slice(&[
+                vertex_index_top_left,
+                vertex_index_bottom_left,
+                vertex_index_bottom_right,
+                vertex_index_top_left,
+                vertex_index_bottom_right,
+                vertex_index_top_right,
+            ]);
+        }
+    }
+    indices
+}
+
+fn validate_extent(extent: &[f32; 4]) -> Result<(), String> {
+    let [x_min, x_max, z_min, z_max] = *extent;
+    if extent.iter().any(|v| !v.is_finite()) || x_min == x_max || z_min == z_max {
+        return Err(String::from("The x and z ranges must be finite and not empty"));
+    }
+    Ok(())
+}
+
+// The smallest and largest finite values. Flat data gets a unit wide range
+// around its value so that it still maps to a colour.
+pub fn value_range(values: &[f32]) -> (f32, f32) {
+    let (min, max) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+    if min > max {
+        (-0.5, 0.5)
+    } else if min == max {
+        (min - 0.5, max + 0.5)
+    } else {
+        (min, max)
+    }
+}
+
+// The rows `count` heights starting at `start_row` cover in a grid `width`
+// wide and `depth` deep. `start_row` comes from the page, so it's checked
+// before any arithmetic could wrap; the end row is within the grid after
+// that, so multiplying rows by `width` can't overflow.
+fn data_row_range(start_row: usize, count: usize, width: usize, depth: usize) -> Result<Range<usize>, String> {
+    if !count.is_multiple_of(width) {
+        return Err(format!("Row updates must hold a multiple of {} heights", width));
+    }
+    match start_row.checked_add(count / width) {
+        Some(end_row) if end_row <= depth => Ok(start_row..end_row),
+        _ => Err(format!("{} rows from row {} are outside the {} row data grid", count / width, start_row, depth)),
+    }
+}
+
+// Normals for the rows in `rows`, packed from the first of them. Slopes come
+// from central differences, or one-sided differences along the edges and next
+// to NaN holes, with the mesh's [-1, 1] spacing.
+fn get_grid_normals(y_vals: &[f32], width: usize, depth: usize, rows: Range<usize>) -> Vec<f32> {
+    let mut normals = vec![0.0; 3 * width * rows.len()];
+    write_grid_normals(y_vals, width, depth, rows, &mut normals);
+    normals
+}
+
+// get_grid_normals into an existing slice
+fn write_grid_normals(y_vals: &[f32], width: usize, depth: usize, rows: Range<usize>, normals: &mut [f32]) {
+    let x_step = 2.0 / (width - 1) as f32;
+    let z_step = 2.0 / (depth - 1) as f32;
+    let mut normals = normals.chunks_mut(3);
+
+    for z in rows {
+        let (z_before, z_after) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+        for x in 0..width {
+            let (x_before, x_after) = (x.saturating_sub(1), (x + 1).min(width - 1));
+
+            let along_x = |column: usize| (column as f32 * x_step, y_vals[z * width + column]);
+            let along_z = |row: usize| (row as f32 * z_step, y_vals[row * width + x]);
+            let dx = finite_slope([along_x(x_before), along_x(x), along_x(x_after)]);
+            let dz = finite_slope([along_z(z_before), along_z(z), along_z(z_after)]);
+
+            let normal = glm::normalize(&glm::vec3(-dx, 1.0, -dz));
+            normals.next().unwrap().copy_from_slice(&[normal.x, normal.y, normal.z]);
+        }
+    }
+}
+
+// The slope through the middle of three (position, height) samples, leaving
+// out a neighbour that isn't finite. It's flat when there's nothing finite to
+// take a difference between.
+fn finite_slope(samples: [(f32, f32); 3]) -> f32 {
+    let [before, centre, after] = samples;
+    let first = if before.1.is_finite() { before } else { centre };
+    let last = if after.1.is_finite() { after } else { centre };
+    if first.0 != last.0 && first.1.is_finite() && last.1.is_finite() {
+        (last.1 - first.1) / (last.0 - first.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normal_at(normals: &[f32], vertex: usize) -> [f32; 3] {
+        [normals[3 * vertex], normals[3 * vertex + 1], normals[3 * vertex + 2]]
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(actual.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() < 1e-5), "{:?} vs {:?}", actual, expected);
+    }
+
+    #[test]
+    fn grid_cells_join_each_row_to_the_next() {
+        assert_eq!(grid_indices(3, 2, false, false), vec![0, 3, 4, 0, 4, 1, 1, 4, 5, 1, 5, 2]);
+        assert_eq!(grid_indices(4, 4, false, false).len(), 6 * 3 * 3);
+    }
+
+    #[test]
+    fn wrapped_grids_join_the_last_vertices_to_the_first() {
+        // The third cell of the row joins column 2 back to column 0
+        let wrap_x = grid_indices(3, 2, true, false);
+        assert_eq!(wrap_x.len(), 6 * 3);
+        assert_eq!(wrap_x[12..], [2, 5, 3, 2, 3, 0]);
+
+        // The last row of cells joins row 1 back to row 0
+        let wrap_z = grid_indices(2, 2, false, true);
+        assert_eq!(wrap_z, vec![0, 2, 3, 0, 3, 1, 2, 0, 1, 2, 1, 3]);
+
+        let both = grid_indices(3, 3, true, true);
+        assert_eq!(both.len(), 6 * 3 * 3);
+        // Every vertex of a wrapped torus is in the same number of triangles
+        let uses: Vec<usize> = (0..9).map(|i| both.iter().filter(|&&j| j == i).count()).collect();
+        assert!(uses.iter().all(|&n| n == 6), "{:?}", uses);
+    }
+
+    #[test]
+    fn data_rows_must_fit_the_grid() {
+        assert_eq!(data_row_range(0, 12, 4, 3), Ok(0..3));
+        assert_eq!(data_row_range(2, 4, 4, 3), Ok(2..3));
+        assert_eq!(data_row_range(3, 0, 4, 3), Ok(3..3));
+        assert!(data_row_range(0, 6, 4, 3).is_err());
+        assert!(data_row_range(2, 8, 4, 3).is_err());
+        // Past the end of usize rather than wrapping round to a small row
+        assert!(data_row_range(usize::MAX, 4, 4, 3).is_err());
+        assert!(data_row_range(usize::MAX / 4 + 1, 4, 4, 3).is_err());
+    }
+
+    #[test]
+    fn normals_follow_the_slope() {
+        // A 3x3 ramp rising by 1 per column, which is a step of 1 in x
+        let heights: Vec<f32> = (0..9).map(|i| (i % 3) as f32).collect();
+        let normals = get_grid_normals(&heights, 3, 3, 0..3);
+        let ramp = 1.0 / 2f32.sqrt();
+        for vertex in 0..9 {
+            assert_close(normal_at(&normals, vertex), [-ramp, ramp, 0.0]);
+        }
+
+        // Only the requested rows, packed from the first
+        let normals = get_grid_normals(&heights, 3, 3, 1..2);
+        assert_eq!(normals.len(), 9);
+    }
+
+    #[test]
+    fn holes_fall_back_to_one_sided_differences() {
+        // A 5x3 ramp, still rising by 1 per unit of x, with a hole in the
+        // middle of its centre row. Its neighbours take their slope from the
+        // other side.
+        let mut heights: Vec<f32> = (0..15).map(|i| (i % 5) as f32 * 0.5).collect();
+        heights[7] = f32::NAN;
+        let normals = get_grid_normals(&heights, 5, 3, 0..3);
+        assert!(normals.iter().all(|n| n.is_finite()), "{:?}", normals);
+
+        let ramp = 1.0 / 2f32.sqrt();
+        for &vertex in [6, 8, 2, 12].iter() {
+            assert_close(normal_at(&normals, vertex), [-ramp, ramp, 0.0]);
+        }
+
+        // A vertex between two holes, or a hole itself, with no finite
+        // neighbour along an axis is flat along it
+        let heights = [f32::NAN, 1.0, f32::NAN, 0.0, 0.0, 0.0];
+        let normals = get_grid_normals(&heights, 3, 2, 0..2);
+        assert!(normals.iter().all(|n| n.is_finite()), "{:?}", normals);
+        let down = 1.0 / 1.25f32.sqrt();
+        assert_close(normal_at(&normals, 1), [0.0, down, 0.5 * down]);
+        assert_close(normal_at(&normals, 0), [0.0, 1.0, 0.0]);
     }
 }
@@ -0,0 +1,107 @@
+use super::buffers::*;
+use super::entity::*;
+use super::mesh::compute_normals;
+use super::vertex_array::{get_instancing_extension, VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// Each instance is a column-major 4x4 transform followed by an RGBA colour
+const INSTANCE_FLOATS: usize = 20;
+const INSTANCE_STRIDE: i32 = (INSTANCE_FLOATS * 4) as i32;
+
+// One mesh drawn many times with a single instanced draw call
+pub struct InstancedMesh {
+    shader_type: ShaderType,
+    instancing: AngleInstancedArrays,
+    vertex_array: VertexArray,
+    instance_array_buffer: WebGlBuffer,
+    index_array_length: usize,
+    index_type: u32,
+    instance_count: usize,
+}
+
+impl Entity for InstancedMesh {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+
+            shader_controller.use_shader(gl, self.shader_type);
+            self.vertex_array.bind(gl);
+
+            let model = get_model_matrix(&current_state, position, rotation, scale);
+            let normal_matrix = glm::inverse_transpose(model);
+            let projection_matrix = current_state.get_projection_matrix();
+
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uNormalMatrix").as_ref(), false, normal_matrix.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            self.instancing
+                .draw_elements_instanced_angle_with_i32(GL::TRIANGLES, self.index_array_length as i32, self.index_type, 0, self.instance_count as i32);
+            self.vertex_array.unbind();
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl InstancedMesh {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, positions: &[f32], normals: Option<&[f32]>, indices: &[u32]) -> Result<Self, String> {
+        let instancing = get_instancing_extension(gl).ok_or("Instanced drawing needs ANGLE_instanced_arrays")?;
+
+        let vertex_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, positions, GL::STATIC_DRAW)?;
+        let normals_array_buffer = match normals {
+            Some(normals) => create_f32_buffer(gl, GL::ARRAY_BUFFER, normals, GL::STATIC_DRAW)?,
+            None => create_f32_buffer(gl, GL::ARRAY_BUFFER, &compute_normals(positions, indices), GL::STATIC_DRAW)?,
+        };
+        let instance_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let (index_array_buffer, index_type) = create_index_buffer(gl, indices)?;
+
+        // The transform is a mat4 attribute, which takes one location per column
+        let mut attributes = vec![VertexAttribute::buffer(0, vertex_array_buffer, 3), VertexAttribute::buffer(1, normals_array_buffer, 3)];
+        for column in 0..4 {
+            attributes.push(VertexAttribute::instanced(
+                2 + column,
+                instance_array_buffer.clone(),
+                4,
+                INSTANCE_STRIDE,
+                16 * column as i32,
+            ));
+        }
+        attributes.push(VertexAttribute::instanced(6, instance_array_buffer.clone(), 4, INSTANCE_STRIDE, 64));
+        let vertex_array = VertexArray::new(gl, attributes, Some(index_array_buffer));
+
+        Ok(Self {
+            shader_type,
+            instancing,
+            vertex_array,
+            instance_array_buffer,
+            index_array_length: indices.len(),
+            index_type,
+            instance_count: 0,
+        })
+    }
+
+    // `instances` holds INSTANCE_FLOATS values per instance
+    pub fn set_instances(&mut self, gl: &WebGlRenderingContext, instances: &[f32]) -> Result<(), String> {
+        if !instances.len().is_multiple_of(INSTANCE_FLOATS) {
+            return Err(format!("Instance data must hold {} floats per instance", INSTANCE_FLOATS));
+        }
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.instance_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, instances, GL::DYNAMIC_DRAW);
+        self.instance_count = instances.len() / INSTANCE_FLOATS;
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use super::entity::*;
+use super::mesh::Mesh;
+use crate::app_state::*;
+use crate::marching_cubes::extract_isosurface;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use web_sys::*;
+
+// The surface where a 3D scalar field crosses a level, drawn as a Mesh. The
+// samples are kept so that the level can change without resending them.
+pub struct Isosurface {
+    shader_type: ShaderType,
+    values: Vec<f32>,
+    // Samples along x, y and z, spread evenly from `min` to `max`
+    size: [usize; 3],
+    min: [f32; 3],
+    max: [f32; 3],
+    level: f32,
+    colour: [f32; 4],
+    // None when the level doesn't cross the field
+    mesh: Option<Mesh>,
+}
+
+impl Entity for Isosurface {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if let Some(mesh) = &self.mesh {
+            let model = get_model_matrix(&get_current_app_state(), position, rotation, scale);
+            mesh.render_with_model(gl, shader_controller, &model);
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Isosurface {
+    // The field spans [-1, 1] on every axis until given bounds
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, values: &[f32], size: [usize; 3], level: f32) -> Result<Self, String> {
+        let mut isosurface = Self {
+            shader_type,
+            values: Vec::new(),
+            size,
+            min: [-1.0; 3],
+            max: [1.0; 3],
+            level,
+            colour: [0.8, 0.8, 0.8, 1.0],
+            mesh: None,
+        };
+        isosurface.set_values(gl, values, size)?;
+        Ok(isosurface)
+    }
+
+    pub fn set_values(&mut self, gl: &WebGlRenderingContext, values: &[f32], size: [usize; 3]) -> Result<(), String> {
+        if size.iter().any(|&n| n < 2) {
+            return Err(String::from("A scalar field needs at least 2 samples along each axis"));
+        }
+        if values.len() != size[0] * size[1] * size[2] {
+            return Err(format!(
+                "A {}x{}x{} field needs {} samples but was given {}",
+                size[0],
+                size[1],
+                size[2],
+                size[0] * size[1] * size[2],
+                values.len()
+            ));
+        }
+        self.values = values.to_vec();
+        self.size = size;
+        self.rebuild(gl)
+    }
+
+    // Where the first and last samples on each axis sit
+    pub fn set_bounds(&mut self, gl: &WebGlRenderingContext, min: [f32; 3], max: [f32; 3]) -> Result<(), String> {
+        if min.iter().chain(max.iter()).any(|v| !v.is_finite()) || (0..3).any(|axis| min[axis] == max[axis]) {
+            return Err(String::from("Isosurface bounds must be finite and not empty"));
+        }
+        self.min = min;
+        self.max = max;
+        self.rebuild(gl)
+    }
+
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        (self.min, self.max)
+    }
+
+    pub fn set_level(&mut self, gl: &WebGlRenderingContext, level: f32) -> Result<(), String> {
+        self.level = level;
+        self.rebuild(gl)
+    }
+
+    pub fn set_colour(&mut self, colour: [f32; 4]) {
+        self.colour = colour;
+        if let Some(mesh) = &mut self.mesh {
+            mesh.set_base_colour(colour);
+        }
+    }
+
+    fn rebuild(&mut self, gl: &WebGlRenderingContext) -> Result<(), String> {
+        let iso_mesh = extract_isosurface(&self.values, self.size, self.min, self.max, self.level);
+        let mesh = if iso_mesh.indices.is_empty() {
+            None
+        } else {
+            let mut mesh = Mesh::new(gl, self.shader_type, &iso_mesh.positions, Some(&iso_mesh.normals), None, &iso_mesh.indices)?;
+            mesh.set_base_colour(self.colour);
+            Some(mesh)
+        };
+        if let Some(old_mesh) = std::mem::replace(&mut self.mesh, mesh) {
+            old_mesh.delete(gl);
+        }
+        Ok(())
+    }
+}
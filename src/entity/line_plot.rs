@@ -0,0 +1,336 @@
+use super::buffers::*;
+use super::entity::Entity;
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use std::ops::Range;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// A 2D plot of one or more series drawn over a rectangle of the canvas, on
+// top of the 3D scene. Each series keeps its latest samples in a ring buffer,
+// so long-running streams only ever hold `capacity` samples.
+pub struct LinePlot {
+    shader_type: ShaderType,
+    series: Vec<Series>,
+    // Subtracted from every x before it reaches the GPU, so large values such
+    // as timestamps keep their precision as f32. Set by the first sample.
+    x_origin: Option<f64>,
+    // Pinned axis ranges. None fits the current samples.
+    x_range: Option<(f64, f64)>,
+    y_range: Option<(f32, f32)>,
+    // left, top, width, height in canvas pixels. None fills the canvas.
+    viewport: Option<[f32; 4]>,
+}
+
+struct Series {
+    vertex_array: VertexArray,
+    positions_array_buffer: WebGlBuffer,
+    ring: Ring,
+    colour: [f32; 4],
+}
+
+impl Series {
+    fn new(gl: &WebGlRenderingContext, capacity: usize, colour: [f32; 4]) -> Result<Self, String> {
+        let ring = Ring::new(capacity);
+        let positions_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, &ring.positions, GL::DYNAMIC_DRAW)?;
+        let vertex_array = VertexArray::new(gl, vec![VertexAttribute::buffer(0, positions_array_buffer.clone(), 2)], None);
+
+        Ok(Self {
+            vertex_array,
+            positions_array_buffer,
+            ring,
+            colour,
+        })
+    }
+
+    // Only the slots written to are uploaded, in up to two runs for where the
+    // ring wraps, each written to both copies
+    fn append(&mut self, gl: &WebGlRenderingContext, samples: &[[f32; 2]]) {
+        let runs = self.ring.push(samples);
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.positions_array_buffer));
+        for run in runs.iter().filter(|run| !run.is_empty()) {
+            for copy in 0..2 {
+                let offset = 2 * (run.start + copy * self.ring.capacity);
+                upload_f32_sub_buffer(gl, GL::ARRAY_BUFFER, offset, &self.ring.positions[offset..offset + 2 * run.len()]);
+            }
+        }
+    }
+}
+
+// The ring is stored twice over, one copy after the other, so that the
+// samples from oldest to newest are always one contiguous run of vertices
+struct Ring {
+    // x, y pairs for 2 * capacity vertices
+    positions: Vec<f32>,
+    capacity: usize,
+    // Slot the next sample goes in, which is the oldest once the ring is full
+    head: usize,
+    len: usize,
+    // x_min, x_max, y_min, y_max of the samples held, relative to the x origin
+    range: Option<[f32; 4]>,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Self {
+        Self {
+            positions: vec![0.0; 4 * capacity],
+            capacity,
+            head: 0,
+            len: 0,
+            range: None,
+        }
+    }
+
+    // Returns the slots written to in the first copy, in up to two runs
+    fn push(&mut self, samples: &[[f32; 2]]) -> [Range<usize>; 2] {
+        // Anything older than the last `capacity` samples would be overwritten
+        let samples = &samples[samples.len().saturating_sub(self.capacity)..];
+        let start = self.head;
+        for sample in samples {
+            for copy in 0..2 {
+                let slot = self.head + copy * self.capacity;
+                self.positions[2 * slot..2 * slot + 2].copy_from_slice(sample);
+            }
+            self.head = (self.head + 1) % self.capacity;
+        }
+        self.len = (self.len + samples.len()).min(self.capacity);
+
+        self.range = self.samples().chunks(2).fold(None, |range, sample| {
+            let [x_min, x_max, y_min, y_max] = range.unwrap_or([f32::INFINITY, f32::NEG_INFINITY, f32::INFINITY, f32::NEG_INFINITY]);
+            Some([x_min.min(sample[0]), x_max.max(sample[0]), y_min.min(sample[1]), y_max.max(sample[1])])
+        });
+
+        let end = start + samples.len();
+        if end <= self.capacity {
+            [start..end, 0..0]
+        } else {
+            [start..self.capacity, 0..end - self.capacity]
+        }
+    }
+
+    // First vertex of the oldest sample
+    fn first(&self) -> usize {
+        if self.len < self.capacity {
+            0
+        } else {
+            self.head
+        }
+    }
+
+    // x, y pairs from oldest to newest
+    fn samples(&self) -> &[f32] {
+        &self.positions[2 * self.first()..2 * (self.first() + self.len)]
+    }
+
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.range = None;
+    }
+}
+
+impl Entity for LinePlot {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, _position: &glm::Vec3, _rotation: &glm::Vec3, _scale: &glm::Vec3) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+            let [x_min, x_max, y_min, y_max] = self.local_ranges();
+            let [left, top, width, height] = self.viewport.unwrap_or([0.0, 0.0, current_state.canvas_width, current_state.canvas_height]);
+            let bottom = current_state.canvas_height - top - height;
+
+            shader_controller.use_shader(gl, self.shader_type);
+
+            gl.uniform2f(shader.get_uniform_location(gl, "uDataMin").as_ref(), x_min, y_min);
+            gl.uniform2f(shader.get_uniform_location(gl, "uDataMax").as_ref(), x_max, y_max);
+            gl.uniform4f(
+                shader.get_uniform_location(gl, "uRect").as_ref(),
+                2.0 * left / current_state.canvas_width - 1.0,
+                2.0 * bottom / current_state.canvas_height - 1.0,
+                2.0 * (left + width) / current_state.canvas_width - 1.0,
+                2.0 * (bottom + height) / current_state.canvas_height - 1.0,
+            );
+
+            // Drawn over the scene, and clipped to the plot for pinned ranges
+            gl.disable(GL::DEPTH_TEST);
+            gl.enable(GL::SCISSOR_TEST);
+            gl.scissor(left as i32, bottom as i32, width as i32, height as i32);
+
+            for series in self.series.iter().filter(|series| series.ring.len > 1) {
+                series.vertex_array.bind(gl);
+                let [r, g, b, a] = series.colour;
+                gl.uniform4f(shader.get_uniform_location(gl, "uColour").as_ref(), r, g, b, a);
+                gl.draw_arrays(GL::LINE_STRIP, series.ring.first() as i32, series.ring.len as i32);
+                series.vertex_array.unbind();
+            }
+
+            gl.disable(GL::SCISSOR_TEST);
+            gl.enable(GL::DEPTH_TEST);
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl LinePlot {
+    pub fn new(shader_type: ShaderType) -> Self {
+        Self {
+            shader_type,
+            series: Vec::new(),
+            x_origin: None,
+            x_range: None,
+            y_range: None,
+            viewport: None,
+        }
+    }
+
+    // Returns the new series' index
+    pub fn add_series(&mut self, gl: &WebGlRenderingContext, capacity: usize, colour: [f32; 4]) -> Result<usize, String> {
+        if capacity < 2 {
+            return Err(String::from("A series needs room for at least 2 samples"));
+        }
+        self.series.push(Series::new(gl, capacity, colour)?);
+        Ok(self.series.len() - 1)
+    }
+
+    pub fn append(&mut self, gl: &WebGlRenderingContext, series: usize, xs: &[f64], ys: &[f32]) -> Result<(), String> {
+        if xs.len() != ys.len() {
+            return Err(format!("Got {} x values but {} y values", xs.len(), ys.len()));
+        }
+        // Only kept once the samples have gone in, so a bad series index
+        // leaves the plot as it was. Without any samples there's no origin.
+        let x_origin = self.x_origin.or_else(|| xs.first().copied());
+        let samples: Vec<[f32; 2]> = xs.iter().zip(ys.iter()).map(|(&x, &y)| [(x - x_origin.unwrap_or(0.0)) as f32, y]).collect();
+        self.series_mut(series)?.append(gl, &samples);
+        self.x_origin = x_origin;
+        Ok(())
+    }
+
+    pub fn clear_series(&mut self, series: usize) -> Result<(), String> {
+        self.series_mut(series)?.ring.clear();
+        Ok(())
+    }
+
+    pub fn set_x_range(&mut self, x_range: Option<(f64, f64)>) {
+        self.x_range = x_range;
+    }
+
+    pub fn set_y_range(&mut self, y_range: Option<(f32, f32)>) {
+        self.y_range = y_range;
+    }
+
+    pub fn set_viewport(&mut self, viewport: Option<[f32; 4]>) {
+        self.viewport = viewport;
+    }
+
+    // x_min, x_max, y_min, y_max as currently drawn, in data units
+    pub fn ranges(&self) -> [f64; 4] {
+        let [x_min, x_max, y_min, y_max] = self.local_ranges();
+        let x_origin = self.x_origin.unwrap_or(0.0);
+        [x_min as f64 + x_origin, x_max as f64 + x_origin, y_min as f64, y_max as f64]
+    }
+
+    // Ranges relative to the x origin. Pinned ranges win; otherwise they fit
+    // every series, widened around flat data so that it can still be drawn.
+    fn local_ranges(&self) -> [f32; 4] {
+        let fitted = self.series.iter().filter_map(|series| series.ring.range).fold(None, |range: Option<[f32; 4]>, series| {
+            Some(match range {
+                Some(range) => [range[0].min(series[0]), range[1].max(series[1]), range[2].min(series[2]), range[3].max(series[3])],
+                None => series,
+            })
+        });
+        let [mut x_min, mut x_max, mut y_min, mut y_max] = fitted.unwrap_or([0.0, 1.0, 0.0, 1.0]);
+        if x_min == x_max {
+            x_min -= 0.5;
+            x_max += 0.5;
+        }
+        if y_min == y_max {
+            y_min -= 0.5;
+            y_max += 0.5;
+        }
+
+        if let Some((min, max)) = self.x_range {
+            let x_origin = self.x_origin.unwrap_or(0.0);
+            x_min = (min - x_origin) as f32;
+            x_max = (max - x_origin) as f32;
+        }
+        if let Some((min, max)) = self.y_range {
+            y_min = min;
+            y_max = max;
+        }
+        [x_min, x_max, y_min, y_max]
+    }
+
+    fn series_mut(&mut self, series: usize) -> Result<&mut Series, String> {
+        let count = self.series.len();
+        self.series
+            .get_mut(series)
+            .ok_or_else(|| format!("Series {} doesn't exist; the plot has {}", series, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(xs: Range<usize>) -> Vec<[f32; 2]> {
+        xs.map(|x| [x as f32, 10.0 * x as f32]).collect()
+    }
+
+    // Both copies of the ring hold the same samples
+    fn copies_match(ring: &Ring) -> bool {
+        let half = 2 * ring.capacity;
+        ring.positions[..half] == ring.positions[half..]
+    }
+
+    #[test]
+    fn filling_up_keeps_the_oldest_first() {
+        let mut ring = Ring::new(4);
+        assert_eq!(ring.push(&samples(0..3)), [0..3, 0..0]);
+        assert_eq!(ring.first(), 0);
+        assert_eq!(ring.samples(), &[0.0, 0.0, 1.0, 10.0, 2.0, 20.0]);
+        assert_eq!(ring.range, Some([0.0, 2.0, 0.0, 20.0]));
+
+        // Filling the last slot wraps the head without splitting the run
+        assert_eq!(ring.push(&samples(3..4)), [3..4, 0..0]);
+        assert_eq!((ring.head, ring.first(), ring.len), (0, 0, 4));
+        assert!(copies_match(&ring));
+    }
+
+    #[test]
+    fn wrapping_splits_the_upload_in_two() {
+        let mut ring = Ring::new(4);
+        ring.push(&samples(0..3));
+        assert_eq!(ring.push(&samples(3..6)), [3..4, 0..2]);
+        assert_eq!((ring.head, ring.first(), ring.len), (2, 2, 4));
+        // Oldest to newest reads on into the second copy
+        assert_eq!(ring.samples(), &[2.0, 20.0, 3.0, 30.0, 4.0, 40.0, 5.0, 50.0]);
+        assert_eq!(ring.range, Some([2.0, 5.0, 20.0, 50.0]));
+        assert!(copies_match(&ring));
+    }
+
+    #[test]
+    fn only_the_newest_of_a_large_batch_are_kept() {
+        let mut ring = Ring::new(4);
+        ring.push(&samples(0..1));
+        assert_eq!(ring.push(&samples(1..11)), [1..4, 0..1]);
+        assert_eq!(ring.samples(), &[7.0, 70.0, 8.0, 80.0, 9.0, 90.0, 10.0, 100.0]);
+        assert!(copies_match(&ring));
+    }
+
+    #[test]
+    fn clearing_starts_again_at_the_first_slot() {
+        let mut ring = Ring::new(3);
+        ring.push(&samples(0..5));
+        ring.clear();
+        assert!(ring.samples().is_empty());
+        assert_eq!(ring.range, None);
+        assert_eq!(ring.push(&samples(5..7)), [0..2, 0..0]);
+        assert_eq!(ring.samples(), &[5.0, 50.0, 6.0, 60.0]);
+    }
+}
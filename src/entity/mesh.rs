@@ -0,0 +1,162 @@
+use super::buffers::*;
+use super::entity::*;
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::loader::image::Image;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+pub struct Mesh {
+    shader_type: ShaderType,
+    vertex_array: VertexArray,
+    // Every buffer the vertex array reads, kept so they can be deleted
+    buffers: Vec<WebGlBuffer>,
+    has_tex_coords: bool,
+    index_array_length: usize,
+    index_type: u32,
+    base_colour: [f32; 4],
+    texture: Option<WebGlTexture>,
+}
+
+impl Entity for Mesh {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        let model = get_model_matrix(&get_current_app_state(), position, rotation, scale);
+        self.render_with_model(gl, shader_controller, &model);
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Mesh {
+    // `normals` are generated from the triangles when the source has none
+    pub fn new(
+        gl: &WebGlRenderingContext,
+        shader_type: ShaderType,
+        positions: &[f32],
+        normals: Option<&[f32]>,
+        tex_coords: Option<&[f32]>,
+        indices: &[u32],
+    ) -> Result<Self, String> {
+        let vertex_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, positions, GL::STATIC_DRAW)?;
+        let normals_array_buffer = match normals {
+            Some(normals) => create_f32_buffer(gl, GL::ARRAY_BUFFER, normals, GL::STATIC_DRAW)?,
+            None => create_f32_buffer(gl, GL::ARRAY_BUFFER, &compute_normals(positions, indices), GL::STATIC_DRAW)?,
+        };
+        let (index_array_buffer, index_type) = create_index_buffer(gl, indices)?;
+        let mut buffers = vec![vertex_array_buffer.clone(), normals_array_buffer.clone(), index_array_buffer.clone()];
+        let tex_coords_attribute = match tex_coords {
+            Some(tex_coords) => {
+                let tex_coords_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, tex_coords, GL::STATIC_DRAW)?;
+                buffers.push(tex_coords_array_buffer.clone());
+                VertexAttribute::buffer(2, tex_coords_array_buffer, 2)
+            }
+            None => VertexAttribute::constant(2, [0.0, 0.0, 0.0, 1.0]),
+        };
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, vertex_array_buffer, 3),
+                VertexAttribute::buffer(1, normals_array_buffer, 3),
+                tex_coords_attribute,
+            ],
+            Some(index_array_buffer),
+        );
+
+        Ok(Self {
+            shader_type,
+            vertex_array,
+            buffers,
+            has_tex_coords: tex_coords.is_some(),
+            index_array_length: indices.len(),
+            index_type,
+            base_colour: [1.0, 1.0, 1.0, 1.0],
+            texture: None,
+        })
+    }
+
+    pub fn set_base_colour(&mut self, base_colour: [f32; 4]) {
+        self.base_colour = base_colour;
+    }
+
+    pub fn set_texture(&mut self, gl: &WebGlRenderingContext, image: &Image) -> Result<(), String> {
+        self.texture = Some(create_rgba_texture(gl, image.width, image.height, &image.rgba)?);
+        Ok(())
+    }
+
+    // Frees the GL buffers and texture along with the mesh, for owners that
+    // replace their meshes
+    pub fn delete(self, gl: &WebGlRenderingContext) {
+        for buffer in self.buffers.iter() {
+            gl.delete_buffer(Some(buffer));
+        }
+        gl.delete_texture(self.texture.as_ref());
+    }
+
+    pub fn render_with_model(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, model: &glm::Mat4) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+
+            shader_controller.use_shader(gl, self.shader_type);
+
+            self.vertex_array.bind(gl);
+
+            let use_texture = self.texture.is_some() && self.has_tex_coords;
+            if use_texture {
+                gl.active_texture(GL::TEXTURE0);
+                gl.bind_texture(GL::TEXTURE_2D, self.texture.as_ref());
+            }
+            gl.uniform1i(shader.get_uniform_location(gl, "uTexture").as_ref(), 0);
+            gl.uniform1i(shader.get_uniform_location(gl, "uUseTexture").as_ref(), use_texture as i32);
+            gl.uniform1i(shader.get_uniform_location(gl, "uTwoSided").as_ref(), 0);
+
+            let [r, g, b, a] = self.base_colour;
+            gl.uniform4f(shader.get_uniform_location(gl, "uBaseColour").as_ref(), r, g, b, a);
+
+            let normal_matrix = glm::inverse_transpose(*model);
+            let projection_matrix = current_state.get_projection_matrix();
+
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uNormalMatrix").as_ref(), false, normal_matrix.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            gl.draw_elements_with_i32(GL::TRIANGLES, self.index_array_length as i32, self.index_type, 0);
+            self.vertex_array.unbind();
+        }
+    }
+}
+
+// Area-weighted vertex normals: the unnormalised cross product of each face is
+// accumulated onto its corners, so larger faces contribute more.
+pub fn compute_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+    let mut normals = vec![0.0; positions.len()];
+    let vertex = |i: u32| glm::vec3(positions[3 * i as usize], positions[3 * i as usize + 1], positions[3 * i as usize + 2]);
+
+    for triangle in indices.chunks(3).filter(|t| t.len() == 3) {
+        let a = vertex(triangle[0]);
+        let b = vertex(triangle[1]);
+        let c = vertex(triangle[2]);
+        let face_normal = glm::cross(&(b - a), &(c - a));
+        for &i in triangle {
+            for axis in 0..3 {
+                normals[3 * i as usize + axis] += face_normal[axis];
+            }
+        }
+    }
+
+    for normal in normals.chunks_mut(3) {
+        let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+        if length > 0.0 {
+            normal.iter_mut().for_each(|n| *n /= length);
+        } else {
+            normal[1] = 1.0;
+        }
+    }
+    normals
+}
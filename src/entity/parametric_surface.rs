@@ -0,0 +1,279 @@
+use super::buffers::*;
+use super::entity::*;
+use super::graph3d::grid_indices;
+use super::mesh::compute_normals;
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::parametric_function::{sphere, ParametricFunction};
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// A surface traced out by (u, v) -> (x, y, z), re-evaluated every frame so
+// that it can move with time. Samples are laid out like a Graph3d grid, with
+// u along each row and one row per v.
+pub struct ParametricSurface {
+    shader_type: ShaderType,
+    function: Box<dyn ParametricFunction>,
+    // u_min, u_max, v_min, v_max
+    ranges: [f32; 4],
+    u_samples: usize,
+    v_samples: usize,
+    // A wrapped parameter joins its last samples back to its first instead of
+    // repeating the first sample at the end of its range
+    wrap_u: bool,
+    wrap_v: bool,
+    grid: ParametricGrid,
+    positions_array_buffer: WebGlBuffer,
+    normals_array_buffer: WebGlBuffer,
+    base_colour: [f32; 4],
+}
+
+// Triangle indices, kept on the CPU for the normals, which only change with
+// the sample counts and wrapping
+struct ParametricGrid {
+    vertex_array: VertexArray,
+    index_array_buffer: WebGlBuffer,
+    indices: Vec<u32>,
+    index_type: u32,
+}
+
+impl ParametricGrid {
+    fn new(
+        gl: &WebGlRenderingContext,
+        u_samples: usize,
+        v_samples: usize,
+        wrap: (bool, bool),
+        positions_array_buffer: &WebGlBuffer,
+        normals_array_buffer: &WebGlBuffer,
+    ) -> Result<Self, String> {
+        let indices = grid_indices(u_samples, v_samples, wrap.0, wrap.1);
+        let (index_array_buffer, index_type) = create_index_buffer(gl, &indices)?;
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, positions_array_buffer.clone(), 3),
+                VertexAttribute::buffer(1, normals_array_buffer.clone(), 3),
+                VertexAttribute::constant(2, [0.0, 0.0, 0.0, 1.0]),
+            ],
+            Some(index_array_buffer.clone()),
+        );
+
+        Ok(Self {
+            vertex_array,
+            index_array_buffer,
+            indices,
+            index_type,
+        })
+    }
+}
+
+impl Entity for ParametricSurface {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+
+            shader_controller.use_shader(gl, self.shader_type);
+
+            self.grid.vertex_array.bind(gl);
+
+            let positions = self.get_points(current_state.time);
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.positions_array_buffer));
+            upload_f32_buffer(gl, GL::ARRAY_BUFFER, &positions, GL::DYNAMIC_DRAW);
+
+            let mut normals = compute_normals(&positions, &self.grid.indices);
+            share_collapsed_normals(&positions, &mut normals, self.u_samples, self.v_samples);
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.normals_array_buffer));
+            upload_f32_buffer(gl, GL::ARRAY_BUFFER, &normals, GL::DYNAMIC_DRAW);
+
+            gl.uniform1i(shader.get_uniform_location(gl, "uUseTexture").as_ref(), 0);
+            gl.uniform1i(shader.get_uniform_location(gl, "uTwoSided").as_ref(), 1);
+            let [r, g, b, a] = self.base_colour;
+            gl.uniform4f(shader.get_uniform_location(gl, "uBaseColour").as_ref(), r, g, b, a);
+
+            let model = get_model_matrix(&current_state, position, rotation, scale);
+            let normal_matrix = glm::inverse_transpose(model);
+            let projection_matrix = current_state.get_projection_matrix();
+
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uNormalMatrix").as_ref(), false, normal_matrix.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            gl.draw_elements_with_i32(GL::TRIANGLES, self.grid.indices.len() as i32, self.grid.index_type, 0);
+            self.grid.vertex_array.unbind();
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl ParametricSurface {
+    // Starts as a unit sphere
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, u_samples: usize, v_samples: usize) -> Result<Self, String> {
+        validate_samples(u_samples, v_samples, true, false)?;
+        let positions_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let normals_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let grid = ParametricGrid::new(gl, u_samples, v_samples, (true, false), &positions_array_buffer, &normals_array_buffer)?;
+
+        Ok(Self {
+            shader_type,
+            function: Box::new(sphere),
+            ranges: [0.0, 2.0 * std::f32::consts::PI, 0.0, std::f32::consts::PI],
+            u_samples,
+            v_samples,
+            wrap_u: true,
+            wrap_v: false,
+            grid,
+            positions_array_buffer,
+            normals_array_buffer,
+            base_colour: [0.8, 0.8, 0.8, 1.0],
+        })
+    }
+
+    pub fn set_function(&mut self, function: Box<dyn ParametricFunction>) {
+        self.function = function;
+    }
+
+    pub fn function_mut(&mut self) -> &mut dyn ParametricFunction {
+        self.function.as_mut()
+    }
+
+    // u_min, u_max, v_min, v_max
+    pub fn set_ranges(&mut self, ranges: [f32; 4]) -> Result<(), String> {
+        let [u_min, u_max, v_min, v_max] = ranges;
+        if ranges.iter().any(|v| !v.is_finite()) || u_min == u_max || v_min == v_max {
+            return Err(String::from("The u and v ranges must be finite and not empty"));
+        }
+        self.ranges = ranges;
+        Ok(())
+    }
+
+    pub fn set_resolution(&mut self, gl: &WebGlRenderingContext, u_samples: usize, v_samples: usize, wrap_u: bool, wrap_v: bool) -> Result<(), String> {
+        validate_samples(u_samples, v_samples, wrap_u, wrap_v)?;
+        if (u_samples, v_samples, wrap_u, wrap_v) == (self.u_samples, self.v_samples, self.wrap_u, self.wrap_v) {
+            return Ok(());
+        }
+        let grid = ParametricGrid::new(gl, u_samples, v_samples, (wrap_u, wrap_v), &self.positions_array_buffer, &self.normals_array_buffer)?;
+        gl.delete_buffer(Some(&std::mem::replace(&mut self.grid, grid).index_array_buffer));
+        self.u_samples = u_samples;
+        self.v_samples = v_samples;
+        self.wrap_u = wrap_u;
+        self.wrap_v = wrap_v;
+        Ok(())
+    }
+
+    pub fn set_base_colour(&mut self, base_colour: [f32; 4]) {
+        self.base_colour = base_colour;
+    }
+
+    fn get_points(&self, curr_time: f32) -> Vec<f32> {
+        let [u_min, u_max, v_min, v_max] = self.ranges;
+        let u_step = (u_max - u_min) / steps(self.u_samples, self.wrap_u);
+        let v_step = (v_max - v_min) / steps(self.v_samples, self.wrap_v);
+        let t = curr_time / 1000.0;
+        let mut positions = Vec::with_capacity(3 * self.u_samples * self.v_samples);
+
+        for v in 0..self.v_samples {
+            for u in 0..self.u_samples {
+                let point = self.function.point(u_min + u as f32 * u_step, v_min + v as f32 * v_step, t);
+                positions.extend_from_slice(&point);
+            }
+        }
+        positions
+    }
+}
+
+// Steps between samples across a range. A wrapped range also steps from its
+// last sample back round to the first.
+fn steps(samples: usize, wrap: bool) -> f32 {
+    if wrap {
+        samples as f32
+    } else {
+        (samples - 1) as f32
+    }
+}
+
+// A row or column of samples that meets at a single point, such as the poles
+// of a sphere, only has slivers of triangles around each of its vertices, so
+// their normals would point every which way. They get the average instead.
+// Points count as the same within a small fraction of the surface's size,
+// since something like sin(PI) is never quite zero as an f32.
+fn share_collapsed_normals(positions: &[f32], normals: &mut [f32], u_samples: usize, v_samples: usize) {
+    let size = positions.iter().filter(|p| p.is_finite()).fold(0.0f32, |size, p| size.max(p.abs()));
+    let tolerance = 1e-5 * size;
+    let point = |i: usize| glm::vec3(positions[3 * i], positions[3 * i + 1], positions[3 * i + 2]);
+
+    let rows = (0..v_samples).map(|v| (v * u_samples, 1, u_samples));
+    let columns = (0..u_samples).map(|u| (u, u_samples, v_samples));
+    for (first, stride, count) in rows.chain(columns) {
+        let line = || (0..count).map(|i| first + i * stride);
+        if !line().all(|i| glm::distance(&point(i), &point(first)) <= tolerance) {
+            continue;
+        }
+        let sum = line().fold(glm::Vec3::zeros(), |sum, i| sum + glm::vec3(normals[3 * i], normals[3 * i + 1], normals[3 * i + 2]));
+        let length = glm::length(&sum);
+        if length > 0.0 {
+            for i in line() {
+                normals[3 * i..3 * i + 3].copy_from_slice((sum / length).as_slice());
+            }
+        }
+    }
+}
+
+fn validate_samples(u_samples: usize, v_samples: usize, wrap_u: bool, wrap_v: bool) -> Result<(), String> {
+    let minimum = |wrap: bool| if wrap { 3 } else { 2 };
+    if u_samples < minimum(wrap_u) || v_samples < minimum(wrap_v) {
+        return Err(String::from("A parametric surface needs at least 2 samples along u and v, or 3 along a wrapped parameter"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn wrapped_ranges_stop_a_step_short_of_the_end() {
+        // Samples at 0, 1/4, 1/2 and 3/4 of the way round, then back to 0
+        assert_eq!(steps(4, true), 4.0);
+        // Samples at 0, 1/3, 2/3 and the end of the range
+        assert_eq!(steps(4, false), 3.0);
+        assert!(validate_samples(2, 2, false, false).is_ok());
+        assert!(validate_samples(2, 2, true, false).is_err());
+        assert!(validate_samples(3, 1, true, false).is_err());
+    }
+
+    #[test]
+    fn sphere_poles_point_along_y() {
+        // The default sphere, wrapped along u
+        let (u_samples, v_samples) = (8, 5);
+        let mut positions = Vec::new();
+        for v in 0..v_samples {
+            for u in 0..u_samples {
+                let point = sphere(u as f32 * 2.0 * PI / steps(u_samples, true), v as f32 * PI / steps(v_samples, false), 0.0);
+                positions.extend_from_slice(&point);
+            }
+        }
+        let indices = grid_indices(u_samples, v_samples, true, false);
+        let mut normals = compute_normals(&positions, &indices);
+        share_collapsed_normals(&positions, &mut normals, u_samples, v_samples);
+
+        // Same side of the surface as the rows next to each pole
+        let side = normals[3 * u_samples + 1].signum();
+        for (row, y) in [(0, side), (v_samples - 1, -side)].iter() {
+            for u in 0..u_samples {
+                let i = 3 * (row * u_samples + u);
+                assert!((normals[i + 1] - y).abs() < 1e-5, "{:?}", &normals[i..i + 3]);
+            }
+        }
+        // Every other row is left as it was
+        assert!(normals[3 * u_samples..3 * 2 * u_samples].chunks(3).any(|n| n[0].abs() > 0.1));
+    }
+}
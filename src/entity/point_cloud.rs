@@ -0,0 +1,86 @@
+use super::buffers::*;
+use super::entity::*;
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::loader::points::PointCloudData;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// Distance from the camera to the origin, where attenuated points are drawn at
+// their nominal size
+const ATTENUATION_DISTANCE: f32 = 4.0;
+const DEFAULT_COLOUR: [f32; 3] = [0.9, 0.9, 0.9];
+
+pub struct PointCloud {
+    shader_type: ShaderType,
+    vertex_array: VertexArray,
+    point_count: usize,
+    point_size: f32,
+    size_attenuation: bool,
+}
+
+impl Entity for PointCloud {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+
+            shader_controller.use_shader(gl, self.shader_type);
+
+            self.vertex_array.bind(gl);
+
+            let model = get_model_matrix(&current_state, position, rotation, scale);
+            let projection_matrix = current_state.get_projection_matrix();
+
+            gl.uniform1f(shader.get_uniform_location(gl, "uPointSize").as_ref(), self.point_size);
+            gl.uniform1i(shader.get_uniform_location(gl, "uSizeAttenuation").as_ref(), self.size_attenuation as i32);
+            gl.uniform1f(shader.get_uniform_location(gl, "uAttenuationDistance").as_ref(), ATTENUATION_DISTANCE);
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            gl.draw_arrays(GL::POINTS, 0, self.point_count as i32);
+            self.vertex_array.unbind();
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl PointCloud {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, data: &PointCloudData) -> Result<Self, String> {
+        let colours = match &data.colours {
+            Some(colours) => colours.clone(),
+            None => DEFAULT_COLOUR.iter().cloned().cycle().take(data.positions.len()).collect(),
+        };
+
+        let vertex_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, &data.positions, GL::STATIC_DRAW)?;
+        let colours_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, &colours, GL::STATIC_DRAW)?;
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![VertexAttribute::buffer(0, vertex_array_buffer, 3), VertexAttribute::buffer(1, colours_array_buffer, 3)],
+            None,
+        );
+
+        Ok(Self {
+            shader_type,
+            vertex_array,
+            point_count: data.positions.len() / 3,
+            point_size: 2.0,
+            size_attenuation: false,
+        })
+    }
+
+    pub fn set_point_size(&mut self, point_size: f32) {
+        self.point_size = point_size;
+    }
+
+    pub fn set_size_attenuation(&mut self, size_attenuation: bool) {
+        self.size_attenuation = size_attenuation;
+    }
+}
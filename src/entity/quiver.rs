@@ -0,0 +1,236 @@
+use super::entity::*;
+use super::graph3d::value_range;
+use super::instanced_mesh::InstancedMesh;
+use crate::colourmap::Colourmap;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use std::f32::consts::PI;
+use web_sys::*;
+
+const ARROW_SEGMENTS: usize = 12;
+const SHAFT_RADIUS: f32 = 0.03;
+const HEAD_RADIUS: f32 = 0.08;
+// Where the head starts along the unit length arrow
+const HEAD_START: f32 = 0.7;
+
+// A vector field drawn as arrows, one instance of a shared arrow mesh per
+// sample. Arrows start at their sample, and their length and colour follow the
+// vector's magnitude.
+pub struct Quiver {
+    arrows: InstancedMesh,
+    positions: Vec<f32>,
+    vectors: Vec<f32>,
+    // Every arrow the same length, so only the colour shows magnitude
+    normalize: bool,
+    // Model space length of the longest arrow, or of every arrow when normalised
+    arrow_length: f32,
+    colourmap: Colourmap,
+    // Magnitudes mapped to either end of the colourmap. None fits the field.
+    colour_range: Option<(f32, f32)>,
+    // Where the first and last grid points sit when sampling a function
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Entity for Quiver {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        self.arrows.render(gl, shader_controller, position, rotation, scale);
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Quiver {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, positions: &[f32], vectors: &[f32]) -> Result<Self, String> {
+        let (arrow_positions, arrow_indices) = arrow_mesh();
+        let mut quiver = Self {
+            arrows: InstancedMesh::new(gl, shader_type, &arrow_positions, None, &arrow_indices)?,
+            positions: Vec::new(),
+            vectors: Vec::new(),
+            normalize: false,
+            arrow_length: 0.15,
+            colourmap: Colourmap::default(),
+            colour_range: None,
+            min: [-1.0; 3],
+            max: [1.0; 3],
+        };
+        quiver.set_field(gl, positions, vectors)?;
+        Ok(quiver)
+    }
+
+    // x, y, z triples for both the samples and their vectors
+    pub fn set_field(&mut self, gl: &WebGlRenderingContext, positions: &[f32], vectors: &[f32]) -> Result<(), String> {
+        if !positions.len().is_multiple_of(3) || positions.len() != vectors.len() {
+            return Err(String::from("A vector field needs an x, y, z vector for every x, y, z position"));
+        }
+        self.positions = positions.to_vec();
+        self.vectors = vectors.to_vec();
+        self.rebuild(gl)
+    }
+
+    // x, y pairs for a planar field, laid on the floor plane as x and z to
+    // match Graph3d's domain
+    pub fn set_field_2d(&mut self, gl: &WebGlRenderingContext, positions: &[f32], vectors: &[f32]) -> Result<(), String> {
+        if !positions.len().is_multiple_of(2) || positions.len() != vectors.len() {
+            return Err(String::from("A 2D vector field needs an x, y vector for every x, y position"));
+        }
+        let lift = |pairs: &[f32]| pairs.chunks(2).flat_map(|pair| vec![pair[0], 0.0, pair[1]]).collect::<Vec<f32>>();
+        self.set_field(gl, &lift(positions), &lift(vectors))
+    }
+
+    // Samples `function` on a grid over the current bounds. Axes with a single
+    // sample use the middle of their bounds.
+    pub fn set_function(&mut self, gl: &WebGlRenderingContext, function: &dyn Fn(f32, f32, f32) -> [f32; 3], size: [usize; 3]) -> Result<(), String> {
+        if size.contains(&0) {
+            return Err(String::from("A vector field grid needs at least 1 sample along each axis"));
+        }
+        let step = |axis: usize| {
+            if size[axis] > 1 {
+                (self.max[axis] - self.min[axis]) / (size[axis] - 1) as f32
+            } else {
+                0.0
+            }
+        };
+        let start = |axis: usize| {
+            if size[axis] > 1 {
+                self.min[axis]
+            } else {
+                (self.min[axis] + self.max[axis]) / 2.0
+            }
+        };
+        let (start, step) = ([start(0), start(1), start(2)], [step(0), step(1), step(2)]);
+
+        let count = size[0] * size[1] * size[2];
+        let mut positions = Vec::with_capacity(3 * count);
+        let mut vectors = Vec::with_capacity(3 * count);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let point = [start[0] + x as f32 * step[0], start[1] + y as f32 * step[1], start[2] + z as f32 * step[2]];
+                    positions.extend_from_slice(&point);
+                    vectors.extend_from_slice(&function(point[0], point[1], point[2]));
+                }
+            }
+        }
+        self.set_field(gl, &positions, &vectors)
+    }
+
+    // Only affects functions sampled afterwards
+    pub fn set_bounds(&mut self, min: [f32; 3], max: [f32; 3]) -> Result<(), String> {
+        if min.iter().chain(max.iter()).any(|v| !v.is_finite()) {
+            return Err(String::from("Vector field bounds must be finite"));
+        }
+        self.min = min;
+        self.max = max;
+        Ok(())
+    }
+
+    pub fn set_normalize(&mut self, gl: &WebGlRenderingContext, normalize: bool) -> Result<(), String> {
+        self.normalize = normalize;
+        self.rebuild(gl)
+    }
+
+    pub fn set_arrow_length(&mut self, gl: &WebGlRenderingContext, arrow_length: f32) -> Result<(), String> {
+        if !(arrow_length.is_finite() && arrow_length > 0.0) {
+            return Err(String::from("Arrow length must be positive"));
+        }
+        self.arrow_length = arrow_length;
+        self.rebuild(gl)
+    }
+
+    pub fn set_colourmap(&mut self, gl: &WebGlRenderingContext, colourmap: Colourmap) -> Result<(), String> {
+        self.colourmap = colourmap;
+        self.rebuild(gl)
+    }
+
+    pub fn set_colour_range(&mut self, gl: &WebGlRenderingContext, colour_range: Option<(f32, f32)>) -> Result<(), String> {
+        self.colour_range = colour_range;
+        self.rebuild(gl)
+    }
+
+    fn rebuild(&mut self, gl: &WebGlRenderingContext) -> Result<(), String> {
+        let magnitudes: Vec<f32> = self.vectors.chunks(3).map(|v| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()).collect();
+        let longest = magnitudes.iter().filter(|m| m.is_finite()).fold(0.0f32, |longest, &m| longest.max(m));
+        let (colour_min, colour_max) = self.colour_range.unwrap_or_else(|| value_range(&magnitudes));
+
+        let mut instances = Vec::with_capacity(20 * magnitudes.len());
+        for (i, &magnitude) in magnitudes.iter().enumerate() {
+            // Zero vectors have no direction to point in
+            if !magnitude.is_finite() || magnitude == 0.0 {
+                continue;
+            }
+            let length = if self.normalize { self.arrow_length } else { self.arrow_length * magnitude / longest };
+            let position = glm::vec3(self.positions[3 * i], self.positions[3 * i + 1], self.positions[3 * i + 2]);
+            let direction = glm::vec3(self.vectors[3 * i], self.vectors[3 * i + 1], self.vectors[3 * i + 2]) / magnitude;
+            let transform = glm::translation(&position) * rotation_from_up(&direction) * glm::scaling(&glm::vec3(length, length, length));
+            let colour = self.colourmap.sample((magnitude - colour_min) / (colour_max - colour_min));
+
+            instances.extend_from_slice(transform.as_slice());
+            instances.extend_from_slice(&[colour[0], colour[1], colour[2], 1.0]);
+        }
+        self.arrows.set_instances(gl, &instances)
+    }
+}
+
+// Turns +y onto `direction`, which must be a unit vector
+fn rotation_from_up(direction: &glm::Vec3) -> glm::Mat4 {
+    let up = glm::vec3(0.0, 1.0, 0.0);
+    let axis = glm::cross(&up, direction);
+    if glm::length(&axis) > 1e-6 {
+        glm::rotation(glm::dot(&up, direction).clamp(-1.0, 1.0).acos(), &glm::normalize(&axis))
+    } else if direction.y < 0.0 {
+        glm::rotation(PI, &glm::vec3(1.0, 0.0, 0.0))
+    } else {
+        glm::identity()
+    }
+}
+
+// A unit length arrow along +y from the origin: a capped cylinder for the shaft
+// and a cone for the head. Each part has its own vertices so the normals
+// computed from it stay sharp at the rims.
+fn arrow_mesh() -> (Vec<f32>, Vec<u32>) {
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    let ring = |radius: f32, y: f32, positions: &mut Vec<f32>| -> u32 {
+        let first = (positions.len() / 3) as u32;
+        for i in 0..ARROW_SEGMENTS {
+            let angle = 2.0 * PI * i as f32 / ARROW_SEGMENTS as f32;
+            positions.extend_from_slice(&[radius * angle.cos(), y, -radius * angle.sin()]);
+        }
+        first
+    };
+    let next = |i: usize| ((i + 1) % ARROW_SEGMENTS) as u32;
+
+    // Shaft sides
+    let bottom = ring(SHAFT_RADIUS, 0.0, &mut positions);
+    let top = ring(SHAFT_RADIUS, HEAD_START, &mut positions);
+    for i in 0..ARROW_SEGMENTS {
+        let (a, b) = (i as u32, next(i));
+        indices.extend_from_slice(&[bottom + a, bottom + b, top + b, bottom + a, top + b, top + a]);
+    }
+
+    // Discs closing the shaft's bottom and the underside of the head
+    for (radius, y) in [(SHAFT_RADIUS, 0.0), (HEAD_RADIUS, HEAD_START)].iter() {
+        let rim = ring(*radius, *y, &mut positions);
+        for i in 1..ARROW_SEGMENTS as u32 - 1 {
+            indices.extend_from_slice(&[rim, rim + i + 1, rim + i]);
+        }
+    }
+
+    // Head sides, with a tip vertex per segment so each face keeps its slope
+    let base = ring(HEAD_RADIUS, HEAD_START, &mut positions);
+    let tips = (positions.len() / 3) as u32;
+    for _ in 0..ARROW_SEGMENTS {
+        positions.extend_from_slice(&[0.0, 1.0, 0.0]);
+    }
+    for i in 0..ARROW_SEGMENTS {
+        indices.extend_from_slice(&[base + i as u32, base + next(i), tips + i as u32]);
+    }
+
+    (positions, indices)
+}
@@ -0,0 +1,246 @@
+use super::buffers::*;
+use super::entity::*;
+use super::graph3d::value_range;
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::colourmap::Colourmap;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// Same as PointCloud
+const ATTENUATION_DISTANCE: f32 = 4.0;
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PointShape {
+    Circle = 0,
+    Square = 1,
+    Cross = 2,
+}
+
+// Points drawn as sprites, each with its own size, colour and shape. Columns
+// that haven't been given fall back to one value for every point. Colours can
+// also come from a colourmap, driven by a scalar per point.
+pub struct Scatter3d {
+    shader_type: ShaderType,
+    vertex_array: VertexArray,
+    point_count: usize,
+    positions_array_buffer: WebGlBuffer,
+    // Per-point columns, or None to use the defaults below
+    sizes_array_buffer: Option<WebGlBuffer>,
+    colours_array_buffer: Option<WebGlBuffer>,
+    scalars_array_buffer: Option<WebGlBuffer>,
+    shapes_array_buffer: Option<WebGlBuffer>,
+    size: f32,
+    colour: [f32; 4],
+    shape: PointShape,
+    size_attenuation: bool,
+    colourmap_texture: WebGlTexture,
+    scalar_range: (f32, f32),
+    // Values mapped to either end of the colourmap. None fits the scalars.
+    colour_range: Option<(f32, f32)>,
+}
+
+impl Entity for Scatter3d {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+
+            shader_controller.use_shader(gl, self.shader_type);
+
+            self.vertex_array.bind(gl);
+
+            let model = get_model_matrix(&current_state, position, rotation, scale);
+            let projection_matrix = current_state.get_projection_matrix();
+
+            gl.uniform1i(shader.get_uniform_location(gl, "uSizeAttenuation").as_ref(), self.size_attenuation as i32);
+            gl.uniform1f(shader.get_uniform_location(gl, "uAttenuationDistance").as_ref(), ATTENUATION_DISTANCE);
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+
+            let (colour_min, colour_max) = self.colour_range.unwrap_or(self.scalar_range);
+            gl.active_texture(GL::TEXTURE0);
+            gl.bind_texture(GL::TEXTURE_2D, Some(&self.colourmap_texture));
+            gl.uniform1i(shader.get_uniform_location(gl, "uColourmap").as_ref(), 0);
+            gl.uniform1i(shader.get_uniform_location(gl, "uUseScalars").as_ref(), self.scalars_array_buffer.is_some() as i32);
+            gl.uniform2f(shader.get_uniform_location(gl, "uColourRange").as_ref(), colour_min, colour_max);
+
+            gl.draw_arrays(GL::POINTS, 0, self.point_count as i32);
+            self.vertex_array.unbind();
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Scatter3d {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, positions: &[f32]) -> Result<Self, String> {
+        if !positions.len().is_multiple_of(3) {
+            return Err(String::from("Scatter positions must be x, y, z triples"));
+        }
+        let positions_array_buffer = create_f32_buffer(gl, GL::ARRAY_BUFFER, positions, GL::DYNAMIC_DRAW)?;
+        let colourmap_texture = create_lookup_texture(gl, &Colourmap::default().to_rgba())?;
+
+        let mut scatter = Self {
+            shader_type,
+            vertex_array: VertexArray::new(gl, Vec::new(), None),
+            point_count: positions.len() / 3,
+            positions_array_buffer,
+            sizes_array_buffer: None,
+            colours_array_buffer: None,
+            scalars_array_buffer: None,
+            shapes_array_buffer: None,
+            size: 6.0,
+            colour: [0.9, 0.9, 0.9, 1.0],
+            shape: PointShape::Circle,
+            size_attenuation: false,
+            colourmap_texture,
+            scalar_range: (0.0, 1.0),
+            colour_range: None,
+        };
+        scatter.rebuild_vertex_array(gl);
+        Ok(scatter)
+    }
+
+    // Columns that no longer have one value per point go back to their defaults
+    pub fn set_positions(&mut self, gl: &WebGlRenderingContext, positions: &[f32]) -> Result<(), String> {
+        if !positions.len().is_multiple_of(3) {
+            return Err(String::from("Scatter positions must be x, y, z triples"));
+        }
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.positions_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, positions, GL::DYNAMIC_DRAW);
+
+        let point_count = positions.len() / 3;
+        if point_count != self.point_count {
+            self.point_count = point_count;
+            for column in [
+                &mut self.sizes_array_buffer,
+                &mut self.colours_array_buffer,
+                &mut self.scalars_array_buffer,
+                &mut self.shapes_array_buffer,
+            ]
+            .iter_mut()
+            {
+                if let Some(buffer) = column.take() {
+                    gl.delete_buffer(Some(&buffer));
+                }
+            }
+            self.rebuild_vertex_array(gl);
+        }
+        Ok(())
+    }
+
+    // Diameters in pixels
+    pub fn set_sizes(&mut self, gl: &WebGlRenderingContext, sizes: Option<&[f32]>) -> Result<(), String> {
+        let buffer = self.column_buffer(gl, sizes, 1, "sizes")?;
+        Self::replace_column(gl, &mut self.sizes_array_buffer, buffer);
+        self.rebuild_vertex_array(gl);
+        Ok(())
+    }
+
+    // RGBA per point
+    pub fn set_colours(&mut self, gl: &WebGlRenderingContext, colours: Option<&[f32]>) -> Result<(), String> {
+        let buffer = self.column_buffer(gl, colours, 4, "colours")?;
+        Self::replace_column(gl, &mut self.colours_array_buffer, buffer);
+        self.rebuild_vertex_array(gl);
+        Ok(())
+    }
+
+    // Colours points through the colourmap instead, keeping their alpha
+    pub fn set_scalars(&mut self, gl: &WebGlRenderingContext, scalars: Option<&[f32]>) -> Result<(), String> {
+        let buffer = self.column_buffer(gl, scalars, 1, "scalars")?;
+        Self::replace_column(gl, &mut self.scalars_array_buffer, buffer);
+        if let Some(scalars) = scalars {
+            self.scalar_range = value_range(scalars);
+        }
+        self.rebuild_vertex_array(gl);
+        Ok(())
+    }
+
+    // One PointShape value per point
+    pub fn set_shapes(&mut self, gl: &WebGlRenderingContext, shapes: Option<&[u8]>) -> Result<(), String> {
+        let shapes = match shapes {
+            Some(shapes) => {
+                if let Some(shape) = shapes.iter().find(|&&shape| shape > PointShape::Cross as u8) {
+                    return Err(format!("{} is not a PointShape", shape));
+                }
+                Some(shapes.iter().map(|&shape| shape as f32).collect::<Vec<f32>>())
+            }
+            None => None,
+        };
+        let buffer = self.column_buffer(gl, shapes.as_deref(), 1, "shapes")?;
+        Self::replace_column(gl, &mut self.shapes_array_buffer, buffer);
+        self.rebuild_vertex_array(gl);
+        Ok(())
+    }
+
+    // Used for every point without a value of its own
+    pub fn set_defaults(&mut self, gl: &WebGlRenderingContext, size: f32, colour: [f32; 4], shape: PointShape) {
+        self.size = size;
+        self.colour = colour;
+        self.shape = shape;
+        self.rebuild_vertex_array(gl);
+    }
+
+    pub fn set_size_attenuation(&mut self, size_attenuation: bool) {
+        self.size_attenuation = size_attenuation;
+    }
+
+    pub fn set_colourmap(&mut self, gl: &WebGlRenderingContext, colourmap: &Colourmap) -> Result<(), String> {
+        let texture = create_lookup_texture(gl, &colourmap.to_rgba())?;
+        gl.delete_texture(Some(&std::mem::replace(&mut self.colourmap_texture, texture)));
+        Ok(())
+    }
+
+    pub fn set_colour_range(&mut self, colour_range: Option<(f32, f32)>) {
+        self.colour_range = colour_range;
+    }
+
+    fn column_buffer(&self, gl: &WebGlRenderingContext, values: Option<&[f32]>, size: usize, name: &str) -> Result<Option<WebGlBuffer>, String> {
+        match values {
+            Some(values) if values.len() != size * self.point_count => Err(format!(
+                "{} points need {} {} but were given {}",
+                self.point_count,
+                size * self.point_count,
+                name,
+                values.len()
+            )),
+            Some(values) => Ok(Some(create_f32_buffer(gl, GL::ARRAY_BUFFER, values, GL::DYNAMIC_DRAW)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn replace_column(gl: &WebGlRenderingContext, column: &mut Option<WebGlBuffer>, buffer: Option<WebGlBuffer>) {
+        if let Some(old) = std::mem::replace(column, buffer) {
+            gl.delete_buffer(Some(&old));
+        }
+    }
+
+    // Attributes are fixed when a vertex array is made, so switching a column
+    // between a buffer and its default needs a new one
+    fn rebuild_vertex_array(&mut self, gl: &WebGlRenderingContext) {
+        let column = |location: u32, buffer: &Option<WebGlBuffer>, size: i32, default: [f32; 4]| match buffer {
+            Some(buffer) => VertexAttribute::buffer(location, buffer.clone(), size),
+            None => VertexAttribute::constant(location, default),
+        };
+        self.vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, self.positions_array_buffer.clone(), 3),
+                column(1, &self.sizes_array_buffer, 1, [self.size, 0.0, 0.0, 1.0]),
+                column(2, &self.colours_array_buffer, 4, self.colour),
+                column(3, &self.scalars_array_buffer, 1, [0.0, 0.0, 0.0, 1.0]),
+                column(4, &self.shapes_array_buffer, 1, [self.shape as u8 as f32, 0.0, 0.0, 1.0]),
+            ],
+            None,
+        );
+    }
+}
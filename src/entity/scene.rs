@@ -0,0 +1,66 @@
+use super::entity::*;
+use super::mesh::Mesh;
+use crate::app_state::*;
+use crate::loader::gltf::Gltf;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use nalgebra_glm as glm;
+use std::any::Any;
+use web_sys::*;
+
+// A loaded glTF scene: one Mesh entity per primitive, drawn at the world
+// transform of every node that references it
+pub struct Scene {
+    meshes: Vec<Vec<Mesh>>,
+    instances: Vec<(glm::Mat4, usize)>,
+}
+
+impl Entity for Scene {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        let model = get_model_matrix(&get_current_app_state(), position, rotation, scale);
+        for (world, mesh_index) in self.instances.iter() {
+            let instance_model = model * world;
+            for primitive in self.meshes[*mesh_index].iter() {
+                primitive.render_with_model(gl, shader_controller, &instance_model);
+            }
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Scene {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, gltf: &Gltf) -> Result<Self, String> {
+        let mut meshes = Vec::with_capacity(gltf.meshes.len());
+        for mesh in gltf.meshes.iter() {
+            let mut primitives = Vec::with_capacity(mesh.primitives.len());
+            for primitive in mesh.primitives.iter() {
+                let mut entity = Mesh::new(
+                    gl,
+                    shader_type,
+                    &primitive.positions,
+                    primitive.normals.as_deref(),
+                    primitive.tex_coords.as_deref(),
+                    &primitive.indices,
+                )?;
+
+                if let Some(material) = primitive.material.and_then(|m| gltf.materials.get(m)) {
+                    entity.set_base_colour(material.base_colour);
+                    if let Some(image) = material.base_colour_image.and_then(|i| gltf.images.get(i)).and_then(Option::as_ref) {
+                        entity.set_texture(gl, image)?;
+                    }
+                }
+                primitives.push(entity);
+            }
+            meshes.push(primitives);
+        }
+
+        Ok(Self {
+            meshes,
+            instances: gltf.mesh_instances(),
+        })
+    }
+}
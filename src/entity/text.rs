@@ -0,0 +1,260 @@
+use super::buffers::*;
+use super::entity::*;
+use super::vertex_array::{VertexArray, VertexAttribute};
+use crate::app_state::*;
+use crate::loader::font::{DistanceField, Font};
+use crate::loader::image::Image;
+use crate::shader::shader_controller::{ShaderController, ShaderType};
+use crate::text_layout::{layout_text, TextLayoutOptions};
+use nalgebra_glm as glm;
+use std::any::Any;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TextSpace {
+    // Positioned in canvas pixels from the top left, sized in pixels and drawn
+    // over everything else
+    Screen = 0,
+    // Positioned in the scene, sized in scene units and turned to face the
+    // camera
+    Billboard = 1,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TextLabel {
+    pub text: String,
+    pub space: TextSpace,
+    pub position: [f32; 3],
+    pub colour: [f32; 4],
+    pub options: TextLayoutOptions,
+}
+
+// Every label drawn with one font atlas. Geometry is rebuilt whenever a
+// label changes rather than every frame.
+pub struct Text {
+    shader_type: ShaderType,
+    // Shared with the text entities made by `share_font`
+    font: Rc<Font>,
+    atlas_texture: WebGlTexture,
+    vertex_array: VertexArray,
+    anchors_array_buffer: WebGlBuffer,
+    offsets_array_buffer: WebGlBuffer,
+    tex_coords_array_buffer: WebGlBuffer,
+    colours_array_buffer: WebGlBuffer,
+    // Removed labels leave a gap so the indices handed out stay valid
+    labels: Vec<Option<TextLabel>>,
+    // Screen text comes first in the buffers, then billboards
+    screen_vertex_count: usize,
+    billboard_vertex_count: usize,
+}
+
+impl Entity for Text {
+    fn render(&self, gl: &WebGlRenderingContext, shader_controller: &ShaderController, position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3) {
+        if self.screen_vertex_count + self.billboard_vertex_count == 0 {
+            return;
+        }
+
+        if let Some(shader) = shader_controller.get_shader(&self.shader_type) {
+            let current_state = get_current_app_state();
+
+            shader_controller.use_shader(gl, self.shader_type);
+            self.vertex_array.bind(gl);
+
+            let model = get_model_matrix(&current_state, position, rotation, scale);
+            let projection_matrix = current_state.get_projection_matrix();
+            let view = current_state.get_view_matrix();
+
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uModel").as_ref(), false, model.as_slice());
+            gl.uniform_matrix4fv_with_f32_array(shader.get_uniform_location(gl, "uViewProjection").as_ref(), false, projection_matrix.as_slice());
+            gl.uniform2f(
+                shader.get_uniform_location(gl, "uScreenSize").as_ref(),
+                current_state.canvas_width,
+                current_state.canvas_height,
+            );
+            gl.uniform3f(shader.get_uniform_location(gl, "uCameraRight").as_ref(), view[(0, 0)], view[(0, 1)], view[(0, 2)]);
+            gl.uniform3f(shader.get_uniform_location(gl, "uCameraUp").as_ref(), view[(1, 0)], view[(1, 1)], view[(1, 2)]);
+
+            gl.active_texture(GL::TEXTURE0);
+            gl.bind_texture(GL::TEXTURE_2D, Some(&self.atlas_texture));
+            gl.uniform1i(shader.get_uniform_location(gl, "uAtlas").as_ref(), 0);
+            // Only used with standard derivatives, so they may be compiled out
+            gl.uniform2f(shader.find_uniform_location(gl, "uAtlasSize").as_ref(), self.font.atlas_width, self.font.atlas_height);
+            gl.uniform1f(shader.find_uniform_location(gl, "uDistanceRange").as_ref(), self.font.distance_range);
+            gl.uniform1i(shader.get_uniform_location(gl, "uMsdf").as_ref(), (self.font.distance_field == DistanceField::Msdf) as i32);
+
+            // Glyph quads overlap, so they mustn't hide each other through the
+            // depth buffer
+            gl.depth_mask(false);
+            if self.billboard_vertex_count > 0 {
+                gl.uniform1i(shader.get_uniform_location(gl, "uScreenSpace").as_ref(), 0);
+                gl.draw_arrays(GL::TRIANGLES, self.screen_vertex_count as i32, self.billboard_vertex_count as i32);
+            }
+            if self.screen_vertex_count > 0 {
+                gl.disable(GL::DEPTH_TEST);
+                gl.uniform1i(shader.get_uniform_location(gl, "uScreenSpace").as_ref(), 1);
+                gl.draw_arrays(GL::TRIANGLES, 0, self.screen_vertex_count as i32);
+                gl.enable(GL::DEPTH_TEST);
+            }
+            gl.depth_mask(true);
+
+            self.vertex_array.unbind();
+        }
+    }
+
+    fn update(&self, _time: f32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Text {
+    pub fn new(gl: &WebGlRenderingContext, shader_type: ShaderType, font: Font, atlas: &Image) -> Result<Self, String> {
+        if atlas.width as f32 != font.atlas_width || atlas.height as f32 != font.atlas_height {
+            return Err(format!(
+                "Font expects a {}x{} atlas but the image is {}x{}",
+                font.atlas_width, font.atlas_height, atlas.width, atlas.height
+            ));
+        }
+
+        let atlas_texture = create_rgba_texture(gl, atlas.width, atlas.height, &atlas.rgba)?;
+        // Mipmaps blur the distance field, so always sample the full size atlas
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        Self::with_font(gl, shader_type, Rc::new(font), atlas_texture)
+    }
+
+    // A new text entity with no labels that draws with this one's font and
+    // atlas, for other entities to label themselves with
+    pub fn share_font(&self, gl: &WebGlRenderingContext) -> Result<Self, String> {
+        Self::with_font(gl, self.shader_type, self.font.clone(), self.atlas_texture.clone())
+    }
+
+    fn with_font(gl: &WebGlRenderingContext, shader_type: ShaderType, font: Rc<Font>, atlas_texture: WebGlTexture) -> Result<Self, String> {
+        let anchors_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let offsets_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let tex_coords_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let colours_array_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+        let vertex_array = VertexArray::new(
+            gl,
+            vec![
+                VertexAttribute::buffer(0, anchors_array_buffer.clone(), 3),
+                VertexAttribute::buffer(1, offsets_array_buffer.clone(), 2),
+                VertexAttribute::buffer(2, tex_coords_array_buffer.clone(), 2),
+                VertexAttribute::buffer(3, colours_array_buffer.clone(), 4),
+            ],
+            None,
+        );
+
+        Ok(Self {
+            shader_type,
+            font,
+            atlas_texture,
+            vertex_array,
+            anchors_array_buffer,
+            offsets_array_buffer,
+            tex_coords_array_buffer,
+            colours_array_buffer,
+            labels: Vec::new(),
+            screen_vertex_count: 0,
+            billboard_vertex_count: 0,
+        })
+    }
+
+    pub fn add_label(&mut self, gl: &WebGlRenderingContext, label: TextLabel) -> usize {
+        self.labels.push(Some(label));
+        self.rebuild(gl);
+        self.labels.len() - 1
+    }
+
+    pub fn set_label_text(&mut self, gl: &WebGlRenderingContext, index: usize, text: &str) -> Result<(), String> {
+        self.label_mut(index)?.text = text.to_string();
+        self.rebuild(gl);
+        Ok(())
+    }
+
+    pub fn set_label_position(&mut self, gl: &WebGlRenderingContext, index: usize, position: [f32; 3]) -> Result<(), String> {
+        self.label_mut(index)?.position = position;
+        self.rebuild(gl);
+        Ok(())
+    }
+
+    pub fn remove_label(&mut self, gl: &WebGlRenderingContext, index: usize) -> Result<(), String> {
+        self.label_mut(index)?;
+        self.labels[index] = None;
+        self.rebuild(gl);
+        Ok(())
+    }
+
+    pub fn clear(&mut self, gl: &WebGlRenderingContext) {
+        self.labels.clear();
+        self.rebuild(gl);
+    }
+
+    // Replaces every label, rebuilding only if they differ from the current ones
+    pub fn set_labels(&mut self, gl: &WebGlRenderingContext, labels: Vec<TextLabel>) {
+        if self.labels.len() != labels.len() || self.labels.iter().zip(labels.iter()).any(|(current, new)| current.as_ref() != Some(new)) {
+            self.labels = labels.into_iter().map(Some).collect();
+            self.rebuild(gl);
+        }
+    }
+
+    // Width and height of `text` laid out with `options`
+    pub fn measure(&self, text: &str, options: &TextLayoutOptions) -> (f32, f32) {
+        let layout = layout_text(&self.font, text, options);
+        (layout.width, layout.height)
+    }
+
+    fn label_mut(&mut self, index: usize) -> Result<&mut TextLabel, String> {
+        self.labels
+            .get_mut(index)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| format!("There is no text label {}", index))
+    }
+
+    fn rebuild(&mut self, gl: &WebGlRenderingContext) {
+        let mut anchors = Vec::new();
+        let mut offsets = Vec::new();
+        let mut tex_coords = Vec::new();
+        let mut colours = Vec::new();
+
+        let mut vertex_counts = [0; 2];
+        for (count, space) in vertex_counts.iter_mut().zip([TextSpace::Screen, TextSpace::Billboard].iter()) {
+            for label in self.labels.iter().flatten().filter(|label| label.space == *space) {
+                let layout = layout_text(&self.font, &label.text, &label.options);
+                for quad in layout.quads.iter() {
+                    let [u0, v0, u1, v1] = quad.tex_coords;
+                    let corners = [
+                        (quad.left, quad.top, u0, v0),
+                        (quad.left, quad.bottom, u0, v1),
+                        (quad.right, quad.bottom, u1, v1),
+                        (quad.left, quad.top, u0, v0),
+                        (quad.right, quad.bottom, u1, v1),
+                        (quad.right, quad.top, u1, v0),
+                    ];
+                    for &(x, y, u, v) in corners.iter() {
+                        anchors.extend_from_slice(&label.position);
+                        offsets.extend_from_slice(&[x, y]);
+                        tex_coords.extend_from_slice(&[u, v]);
+                        colours.extend_from_slice(&label.colour);
+                    }
+                }
+                *count += 6 * layout.quads.len();
+            }
+        }
+        self.screen_vertex_count = vertex_counts[0];
+        self.billboard_vertex_count = vertex_counts[1];
+
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.anchors_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, &anchors, GL::DYNAMIC_DRAW);
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.offsets_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, &offsets, GL::DYNAMIC_DRAW);
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.tex_coords_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, &tex_coords, GL::DYNAMIC_DRAW);
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.colours_array_buffer));
+        upload_f32_buffer(gl, GL::ARRAY_BUFFER, &colours, GL::DYNAMIC_DRAW);
+    }
+}
@@ -0,0 +1,174 @@
+use wasm_bindgen::JsCast;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+pub enum AttributeSource {
+    // Tightly packed floats, `size` components per vertex
+    Buffer(WebGlBuffer, i32),
+    // Advances once per instance rather than once per vertex, read from an
+    // interleaved buffer with byte `stride` and `offset`
+    Instanced { buffer: WebGlBuffer, size: i32, stride: i32, offset: i32 },
+    // The same value for every vertex; generic attribute values aren't part of
+    // VAO state so these are re-applied on every bind
+    Constant([f32; 4]),
+}
+
+pub struct VertexAttribute {
+    pub location: u32,
+    pub source: AttributeSource,
+}
+
+impl VertexAttribute {
+    pub fn buffer(location: u32, buffer: WebGlBuffer, size: i32) -> Self {
+        Self {
+            location,
+            source: AttributeSource::Buffer(buffer, size),
+        }
+    }
+
+    pub fn instanced(location: u32, buffer: WebGlBuffer, size: i32, stride: i32, offset: i32) -> Self {
+        Self {
+            location,
+            source: AttributeSource::Instanced { buffer, size, stride, offset },
+        }
+    }
+
+    pub fn constant(location: u32, value: [f32; 4]) -> Self {
+        Self {
+            location,
+            source: AttributeSource::Constant(value),
+        }
+    }
+}
+
+enum Binding {
+    Vao(OesVertexArrayObject, WebGlVertexArrayObject),
+    // Without the extension the attribute setup is replayed on every bind, and
+    // every other attribute is disabled so that state left behind by the
+    // previous draw can't leak into this one
+    Fallback { max_attributes: u32 },
+}
+
+// The attribute and index buffer bindings for one mesh. The client runs a
+// WebGL 1 context, so vertex array objects come from OES_vertex_array_object.
+pub struct VertexArray {
+    binding: Binding,
+    instancing: Option<AngleInstancedArrays>,
+    attributes: Vec<VertexAttribute>,
+    index_buffer: Option<WebGlBuffer>,
+}
+
+impl VertexArray {
+    pub fn new(gl: &WebGlRenderingContext, attributes: Vec<VertexAttribute>, index_buffer: Option<WebGlBuffer>) -> Self {
+        let extension = gl
+            .get_extension("OES_vertex_array_object")
+            .ok()
+            .flatten()
+            .map(|e| e.unchecked_into::<OesVertexArrayObject>());
+        let instancing = get_instancing_extension(gl);
+        let binding = match extension {
+            Some(extension) => match extension.create_vertex_array_oes() {
+                Some(vao) => {
+                    extension.bind_vertex_array_oes(Some(&vao));
+                    Self::specify(gl, instancing.as_ref(), &attributes, index_buffer.as_ref());
+                    extension.bind_vertex_array_oes(None);
+                    Binding::Vao(extension, vao)
+                }
+                None => Self::fallback_binding(gl),
+            },
+            None => Self::fallback_binding(gl),
+        };
+
+        Self {
+            binding,
+            instancing,
+            attributes,
+            index_buffer,
+        }
+    }
+
+    pub fn bind(&self, gl: &WebGlRenderingContext) {
+        match &self.binding {
+            Binding::Vao(extension, vao) => {
+                extension.bind_vertex_array_oes(Some(vao));
+                Self::apply_constants(gl, &self.attributes);
+            }
+            Binding::Fallback { max_attributes } => {
+                for location in 0..*max_attributes {
+                    if !self.attributes.iter().any(|a| a.location == location) {
+                        gl.disable_vertex_attrib_array(location);
+                    }
+                }
+                Self::specify(gl, self.instancing.as_ref(), &self.attributes, self.index_buffer.as_ref());
+            }
+        }
+    }
+
+    // Entities unbind after drawing so that later buffer uploads can't bind an
+    // element array buffer into this VAO
+    pub fn unbind(&self) {
+        if let Binding::Vao(extension, _) = &self.binding {
+            extension.bind_vertex_array_oes(None);
+        }
+    }
+
+    fn fallback_binding(gl: &WebGlRenderingContext) -> Binding {
+        let max_attributes = gl.get_parameter(GL::MAX_VERTEX_ATTRIBS).ok().and_then(|v| v.as_f64()).unwrap_or(8.0) as u32;
+        Binding::Fallback { max_attributes }
+    }
+
+    // Divisors are reset for per-vertex attributes too, as the fallback path
+    // shares its attribute state with whatever instanced draw came before
+    fn specify(gl: &WebGlRenderingContext, instancing: Option<&AngleInstancedArrays>, attributes: &[VertexAttribute], index_buffer: Option<&WebGlBuffer>) {
+        for attribute in attributes.iter() {
+            match &attribute.source {
+                AttributeSource::Buffer(buffer, size) => {
+                    gl.bind_buffer(GL::ARRAY_BUFFER, Some(buffer));
+                    gl.vertex_attrib_pointer_with_i32(attribute.location, *size, GL::FLOAT, false, 0, 0);
+                    gl.enable_vertex_attrib_array(attribute.location);
+                    if let Some(instancing) = instancing {
+                        instancing.vertex_attrib_divisor_angle(attribute.location, 0);
+                    }
+                }
+                AttributeSource::Instanced { buffer, size, stride, offset } => {
+                    gl.bind_buffer(GL::ARRAY_BUFFER, Some(buffer));
+                    gl.vertex_attrib_pointer_with_i32(attribute.location, *size, GL::FLOAT, false, *stride, *offset);
+                    gl.enable_vertex_attrib_array(attribute.location);
+                    if let Some(instancing) = instancing {
+                        instancing.vertex_attrib_divisor_angle(attribute.location, 1);
+                    }
+                }
+                AttributeSource::Constant(value) => {
+                    gl.disable_vertex_attrib_array(attribute.location);
+                    gl.vertex_attrib4fv_with_f32_array(attribute.location, value);
+                }
+            }
+        }
+        if let Some(index_buffer) = index_buffer {
+            gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(index_buffer));
+        }
+    }
+
+    fn apply_constants(gl: &WebGlRenderingContext, attributes: &[VertexAttribute]) {
+        for attribute in attributes.iter() {
+            if let AttributeSource::Constant(value) = &attribute.source {
+                gl.vertex_attrib4fv_with_f32_array(attribute.location, value);
+            }
+        }
+    }
+}
+
+pub fn get_instancing_extension(gl: &WebGlRenderingContext) -> Option<AngleInstancedArrays> {
+    gl.get_extension("ANGLE_instanced_arrays")
+        .ok()
+        .flatten()
+        .map(|e| e.unchecked_into::<AngleInstancedArrays>())
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        if let Binding::Vao(extension, vao) = &self.binding {
+            extension.delete_vertex_array_oes(Some(vao));
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt::Write;
+
+// Writers for triangle meshes as Wavefront OBJ text and binary STL, for
+// loading surfaces into modelling tools or slicers. Meshes are given as x, y, z
+// triples of positions and optionally normals, and indices in threes.
+// Triangles with a non-finite corner are holes: they're left out, along with
+// any vertices only they use. Normals that aren't finite, as next to a hole
+// or at a singular point of a gradient, are replaced from the facets around
+// them.
+
+const STL_HEADER: &[u8] = b"wasm_gl surface";
+const STL_HEADER_LENGTH: usize = 80;
+const STL_TRIANGLE_LENGTH: usize = 50;
+
+pub fn write_obj(positions: &[f32], normals: Option<&[f32]>, indices: &[u32]) -> String {
+    let (kept, triangles) = finite_triangles(positions, indices);
+
+    let mut obj = String::new();
+    for &vertex in kept.iter() {
+        let p = &positions[3 * vertex..3 * vertex + 3];
+        writeln!(obj, "v {} {} {}", p[0], p[1], p[2]).unwrap();
+    }
+    if let Some(normals) = normals {
+        let fallback = vertex_normals(positions, &kept, &triangles);
+        for (new_index, &vertex) in kept.iter().enumerate() {
+            let given = &normals[3 * vertex..3 * vertex + 3];
+            let n = if given.iter().all(|v| v.is_finite()) {
+                given
+            } else {
+                &fallback[3 * new_index..3 * new_index + 3]
+            };
+            writeln!(obj, "vn {} {} {}", n[0], n[1], n[2]).unwrap();
+        }
+    }
+    // OBJ indices count from 1
+    for triangle in triangles.chunks(3) {
+        let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+        if normals.is_some() {
+            writeln!(obj, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c).unwrap();
+        } else {
+            writeln!(obj, "f {} {} {}", a, b, c).unwrap();
+        }
+    }
+    obj
+}
+
+// STL has no shared vertices or vertex normals, so each triangle is written
+// with its own corners and a facet normal from its winding
+pub fn write_stl(positions: &[f32], indices: &[u32]) -> Vec<u8> {
+    let (kept, triangles) = finite_triangles(positions, indices);
+    let corner = |i: u32| {
+        let vertex = kept[i as usize];
+        [positions[3 * vertex], positions[3 * vertex + 1], positions[3 * vertex + 2]]
+    };
+
+    let count = triangles.len() / 3;
+    let mut stl = Vec::with_capacity(STL_HEADER_LENGTH + 4 + STL_TRIANGLE_LENGTH * count);
+    stl.extend_from_slice(STL_HEADER);
+    stl.resize(STL_HEADER_LENGTH, 0);
+    stl.extend_from_slice(&(count as u32).to_le_bytes());
+    for triangle in triangles.chunks(3) {
+        let [a, b, c] = [corner(triangle[0]), corner(triangle[1]), corner(triangle[2])];
+        for value in facet_normal(&a, &b, &c).iter().chain(a.iter()).chain(b.iter()).chain(c.iter()) {
+            stl.extend_from_slice(&value.to_le_bytes());
+        }
+        // Attribute byte count, which nothing uses
+        stl.extend_from_slice(&[0, 0]);
+    }
+    stl
+}
+
+// The vertices used by triangles with only finite corners, in their original
+// order, and those triangles indexed into that list
+fn finite_triangles(positions: &[f32], indices: &[u32]) -> (Vec<usize>, Vec<u32>) {
+    let finite = |vertex: u32| positions[3 * vertex as usize..3 * vertex as usize + 3].iter().all(|v| v.is_finite());
+    let triangles: Vec<&[u32]> = indices.chunks_exact(3).filter(|triangle| triangle.iter().all(|&vertex| finite(vertex))).collect();
+
+    let mut remap = vec![None; positions.len() / 3];
+    for &vertex in triangles.iter().flat_map(|triangle| triangle.iter()) {
+        remap[vertex as usize] = Some(0);
+    }
+    let mut kept = Vec::new();
+    for (vertex, new_index) in remap.iter_mut().enumerate() {
+        if new_index.is_some() {
+            *new_index = Some(kept.len() as u32);
+            kept.push(vertex);
+        }
+    }
+    let remapped = triangles
+        .iter()
+        .flat_map(|triangle| triangle.iter())
+        .map(|&vertex| remap[vertex as usize].unwrap())
+        .collect();
+    (kept, remapped)
+}
+
+// Normals for the kept vertices averaged from the facets around them, for
+// standing in where a given normal isn't finite
+fn vertex_normals(positions: &[f32], kept: &[usize], triangles: &[u32]) -> Vec<f32> {
+    let corner = |i: u32| {
+        let vertex = kept[i as usize];
+        [positions[3 * vertex], positions[3 * vertex + 1], positions[3 * vertex + 2]]
+    };
+    let mut normals = vec![0.0; 3 * kept.len()];
+    for triangle in triangles.chunks(3) {
+        let facet = facet_normal(&corner(triangle[0]), &corner(triangle[1]), &corner(triangle[2]));
+        for &i in triangle.iter() {
+            for axis in 0..3 {
+                normals[3 * i as usize + axis] += facet[axis];
+            }
+        }
+    }
+    for normal in normals.chunks_mut(3) {
+        let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+        if length > 0.0 {
+            normal.iter_mut().for_each(|n| *n /= length);
+        }
+    }
+    normals
+}
+
+// Counter-clockwise winding faces the viewer. Degenerate triangles get a zero
+// normal, which readers recompute.
+fn facet_normal(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> [f32; 3] {
+    let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        [n[0] / length, n[1] / length, n[2] / length]
+    } else {
+        [0.0; 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit square in the x-z plane, split into two triangles facing up
+    fn square() -> (Vec<f32>, Vec<f32>, Vec<u32>) {
+        let positions = vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0];
+        let normals = [0.0, 1.0, 0.0].repeat(4);
+        (positions, normals, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    }
+
+    #[test]
+    fn obj_lists_vertices_normals_and_faces() {
+        let (positions, normals, indices) = square();
+        let obj = write_obj(&positions, Some(&normals), &indices);
+        let lines: Vec<&str> = obj.lines().collect();
+        assert_eq!(lines.iter().filter(|line| line.starts_with("v ")).count(), 4);
+        assert_eq!(lines.iter().filter(|line| line.starts_with("vn ")).count(), 4);
+        assert_eq!(lines[1], "v 0 0 1");
+        assert_eq!(lines[4], "vn 0 1 0");
+        assert_eq!(&lines[8..], ["f 1//1 2//2 3//3", "f 1//1 3//3 4//4"]);
+
+        let without_normals = write_obj(&positions, None, &indices);
+        assert!(!without_normals.contains("vn"));
+        assert!(without_normals.ends_with("f 1 2 3\nf 1 3 4\n"));
+    }
+
+    #[test]
+    fn holes_are_left_out() {
+        // A 3x2 grid missing the far corner of its first row, so only the left
+        // cell's triangles and the right cell's first triangle survive
+        let mut positions = Vec::new();
+        for z in 0..2 {
+            for x in 0..3 {
+                positions.extend_from_slice(&[x as f32, 0.0, z as f32]);
+            }
+        }
+        positions[3 * 2 + 1] = f32::NAN;
+        let indices = vec![0, 3, 4, 0, 4, 1, 1, 4, 5, 1, 5, 2];
+
+        let obj = write_obj(&positions, None, &indices);
+        assert!(!obj.contains("NaN"));
+        assert_eq!(obj.lines().filter(|line| line.starts_with("v ")).count(), 5);
+        assert!(obj.ends_with("f 1 3 4\nf 1 4 2\nf 2 4 5\n"));
+
+        let stl = write_stl(&positions, &indices);
+        assert_eq!(u32::from_le_bytes([stl[80], stl[81], stl[82], stl[83]]), 3);
+    }
+
+    #[test]
+    fn normals_next_to_holes_are_finite() {
+        // The same holed grid, facing up, with the hole's neighbours given
+        // normals that came from differencing across it
+        let mut positions = Vec::new();
+        for z in 0..2 {
+            for x in 0..3 {
+                positions.extend_from_slice(&[x as f32, 0.0, z as f32]);
+            }
+        }
+        positions[3 * 2 + 1] = f32::NAN;
+        let mut normals = [0.0, 1.0, 0.0].repeat(6);
+        for &vertex in [1, 5].iter() {
+            normals[3 * vertex..3 * vertex + 3].copy_from_slice(&[f32::NAN; 3]);
+        }
+        let indices = vec![0, 3, 4, 0, 4, 1, 1, 4, 5, 1, 5, 2];
+
+        let obj = write_obj(&positions, Some(&normals), &indices);
+        assert!(!obj.contains("NaN"), "{}", obj);
+        let vns: Vec<&str> = obj.lines().filter(|line| line.starts_with("vn ")).collect();
+        assert_eq!(vns, ["vn 0 1 0"; 5]);
+        assert!(obj.ends_with("f 1//1 3//3 4//4\nf 1//1 4//4 2//2\nf 2//2 4//4 5//5\n"));
+    }
+
+    #[test]
+    fn stl_has_a_header_count_and_facets() {
+        let (positions, _, indices) = square();
+        let stl = write_stl(&positions, &indices);
+        assert_eq!(stl.len(), 80 + 4 + 2 * 50);
+        assert!(!stl.starts_with(b"solid"));
+        assert_eq!(u32::from_le_bytes([stl[80], stl[81], stl[82], stl[83]]), 2);
+
+        // The first facet faces up, with its corners after the normal
+        let facet = 84;
+        let normal: Vec<f32> = (0..3).map(|i| read_f32(&stl, facet + 4 * i)).collect();
+        assert_eq!(normal, [0.0, 1.0, 0.0]);
+        let second_corner: Vec<f32> = (0..3).map(|i| read_f32(&stl, facet + 24 + 4 * i)).collect();
+        assert_eq!(second_corner, [0.0, 0.0, 1.0]);
+        assert_eq!(&stl[facet + 48..facet + 50], &[0, 0]);
+    }
+}
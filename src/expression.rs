@@ -0,0 +1,3 @@
+pub mod ast;
+pub mod compiled;
+pub mod parser;
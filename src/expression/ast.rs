@@ -0,0 +1,265 @@
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum UnaryOp {
+    Negate,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Log10,
+    Log2,
+    Sqrt,
+    Abs,
+    Sign,
+    Floor,
+    Ceil,
+    Round,
+    Min,
+    Max,
+    Pow,
+    Hypot,
+}
+
+impl Function {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let function = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "atan2" => Function::Atan2,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "ln" | "log" => Function::Ln,
+            "log10" => Function::Log10,
+            "log2" => Function::Log2,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "sign" => Function::Sign,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "round" => Function::Round,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "pow" => Function::Pow,
+            "hypot" => Function::Hypot,
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Function::Atan2 | Function::Min | Function::Max | Function::Pow | Function::Hypot => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn apply(self, args: &[f64]) -> f64 {
+        let a = args[0];
+        match self {
+            Function::Sin => a.sin(),
+            Function::Cos => a.cos(),
+            Function::Tan => a.tan(),
+            Function::Asin => a.asin(),
+            Function::Acos => a.acos(),
+            Function::Atan => a.atan(),
+            Function::Atan2 => a.atan2(args[1]),
+            Function::Sinh => a.sinh(),
+            Function::Cosh => a.cosh(),
+            Function::Tanh => a.tanh(),
+            Function::Exp => a.exp(),
+            Function::Ln => a.ln(),
+            Function::Log10 => a.log10(),
+            Function::Log2 => a.log2(),
+            Function::Sqrt => a.sqrt(),
+            Function::Abs => a.abs(),
+            Function::Sign => {
+                if a == 0.0 {
+                    0.0
+                } else {
+                    a.signum()
+                }
+            }
+            Function::Floor => a.floor(),
+            Function::Ceil => a.ceil(),
+            Function::Round => a.round(),
+            Function::Min => a.min(args[1]),
+            Function::Max => a.max(args[1]),
+            Function::Pow => a.powf(args[1]),
+            Function::Hypot => a.hypot(args[1]),
+        }
+    }
+}
+
+pub fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(std::f64::consts::PI),
+        "tau" => Some(2.0 * std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
+
+impl Function {
+    // GLSL for a call to this function. Ones GLSL ES 1.0 lacks, or defines
+    // differently at the edges, are helpers from the graph3d GPU shader.
+    fn glsl(self, args: &[String]) -> String {
+        let name = match self {
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Asin => "asin",
+            Function::Acos => "acos",
+            Function::Atan | Function::Atan2 => "atan",
+            Function::Sinh => "hSinh",
+            Function::Cosh => "hCosh",
+            Function::Tanh => "hTanh",
+            Function::Exp => "exp",
+            Function::Ln => "log",
+            Function::Log10 => "hLog10",
+            Function::Log2 => "log2",
+            Function::Sqrt => "sqrt",
+            Function::Abs => "abs",
+            Function::Sign => "sign",
+            Function::Floor => "floor",
+            Function::Ceil => "ceil",
+            Function::Round => "hRound",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Pow => "hPow",
+            Function::Hypot => "hHypot",
+        };
+        format!("{}({})", name, args.join(", "))
+    }
+}
+
+// A float literal GLSL will accept, which always needs a point or exponent
+pub fn glsl_float(value: f64) -> Result<String, String> {
+    // Checked as the f32 it's written as, since values past f32's range
+    // overflow to infinity
+    if !(value as f32).is_finite() {
+        return Err(format!("{} can't be written as a GLSL float", value));
+    }
+    let mut literal = format!("{:?}", value as f32);
+    if !literal.contains('.') && !literal.contains('e') {
+        literal.push_str(".0");
+    }
+    Ok(if value.is_sign_negative() { format!("({})", literal) } else { literal })
+}
+
+impl BinaryOp {
+    pub fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            BinaryOp::Add => a + b,
+            BinaryOp::Subtract => a - b,
+            BinaryOp::Multiply => a * b,
+            BinaryOp::Divide => a / b,
+            BinaryOp::Remainder => a % b,
+            BinaryOp::Power => a.powf(b),
+        }
+    }
+}
+
+// Variables are resolved to slots at compile time; slot indices are owned by
+// the compiled Expression
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node {
+    Number(f64),
+    Variable(usize),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+impl Node {
+    pub fn evaluate(&self, slots: &[f64]) -> f64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Variable(slot) => slots[*slot],
+            Node::Unary(UnaryOp::Negate, operand) => -operand.evaluate(slots),
+            Node::Binary(op, a, b) => op.apply(a.evaluate(slots), b.evaluate(slots)),
+            Node::Call(function, args) => {
+                let mut values = [0.0; 2];
+                for (value, arg) in values.iter_mut().zip(args.iter()) {
+                    *value = arg.evaluate(slots);
+                }
+                function.apply(&values[..args.len()])
+            }
+        }
+    }
+
+    // The same expression in GLSL, with `variable` naming each slot. Every
+    // operation is bracketed rather than relying on GLSL's precedence.
+    pub fn to_glsl(&self, variable: &dyn Fn(usize) -> String) -> Result<String, String> {
+        Ok(match self {
+            Node::Number(value) => glsl_float(*value)?,
+            Node::Variable(slot) => variable(*slot),
+            Node::Unary(UnaryOp::Negate, operand) => format!("(-{})", operand.to_glsl(variable)?),
+            Node::Binary(op, a, b) => {
+                let (a, b) = (a.to_glsl(variable)?, b.to_glsl(variable)?);
+                match op {
+                    BinaryOp::Add => format!("({} + {})", a, b),
+                    BinaryOp::Subtract => format!("({} - {})", a, b),
+                    BinaryOp::Multiply => format!("({} * {})", a, b),
+                    BinaryOp::Divide => format!("({} / {})", a, b),
+                    BinaryOp::Remainder => format!("hRem({}, {})", a, b),
+                    BinaryOp::Power => format!("hPow({}, {})", a, b),
+                }
+            }
+            Node::Call(function, args) => {
+                let args = args.iter().map(|arg| arg.to_glsl(variable)).collect::<Result<Vec<String>, String>>()?;
+                function.glsl(&args)
+            }
+        })
+    }
+
+    // Collapses every subtree that doesn't depend on a variable
+    pub fn fold_constants(self) -> Node {
+        match self {
+            Node::Unary(op, operand) => match operand.fold_constants() {
+                Node::Number(value) => Node::Number(-value),
+                operand => Node::Unary(op, Box::new(operand)),
+            },
+            Node::Binary(op, a, b) => match (a.fold_constants(), b.fold_constants()) {
+                (Node::Number(a), Node::Number(b)) => Node::Number(op.apply(a, b)),
+                (a, b) => Node::Binary(op, Box::new(a), Box::new(b)),
+            },
+            Node::Call(function, args) => {
+                let args: Vec<Node> = args.into_iter().map(Node::fold_constants).collect();
+                if args.iter().all(|arg| matches!(arg, Node::Number(_))) {
+                    let values: Vec<f64> = args.iter().map(|arg| arg.evaluate(&[])).collect();
+                    Node::Number(function.apply(&values))
+                } else {
+                    Node::Call(function, args)
+                }
+            }
+            node => node,
+        }
+    }
+}
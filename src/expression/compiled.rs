@@ -0,0 +1,251 @@
+use super::ast::Node;
+use super::parser::{parse, ParseError};
+use crate::height_function::HeightFunction;
+use crate::parametric_function::ParametricFunction;
+
+// Slots 0-2 are always the three inputs, x, z and t for surface equations;
+// parameters follow in order of first use
+const PARAMETER_SLOT: usize = 3;
+
+// Size of the parameter array in the graph3d GPU shader
+pub const MAX_GPU_PARAMETERS: usize = 16;
+
+// A parsed surface equation such as `a * sin(x * pi + t) * cos(z * pi)`.
+// Any name that isn't x, z, t, a function or a constant is a parameter,
+// which starts at zero and can be changed without reparsing.
+pub struct Expression {
+    root: Node,
+    parameter_names: Vec<String>,
+    slots: Vec<f64>,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        Self::parse_with_inputs(source, ["x", "z", "t"])
+    }
+
+    // Like `parse`, but with other names for the three inputs
+    pub fn parse_with_inputs(source: &str, inputs: [&str; 3]) -> Result<Self, ParseError> {
+        let mut parameter_names: Vec<String> = Vec::new();
+        let root = parse(source, &mut |name| match inputs.iter().position(|&input| input == name) {
+            Some(slot) => slot,
+            None => match parameter_names.iter().position(|p| p == name) {
+                Some(index) => PARAMETER_SLOT + index,
+                None => {
+                    parameter_names.push(name.to_string());
+                    PARAMETER_SLOT + parameter_names.len() - 1
+                }
+            },
+        })?;
+
+        let slots = vec![0.0; PARAMETER_SLOT + parameter_names.len()];
+        Ok(Self {
+            root: root.fold_constants(),
+            parameter_names,
+            slots,
+        })
+    }
+
+    pub fn parameter_names(&self) -> &[String] {
+        &self.parameter_names
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: f64) -> Result<(), String> {
+        let index = self
+            .parameter_names
+            .iter()
+            .position(|p| p == name)
+            .ok_or_else(|| format!("Expression has no parameter '{}'", name))?;
+        self.slots[PARAMETER_SLOT + index] = value;
+        Ok(())
+    }
+
+    // The expression in GLSL, reading x, z and t as named and parameter i from
+    // uParameters[i]
+    pub fn to_glsl(&self) -> Result<String, String> {
+        if self.parameter_names.len() > MAX_GPU_PARAMETERS {
+            return Err(format!("Expressions on the GPU can have at most {} parameters", MAX_GPU_PARAMETERS));
+        }
+        self.root.to_glsl(&|slot| match slot {
+            0 => String::from("x"),
+            1 => String::from("z"),
+            2 => String::from("t"),
+            _ => format!("uParameters[{}]", slot - PARAMETER_SLOT),
+        })
+    }
+
+    pub fn evaluate(&self, x: f64, z: f64, t: f64) -> f64 {
+        // Parameters are read from `slots`; only the first three are per call
+        let mut slots = [0.0; 8];
+        if self.slots.len() <= slots.len() {
+            slots[..self.slots.len()].copy_from_slice(&self.slots);
+            slots[..PARAMETER_SLOT].copy_from_slice(&[x, z, t]);
+            self.root.evaluate(&slots)
+        } else {
+            let mut slots = self.slots.clone();
+            slots[..PARAMETER_SLOT].copy_from_slice(&[x, z, t]);
+            self.root.evaluate(&slots)
+        }
+    }
+}
+
+// Heights that come out as NaN or infinite, e.g. from sqrt of a negative or a
+// division by zero, are drawn as zero
+impl HeightFunction for Expression {
+    fn height(&self, x: f32, z: f32, t: f32) -> f32 {
+        let height = self.evaluate(x as f64, z as f64, t as f64);
+        if height.is_finite() {
+            height as f32
+        } else {
+            0.0
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), String> {
+        Expression::set_parameter(self, name, value as f64)
+    }
+
+    fn glsl(&self) -> Result<String, String> {
+        self.to_glsl()
+    }
+
+    fn glsl_parameters(&self, parameters: &mut [f32]) {
+        for (parameter, value) in parameters.iter_mut().zip(self.slots[PARAMETER_SLOT..].iter()) {
+            *parameter = *value as f32;
+        }
+    }
+}
+
+// The x, y and z of a parametric surface as three equations in u, v and t.
+// Parameters are shared by name between them.
+pub struct ParametricExpression {
+    components: [Expression; 3],
+}
+
+impl ParametricExpression {
+    pub fn parse(x: &str, y: &str, z: &str) -> Result<Self, ParseError> {
+        let component = |source: &str, axis: &str| {
+            Expression::parse_with_inputs(source, ["u", "v", "t"]).map_err(|error| ParseError {
+                position: error.position,
+                message: format!("{} in {}", error.message, axis),
+            })
+        };
+        Ok(Self {
+            components: [component(x, "x")?, component(y, "y")?, component(z, "z")?],
+        })
+    }
+
+    pub fn parameter_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for name in self.components.iter().flat_map(|component| component.parameter_names()) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+}
+
+// Coordinates that aren't finite are drawn as zero, as for heights
+impl ParametricFunction for ParametricExpression {
+    fn point(&self, u: f32, v: f32, t: f32) -> [f32; 3] {
+        let mut point = [0.0; 3];
+        for (coordinate, component) in point.iter_mut().zip(self.components.iter()) {
+            let value = component.evaluate(u as f64, v as f64, t as f64);
+            if value.is_finite() {
+                *coordinate = value as f32;
+            }
+        }
+        point
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), String> {
+        let mut found = false;
+        for component in self.components.iter_mut().filter(|component| component.parameter_names().iter().any(|p| p == name)) {
+            component.set_parameter(name, value as f64)?;
+            found = true;
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(format!("Expression has no parameter '{}'", name))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::parser::parse;
+
+    const SAMPLES: [(f64, f64, f64); 4] = [(0.0, 0.0, 0.0), (0.3, -0.7, 1.5), (-1.0, 1.0, 2.0), (0.9, 0.1, -3.0)];
+
+    // The same source parsed directly, without folding, with parameters in
+    // slots from 3 in order of first use
+    fn direct(source: &str) -> Node {
+        let mut names: Vec<String> = Vec::new();
+        parse(source, &mut |name| match ["x", "z", "t"].iter().position(|&input| input == name) {
+            Some(slot) => slot,
+            None => {
+                PARAMETER_SLOT
+                    + names.iter().position(|p| p == name).unwrap_or_else(|| {
+                        names.push(name.to_string());
+                        names.len() - 1
+                    })
+            }
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn compiled_expressions_match_direct_evaluation() {
+        let source = "a * sin(x * pi + t) * cos(z * pi) + 2^3 / (1 + b) - -x^2 % 0.3";
+        let mut expression = Expression::parse(source).unwrap();
+        assert_eq!(expression.parameter_names(), ["a", "b"]);
+        expression.set_parameter("a", 1.5).unwrap();
+        expression.set_parameter("b", 3.0).unwrap();
+        let node = direct(source);
+
+        for &(x, z, t) in SAMPLES.iter() {
+            let compiled = expression.evaluate(x, z, t);
+            assert!((compiled - node.evaluate(&[x, z, t, 1.5, 3.0])).abs() < 1e-12);
+            let by_hand = 1.5 * (x * std::f64::consts::PI + t).sin() * (z * std::f64::consts::PI).cos() + 8.0 / 4.0 + (x * x) % 0.3;
+            assert!((compiled - by_hand).abs() < 1e-12, "{} vs {}", compiled, by_hand);
+        }
+    }
+
+    #[test]
+    fn many_parameters_evaluate_the_same() {
+        // More slots than fit in evaluate's stack array. e is a constant, so
+        // it isn't among them.
+        let source = "a + 2*b + 3*c + 4*d + 5*f + 6*g + x*z*t";
+        let mut expression = Expression::parse(source).unwrap();
+        for (i, name) in ["a", "b", "c", "d", "f", "g"].iter().enumerate() {
+            expression.set_parameter(name, i as f64 + 1.0).unwrap();
+        }
+        let node = direct(source);
+        for &(x, z, t) in SAMPLES.iter() {
+            assert_eq!(expression.evaluate(x, z, t), node.evaluate(&[x, z, t, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        }
+        assert_eq!(expression.evaluate(1.0, 1.0, 1.0), 1.0 + 4.0 + 9.0 + 16.0 + 25.0 + 36.0 + 1.0);
+    }
+
+    #[test]
+    fn unknown_parameters_are_an_error() {
+        let mut expression = Expression::parse("a * x").unwrap();
+        assert!(expression.set_parameter("b", 1.0).is_err());
+        // Parameters start at zero
+        assert_eq!(expression.evaluate(2.0, 0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn glsl_constants_must_fit_in_a_float() {
+        let glsl = Expression::parse("1e38 * x").unwrap().to_glsl().unwrap();
+        assert!(glsl.contains("1e38"), "{}", glsl);
+        // Finite as a double but infinite as the float GLSL would read
+        for source in ["1e39 * x", "1e20 * 1e20 * x", "-1e39 + x"].iter() {
+            let error = Expression::parse(source).unwrap().to_glsl().unwrap_err();
+            assert!(error.contains("GLSL float"), "{}: {}", source, error);
+        }
+    }
+}
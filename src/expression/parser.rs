@@ -0,0 +1,436 @@
+use super::ast::*;
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+// How deeply brackets, signs, powers and operator chains may nest. Parsing,
+// evaluating and dropping all recurse through the tree, and running out of
+// stack can't be recovered from in wasm.
+const MAX_DEPTH: usize = 100;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    // Character offset into the source
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl From<ParseError> for JsValue {
+    fn from(error: ParseError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_sys::Reflect::set(&js_error, &JsValue::from_str("position"), &JsValue::from_f64(error.position as f64)).unwrap();
+        js_error.into()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Operator(char),
+    LeftParen,
+    RightParen,
+    Comma,
+    End,
+}
+
+// Parses `source`, handing every identifier that isn't a function or constant
+// to `resolve_variable` to turn into a slot
+pub fn parse(source: &str, resolve_variable: &mut dyn FnMut(&str) -> usize) -> Result<Node, ParseError> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        depth: 0,
+        resolve_variable,
+    };
+    let node = parser.parse_expression()?;
+    match parser.peek() {
+        Token::End => Ok(node),
+        token => Err(parser.error(format!("Unexpected {}", describe(token)))),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // An exponent only counts when digits follow it, so `2e` is read as 2 then e
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text.parse::<f64>().map_err(|_| ParseError {
+                position: start,
+                message: format!("Invalid number '{}'", text),
+            })?;
+            tokens.push((Token::Number(value), start));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Identifier(chars[start..i].iter().collect()), start));
+        } else {
+            let token = match c {
+                '+' | '-' | '*' | '/' | '%' | '^' => Token::Operator(c),
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                ',' => Token::Comma,
+                _ => {
+                    return Err(ParseError {
+                        position: start,
+                        message: format!("Unexpected character '{}'", c),
+                    })
+                }
+            };
+            i += 1;
+            tokens.push((token, start));
+        }
+    }
+
+    tokens.push((Token::End, chars.len()));
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(value) => format!("number {}", value),
+        Token::Identifier(name) => format!("'{}'", name),
+        Token::Operator(c) => format!("'{}'", c),
+        Token::LeftParen => String::from("'('"),
+        Token::RightParen => String::from("')'"),
+        Token::Comma => String::from("','"),
+        Token::End => String::from("end of expression"),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    depth: usize,
+    resolve_variable: &'a mut dyn FnMut(&str) -> usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.index].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if token != Token::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            position: self.position(),
+            message,
+        }
+    }
+
+    fn descend(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(String::from("Expression is nested too deeply")));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if *self.peek() == expected {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected {} but found {}", describe(&expected), describe(self.peek()))))
+        }
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn parse_expression(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut node = self.parse_term()?;
+        loop {
+            let op = match self.peek() {
+                Token::Operator('+') => BinaryOp::Add,
+                Token::Operator('-') => BinaryOp::Subtract,
+                _ => break,
+            };
+            // Each operator nests the chain so far a level deeper
+            self.descend()?;
+            self.advance();
+            node = Node::Binary(op, Box::new(node), Box::new(self.parse_term()?));
+        }
+        self.depth = depth;
+        Ok(node)
+    }
+
+    // term := unary (('*' | '/' | '%') unary)*
+    fn parse_term(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut node = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Operator('*') => BinaryOp::Multiply,
+                Token::Operator('/') => BinaryOp::Divide,
+                Token::Operator('%') => BinaryOp::Remainder,
+                _ => break,
+            };
+            self.descend()?;
+            self.advance();
+            node = Node::Binary(op, Box::new(node), Box::new(self.parse_unary()?));
+        }
+        self.depth = depth;
+        Ok(node)
+    }
+
+    // unary := ('-' | '+') unary | power. Every other rule recurses through
+    // here, so this is where nesting is counted.
+    fn parse_unary(&mut self) -> Result<Node, ParseError> {
+        self.descend()?;
+        let node = match self.peek() {
+            Token::Operator('-') => {
+                self.advance();
+                self.parse_unary().map(|operand| Node::Unary(UnaryOp::Negate, Box::new(operand)))
+            }
+            Token::Operator('+') => {
+                self.advance();
+                self.parse_unary()
+            }
+            _ => self.parse_power(),
+        };
+        self.depth -= 1;
+        node
+    }
+
+    // power := primary ('^' unary)?, right associative so -x^2 is -(x^2) and
+    // 2^3^2 is 2^(3^2)
+    fn parse_power(&mut self) -> Result<Node, ParseError> {
+        let base = self.parse_primary()?;
+        if *self.peek() == Token::Operator('^') {
+            self.advance();
+            let exponent = self.parse_unary()?;
+            Ok(Node::Binary(BinaryOp::Power, Box::new(base), Box::new(exponent)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    // primary := number | identifier | identifier '(' arguments ')' | '(' expression ')'
+    fn parse_primary(&mut self) -> Result<Node, ParseError> {
+        let position = self.position();
+        match self.advance() {
+            Token::Number(value) => Ok(Node::Number(value)),
+            Token::LeftParen => {
+                let node = self.parse_expression()?;
+                self.expect(Token::RightParen)?;
+                Ok(node)
+            }
+            Token::Identifier(name) => {
+                if *self.peek() == Token::LeftParen {
+                    return self.parse_call(&name, position);
+                }
+                if let Some(value) = constant(&name) {
+                    return Ok(Node::Number(value));
+                }
+                if Function::from_name(&name).is_some() {
+                    return Err(ParseError {
+                        position,
+                        message: format!("Function '{}' needs arguments", name),
+                    });
+                }
+                Ok(Node::Variable((self.resolve_variable)(&name)))
+            }
+            token => Err(ParseError {
+                position,
+                message: format!("Expected a value but found {}", describe(&token)),
+            }),
+        }
+    }
+
+    fn parse_call(&mut self, name: &str, position: usize) -> Result<Node, ParseError> {
+        let function = Function::from_name(name).ok_or_else(|| ParseError {
+            position,
+            message: format!("Unknown function '{}'", name),
+        })?;
+        self.expect(Token::LeftParen)?;
+
+        let mut args = Vec::new();
+        if *self.peek() != Token::RightParen {
+            args.push(self.parse_expression()?);
+            while *self.peek() == Token::Comma {
+                self.advance();
+                args.push(self.parse_expression()?);
+            }
+        }
+        self.expect(Token::RightParen)?;
+
+        if args.len() != function.arity() {
+            return Err(ParseError {
+                position,
+                message: format!("'{}' takes {} argument(s) but was given {}", name, function.arity(), args.len()),
+            });
+        }
+        Ok(Node::Call(function, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // x, z and t take slots 0-2 and any other name slot 3
+    fn parse_xzt(source: &str) -> Result<Node, ParseError> {
+        parse(source, &mut |name| ["x", "z", "t"].iter().position(|&input| input == name).unwrap_or(3))
+    }
+
+    fn evaluate(source: &str, x: f64) -> f64 {
+        parse_xzt(source).unwrap().evaluate(&[x, 0.0, 0.0, 0.0])
+    }
+
+    fn error(source: &str) -> ParseError {
+        parse_xzt(source).unwrap_err()
+    }
+
+    #[test]
+    fn precedence_follows_maths() {
+        assert_eq!(evaluate("2 + 3 * 4", 0.0), 14.0);
+        assert_eq!(evaluate("2 * 3 + 4", 0.0), 10.0);
+        assert_eq!(evaluate("(2 + 3) * 4", 0.0), 20.0);
+        assert_eq!(evaluate("1 + 7 % 4", 0.0), 4.0);
+        // Powers bind tighter than negation, on either side of the '^'
+        assert_eq!(evaluate("-x^2", 3.0), -9.0);
+        assert_eq!(parse_xzt("-x^2"), parse_xzt("-(x^2)"));
+        assert_eq!(evaluate("2^-1", 0.0), 0.5);
+        assert_eq!(evaluate("2 * -x", 3.0), -6.0);
+        assert_eq!(evaluate("--x", 3.0), 3.0);
+        assert_eq!(evaluate("+x", 3.0), 3.0);
+    }
+
+    #[test]
+    fn associativity() {
+        // Powers group from the right and everything else from the left
+        assert_eq!(evaluate("2^3^2", 0.0), 512.0);
+        assert_eq!(parse_xzt("2^3^2"), parse_xzt("2^(3^2)"));
+        assert_eq!(evaluate("10 - 4 - 3", 0.0), 3.0);
+        assert_eq!(evaluate("64 / 4 / 2", 0.0), 8.0);
+        assert_eq!(evaluate("17 % 10 % 4", 0.0), 3.0);
+    }
+
+    #[test]
+    fn numbers_constants_and_calls() {
+        assert_eq!(evaluate("2e3 + .5", 0.0), 2000.5);
+        assert_eq!(evaluate("1.5E-1", 0.0), 0.15);
+        assert_eq!(evaluate("pi", 0.0), std::f64::consts::PI);
+        assert_eq!(evaluate("max(x, 2) + min(x, 2)", 5.0), 7.0);
+        assert_eq!(evaluate("atan2(0, -1)", 0.0), std::f64::consts::PI);
+        assert_eq!(
+            parse_xzt("t * rate"),
+            Ok(Node::Binary(BinaryOp::Multiply, Box::new(Node::Variable(2)), Box::new(Node::Variable(3))))
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error("1 + * 2").position, 4);
+        assert_eq!(error("1 + * 2").message, "Expected a value but found '*'");
+        assert_eq!(error("1 $ 2").position, 2);
+        // `2e` is 2 followed by e, which can't follow a number
+        assert_eq!(error("2e").position, 1);
+        assert_eq!(error("x y").message, "Unexpected 'y'");
+        assert_eq!(error("1 +").message, "Expected a value but found end of expression");
+        assert_eq!(error("1 +").position, 3);
+    }
+
+    #[test]
+    fn unknown_functions_and_wrong_arity() {
+        let unknown = error("2 * foo(x)");
+        assert_eq!(unknown.position, 4);
+        assert_eq!(unknown.message, "Unknown function 'foo'");
+
+        let too_many = error("1 + sin(x, 2)");
+        assert_eq!(too_many.position, 4);
+        assert_eq!(too_many.message, "'sin' takes 1 argument(s) but was given 2");
+        assert_eq!(error("atan2(x)").position, 0);
+        assert_eq!(error("max()").message, "'max' takes 2 argument(s) but was given 0");
+        assert_eq!(error("x + cos").message, "Function 'cos' needs arguments");
+        assert_eq!(error("x + cos").position, 4);
+    }
+
+    #[test]
+    fn unbalanced_parentheses() {
+        let unclosed = error("(1 + 2");
+        assert_eq!(unclosed.position, 6);
+        assert_eq!(unclosed.message, "Expected ')' but found end of expression");
+        assert_eq!(error("sin(x").position, 5);
+        assert_eq!(error("min(x, (2)").position, 10);
+
+        let extra = error("(1 + 2))");
+        assert_eq!(extra.position, 7);
+        assert_eq!(extra.message, "Unexpected ')'");
+        assert_eq!(error(")").position, 0);
+        assert_eq!(error("()").message, "Expected a value but found ')'");
+    }
+
+    #[test]
+    fn error_messages_include_the_position() {
+        assert_eq!(error("1 $ 2").to_string(), "Unexpected character '$' at position 2");
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error_not_a_crash() {
+        let brackets = |depth: usize| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(evaluate(&brackets(MAX_DEPTH - 1), 2.0), 2.0);
+        assert_eq!(evaluate(&format!("{}x", "--".repeat((MAX_DEPTH - 1) / 2)), 2.0), 2.0);
+
+        let too_deep = [
+            brackets(MAX_DEPTH),
+            brackets(100_000),
+            format!("{}x", "-".repeat(100_000)),
+            format!("{}2", "2^".repeat(100_000)),
+            format!("sin({}x{}", "cos(".repeat(100_000), "))".repeat(50_000)),
+            // Chains of operators nest too, as each takes the one before as
+            // its left operand
+            format!("x{}", "+1".repeat(100_000)),
+            format!("x{}", "*2".repeat(100_000)),
+        ];
+        for source in too_deep.iter() {
+            let error = error(source);
+            assert_eq!(error.message, "Expression is nested too deeply");
+            assert!(error.position < source.len());
+        }
+    }
+}
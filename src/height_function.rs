@@ -0,0 +1,159 @@
+use crate::expression::ast::glsl_float;
+use wasm_bindgen::JsValue;
+
+// Supplies the surface height for a Graph3d. `x` and `z` are positions on the
+// graph and `t` is the time in seconds.
+pub trait HeightFunction {
+    fn height(&self, x: f32, z: f32, t: f32) -> f32;
+
+    // (dh/dx, dh/dz) at the same point, for functions that know it exactly.
+    // Without it normals are estimated from neighbouring heights.
+    fn gradient(&self, _x: f32, _z: f32, _t: f32) -> Option<(f32, f32)> {
+        None
+    }
+
+    // Named inputs such as the user parameters of an Expression
+    fn set_parameter(&mut self, name: &str, _value: f32) -> Result<(), String> {
+        Err(format!("Height function has no parameter '{}'", name))
+    }
+
+    // The height as a GLSL expression in `x`, `z`, `t` and `uParameters[i]`,
+    // so that a Graph3d can evaluate it in its vertex shader
+    fn glsl(&self) -> Result<String, String> {
+        Err(String::from("This height function can only be evaluated on the CPU"))
+    }
+
+    // Current values for the uParameters the GLSL reads
+    fn glsl_parameters(&self, _parameters: &mut [f32]) {}
+}
+
+impl<F: Fn(f32, f32, f32) -> f32> HeightFunction for F {
+    fn height(&self, x: f32, z: f32, t: f32) -> f32 {
+        self(x, z, t)
+    }
+}
+
+// Concentric waves moving out from the origin
+pub struct Ripple {
+    pub amplitude: f32,
+    pub frequency: f32,
+}
+
+impl Default for Ripple {
+    fn default() -> Self {
+        Self {
+            amplitude: 0.15,
+            frequency: 3.0 * std::f32::consts::PI,
+        }
+    }
+}
+
+impl HeightFunction for Ripple {
+    fn height(&self, x: f32, z: f32, t: f32) -> f32 {
+        let scaled_x = self.frequency * x;
+        let scaled_z = self.frequency * z;
+        self.amplitude * ((scaled_x * scaled_x + scaled_z * scaled_z).sqrt() + t).sin()
+    }
+
+    fn gradient(&self, x: f32, z: f32, t: f32) -> Option<(f32, f32)> {
+        let scaled_x = self.frequency * x;
+        let scaled_z = self.frequency * z;
+        let radius = (scaled_x * scaled_x + scaled_z * scaled_z).sqrt();
+        // The peak at the origin has no single slope
+        if radius == 0.0 {
+            return Some((0.0, 0.0));
+        }
+        let slope = self.amplitude * (radius + t).cos() * self.frequency / radius;
+        Some((slope * scaled_x, slope * scaled_z))
+    }
+
+    fn glsl(&self) -> Result<String, String> {
+        let (amplitude, frequency) = (glsl_float(self.amplitude as f64)?, glsl_float(self.frequency as f64)?);
+        Ok(format!("{} * sin(length(vec2(x, z) * {}) + t)", amplitude, frequency))
+    }
+}
+
+// A JS function called as `callback(x, z, t)`. Exceptions and non-numeric
+// results are drawn as zero height. The optional `gradient(x, z, t)` returns
+// [dh/dx, dh/dz].
+pub struct JsHeightFunction {
+    callback: js_sys::Function,
+    gradient: Option<js_sys::Function>,
+}
+
+impl JsHeightFunction {
+    pub fn new(callback: js_sys::Function, gradient: Option<js_sys::Function>) -> Self {
+        Self { callback, gradient }
+    }
+}
+
+impl HeightFunction for JsHeightFunction {
+    fn height(&self, x: f32, z: f32, t: f32) -> f32 {
+        self.callback
+            .call3(&JsValue::NULL, &JsValue::from_f64(x as f64), &JsValue::from_f64(z as f64), &JsValue::from_f64(t as f64))
+            .ok()
+            .and_then(|value| value.as_f64())
+            .filter(|value| value.is_finite())
+            .unwrap_or(0.0) as f32
+    }
+
+    fn gradient(&self, x: f32, z: f32, t: f32) -> Option<(f32, f32)> {
+        let gradient = self.gradient.as_ref()?;
+        let slopes = gradient
+            .call3(&JsValue::NULL, &JsValue::from_f64(x as f64), &JsValue::from_f64(z as f64), &JsValue::from_f64(t as f64))
+            .unwrap_or(JsValue::UNDEFINED);
+        let slope = |index: u32| {
+            js_sys::Reflect::get_u32(&slopes, index)
+                .ok()
+                .and_then(|value| value.as_f64())
+                .filter(|value| value.is_finite())
+                .unwrap_or(0.0) as f32
+        };
+        Some((slope(0), slope(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Central differences of the height, which the exact gradient should match
+    fn estimated_gradient(function: &dyn HeightFunction, x: f32, z: f32, t: f32) -> (f32, f32) {
+        let h = 1e-3;
+        (
+            (function.height(x + h, z, t) - function.height(x - h, z, t)) / (2.0 * h),
+            (function.height(x, z + h, t) - function.height(x, z - h, t)) / (2.0 * h),
+        )
+    }
+
+    #[test]
+    fn ripple_gradient_matches_its_heights() {
+        let ripple = Ripple::default();
+        for &(x, z, t) in [(0.3, 0.0, 0.0), (0.0, -0.7, 1.0), (0.25, 0.4, 2.5), (-0.9, 0.6, 4.0), (0.05, -0.02, 0.5)].iter() {
+            let (dx, dz) = ripple.gradient(x, z, t).unwrap();
+            let (estimated_dx, estimated_dz) = estimated_gradient(&ripple, x, z, t);
+            assert!(
+                (dx - estimated_dx).abs() < 5e-3 && (dz - estimated_dz).abs() < 5e-3,
+                "({}, {}) vs ({}, {}) at {}, {}",
+                dx,
+                dz,
+                estimated_dx,
+                estimated_dz,
+                x,
+                z
+            );
+        }
+    }
+
+    #[test]
+    fn ripple_is_flat_at_its_peak() {
+        // The slopes either side of the origin cancel, as they do for the
+        // estimate from neighbouring heights
+        let ripple = Ripple { amplitude: 0.5, frequency: 2.0 };
+        for &t in [0.0, 1.0, 3.0].iter() {
+            assert_eq!(ripple.gradient(0.0, 0.0, t), Some((0.0, 0.0)));
+            let (estimated_dx, estimated_dz) = estimated_gradient(&ripple, 0.0, 0.0, t);
+            assert!(estimated_dx.abs() < 1e-5 && estimated_dz.abs() < 1e-5);
+        }
+    }
+}
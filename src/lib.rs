@@ -2,15 +2,50 @@
 extern crate lazy_static;
 
 mod app_state;
+mod colourmap;
+mod contour;
 mod entity;
+mod export;
+mod expression;
+mod height_function;
+mod loader;
+mod marching_cubes;
+mod parametric_function;
+mod picking;
 mod shader;
+mod text_layout;
 
 use app_state::*;
+use colourmap::{Colourmap, ColourmapPreset};
+use entity::axes::Axes;
+use entity::colourbar::{Colourbar, Corner};
+use entity::contours::{ContourLevels, ContourOverlay};
 use entity::entity::Entity;
-use entity::graph3d::Graph3d;
+use entity::graph3d::{Graph3d, RenderMode};
+use entity::instanced_mesh::InstancedMesh;
+use entity::isosurface::Isosurface;
+use entity::line_plot::LinePlot;
+use entity::parametric_surface::ParametricSurface;
+use entity::point_cloud::PointCloud;
 use entity::quad::Quad;
+use entity::quiver::Quiver;
+use entity::scatter::{PointShape, Scatter3d};
+use entity::scene::Scene;
+use entity::text::{Text, TextLabel, TextSpace};
+use export::{write_obj, write_stl};
+use expression::compiled::{Expression, ParametricExpression};
+use height_function::{JsHeightFunction, Ripple};
+use loader::font::Font;
+use loader::gltf::Gltf;
+use loader::heightmap::{load_png_heightmap, HeightmapOptions};
+use loader::image::decode_png_rgba;
+use loader::ply::parse_ply;
+use loader::xyz::parse_xyz;
+use marching_cubes::sample_grid;
 use nalgebra_glm as glm;
+use parametric_function::{JsParametricFunction, ParametricFunction};
 use shader::shader_controller::{ShaderController, ShaderType};
+use text_layout::{TextAlign, TextLayoutOptions, VerticalAlign};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
@@ -52,8 +87,8 @@ impl WebGlClient {
                 Box::new(Quad::new(&gl, ShaderType::BasicShader)),
                 Box::new(Graph3d::new(&gl, ShaderType::Graph3dShader, 100)),
             ],
-            gl: gl,
-            shader_controller: shader_controller,
+            gl,
+            shader_controller,
         }
     }
 
@@ -65,20 +100,791 @@ impl WebGlClient {
         Ok(())
     }
 
-    pub fn render(&mut self) {
+    pub fn render(&self) {
         self.gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
 
-        let position = glm::vec3(0.0, 0.0, 0.0);
-        let rotation = glm::vec3(0.0, 0.0, 0.0);
-        let scale = glm::vec3(1.0, 1.0, 1.0);
+        let (position, rotation, scale) = Self::scene_transform();
 
         for e in self.entities.iter() {
-            e.render(&self.gl, &mut self.shader_controller, &position, &rotation, &scale);
+            e.render(&self.gl, &self.shader_controller, &position, &rotation, &scale);
         }
     }
+
+    // Loads a .glb or .gltf file as a new scene entity and returns its index.
+    // `resources` maps any external URIs in the file to their bytes.
+    pub fn load_gltf(&mut self, data: &[u8], resources: &js_sys::Object) -> Result<usize, JsValue> {
+        let resolve_uri = |uri: &str| -> Result<Vec<u8>, String> {
+            let resource = js_sys::Reflect::get(resources, &JsValue::from_str(uri)).map_err(|_| format!("Unable to read glTF resource '{}'", uri))?;
+            if resource.is_undefined() {
+                return Err(format!("Missing glTF resource '{}'", uri));
+            }
+            Ok(js_sys::Uint8Array::new(&resource).to_vec())
+        };
+
+        let gltf = Gltf::parse(data, &resolve_uri)?;
+        let scene = Scene::new(&self.gl, ShaderType::MeshShader, &gltf)?;
+        self.entities.push(Box::new(scene));
+        Ok(self.entities.len() - 1)
+    }
+
+    pub fn load_ply(&mut self, data: &[u8]) -> Result<usize, JsValue> {
+        let points = parse_ply(data)?;
+        let point_cloud = PointCloud::new(&self.gl, ShaderType::PointShader, &points)?;
+        self.entities.push(Box::new(point_cloud));
+        Ok(self.entities.len() - 1)
+    }
+
+    pub fn load_xyz(&mut self, text: &str) -> Result<usize, JsValue> {
+        let points = parse_xyz(text)?;
+        let point_cloud = PointCloud::new(&self.gl, ShaderType::PointShader, &points)?;
+        self.entities.push(Box::new(point_cloud));
+        Ok(self.entities.len() - 1)
+    }
+
+    pub fn set_point_size(&mut self, id: usize, point_size: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<PointCloud>(&mut self.entities, id)?.set_point_size(point_size);
+        Ok(())
+    }
+
+    pub fn set_point_size_attenuation(&mut self, id: usize, enabled: bool) -> Result<(), JsValue> {
+        Self::get_entity_mut::<PointCloud>(&mut self.entities, id)?.set_size_attenuation(enabled);
+        Ok(())
+    }
+
+    // Adds a 3D scatter plot of x, y, z triples and returns its index
+    pub fn add_scatter3d(&mut self, positions: &[f32]) -> Result<usize, JsValue> {
+        let scatter = Scatter3d::new(&self.gl, ShaderType::ScatterShader, positions)?;
+        self.entities.push(Box::new(scatter));
+        Ok(self.entities.len() - 1)
+    }
+
+    // Changing the number of points drops the other per-point columns
+    pub fn set_scatter3d_positions(&mut self, id: usize, positions: &[f32]) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_positions(&self.gl, positions)?;
+        Ok(())
+    }
+
+    // One diameter in pixels per point, or undefined to use the default size
+    pub fn set_scatter3d_sizes(&mut self, id: usize, sizes: Option<Vec<f32>>) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_sizes(&self.gl, sizes.as_deref())?;
+        Ok(())
+    }
+
+    // RGBA per point, or undefined to use the default colour
+    pub fn set_scatter3d_colours(&mut self, id: usize, colours: Option<Vec<f32>>) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_colours(&self.gl, colours.as_deref())?;
+        Ok(())
+    }
+
+    // One PointShape per point, or undefined to use the default shape
+    pub fn set_scatter3d_shapes(&mut self, id: usize, shapes: Option<Vec<u8>>) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_shapes(&self.gl, shapes.as_deref())?;
+        Ok(())
+    }
+
+    // Colours points through the colourmap by one scalar each, keeping the
+    // alpha of their colours. Undefined goes back to plain colours.
+    pub fn set_scatter3d_scalars(&mut self, id: usize, scalars: Option<Vec<f32>>) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_scalars(&self.gl, scalars.as_deref())?;
+        Ok(())
+    }
+
+    // Used for points without a size, colour or shape of their own
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_scatter3d_defaults(&mut self, id: usize, size: f32, r: f32, g: f32, b: f32, a: f32, shape: PointShape) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_defaults(&self.gl, size, [r, g, b, a], shape);
+        Ok(())
+    }
+
+    pub fn set_scatter3d_size_attenuation(&mut self, id: usize, enabled: bool) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_size_attenuation(enabled);
+        Ok(())
+    }
+
+    pub fn set_scatter3d_colourmap(&mut self, id: usize, preset: ColourmapPreset) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_colourmap(&self.gl, &Colourmap::preset(preset))?;
+        Ok(())
+    }
+
+    pub fn set_scatter3d_colour_range(&mut self, id: usize, min: f32, max: f32) -> Result<(), JsValue> {
+        if !min.is_finite() || !max.is_finite() || min == max {
+            return Err(JsValue::from("Colour range needs two different finite values"));
+        }
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_colour_range(Some((min, max)));
+        Ok(())
+    }
+
+    // Goes back to fitting the colour range to the scalars
+    pub fn clear_scatter3d_colour_range(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Scatter3d>(&mut self.entities, id)?.set_colour_range(None);
+        Ok(())
+    }
+
+    pub fn set_graph3d_render_mode(&mut self, id: usize, render_mode: RenderMode) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_render_mode(render_mode);
+        Ok(())
+    }
+
+    pub fn set_graph3d_grid_lines(&mut self, id: usize, grid_lines: f32, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        let graph = Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?;
+        graph.set_grid_lines(grid_lines);
+        graph.set_grid_line_colour([r, g, b, a]);
+        Ok(())
+    }
+
+    // `callback(x, z, t)` returns the height at graph position (x, z) at time t
+    // seconds. The optional `gradient(x, z, t)` returns [dh/dx, dh/dz] there,
+    // which lights the surface exactly instead of estimating its slope.
+    pub fn set_graph3d_height_function(&mut self, id: usize, callback: js_sys::Function, gradient: Option<js_sys::Function>) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_height_function(&self.gl, Box::new(JsHeightFunction::new(callback, gradient)));
+        Ok(())
+    }
+
+    pub fn set_graph3d_ripple(&mut self, id: usize, amplitude: f32, frequency: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_height_function(&self.gl, Box::new(Ripple { amplitude, frequency }));
+        Ok(())
+    }
+
+    // Parse errors are thrown as an Error with a `position` property holding the
+    // character offset of the problem. Returns the names of the expression's
+    // parameters, which all start at zero.
+    pub fn set_graph3d_expression(&mut self, id: usize, source: &str) -> Result<js_sys::Array, JsValue> {
+        let expression = Expression::parse(source)?;
+        let parameter_names = expression.parameter_names().iter().map(|name| JsValue::from_str(name)).collect();
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_height_function(&self.gl, Box::new(expression));
+        Ok(parameter_names)
+    }
+
+    pub fn set_graph3d_parameter(&mut self, id: usize, name: &str, value: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.height_function_mut()?.set_parameter(name, value)?;
+        Ok(())
+    }
+
+    // The data point [x, z, height] under canvas pixel (x, y) from the top
+    // left, or undefined if the surface isn't there
+    pub fn pick_graph3d(&mut self, id: usize, x: f32, y: f32) -> Result<Option<Vec<f32>>, JsValue> {
+        let (position, rotation, scale) = Self::scene_transform();
+        let point = Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.pick(&position, &rotation, &scale, x, y);
+        Ok(point.map(|point| point.to_vec()))
+    }
+
+    // `callback(type, point)` is called as the pointer hovers over or clicks the
+    // canvas, with type "hover" or "click" and point [x, z, height], or null
+    // when it's off the surface. Undefined stops the calls.
+    pub fn set_graph3d_pick_callback(&mut self, id: usize, callback: Option<js_sys::Function>) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_pick_callback(callback);
+        Ok(())
+    }
+
+    // Evaluates expressions and ripples in the vertex shader, so large grids
+    // animate without per-frame CPU work. JS callbacks always run on the CPU.
+    // Contour lines aren't drawn while this is on.
+    pub fn set_graph3d_gpu_evaluation(&mut self, id: usize, enabled: bool) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_gpu_evaluation(&self.gl, enabled)?;
+        Ok(())
+    }
+
+    // Shows measured data instead of a height function. `heights` is row-major,
+    // `width` samples along x per row and `depth` rows along z.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_graph3d_data(&mut self, id: usize, width: usize, depth: usize, heights: &[f32], x_min: f32, x_max: f32, z_min: f32, z_max: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_data(&self.gl, width, depth, heights, [x_min, x_max, z_min, z_max])?;
+        Ok(())
+    }
+
+    // Builds the surface from an 8- or 16-bit grayscale PNG, with heights of
+    // `offset + height_scale * level` for levels from 0 (black) to 1 (white).
+    // The image is resampled to `width` x `depth` samples; pass 0 to keep the
+    // image's resolution. x and z span the image's pixel coordinates.
+    pub fn load_graph3d_heightmap(&mut self, id: usize, data: &[u8], height_scale: f32, offset: f32, width: usize, depth: usize) -> Result<(), JsValue> {
+        let options = HeightmapOptions {
+            height_scale,
+            offset,
+            width,
+            depth,
+        };
+        let heightmap = load_png_heightmap(data, &options)?;
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_data(&self.gl, heightmap.width, heightmap.depth, &heightmap.heights, heightmap.extent)?;
+        Ok(())
+    }
+
+    // The x and z range, in data units, that a height function is drawn over.
+    // The longer side fills the display box and the shorter keeps its
+    // proportion.
+    pub fn set_graph3d_domain(&mut self, id: usize, x_min: f32, x_max: f32, z_min: f32, z_max: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_extent([x_min, x_max, z_min, z_max])?;
+        Ok(())
+    }
+
+    // Samples along x and z for a height function; `nx` and `nz` may differ
+    pub fn set_graph3d_resolution(&mut self, id: usize, nx: usize, nz: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_resolution(&self.gl, nx, nz)?;
+        Ok(())
+    }
+
+    // Scales heights in the display box, where 1 draws them at the same scale
+    // as x and z
+    pub fn set_graph3d_vertical_exaggeration(&mut self, id: usize, exaggeration: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_vertical_exaggeration(exaggeration);
+        Ok(())
+    }
+
+    pub fn set_graph3d_colourmap(&mut self, id: usize, preset: ColourmapPreset) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_colourmap(&self.gl, &Colourmap::preset(preset))?;
+        Ok(())
+    }
+
+    // A custom gradient with stops at `positions` between 0 and 1, and an RGB
+    // triple per stop in `colours`
+    pub fn set_graph3d_colour_gradient(&mut self, id: usize, positions: &[f32], colours: &[f32]) -> Result<(), JsValue> {
+        let colourmap = Colourmap::from_stops(positions, colours)?;
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_colourmap(&self.gl, &colourmap)?;
+        Ok(())
+    }
+
+    // Values at or beyond `min` and `max` get the colours at either end
+    pub fn set_graph3d_colour_range(&mut self, id: usize, min: f32, max: f32) -> Result<(), JsValue> {
+        if !min.is_finite() || !max.is_finite() || min == max {
+            return Err(JsValue::from("Colour range needs two different finite values"));
+        }
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_colour_range(Some((min, max)));
+        Ok(())
+    }
+
+    // Goes back to fitting the colour range to the data
+    pub fn clear_graph3d_colour_range(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_colour_range(None);
+        Ok(())
+    }
+
+    // Splits the colourmap into `bands` flat colours, or 0 for a smooth gradient
+    pub fn set_graph3d_colour_bands(&mut self, id: usize, bands: u32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_colour_bands(bands);
+        Ok(())
+    }
+
+    // Colours by one scalar per grid sample, in the same order as the heights,
+    // instead of by height
+    pub fn set_graph3d_scalars(&mut self, id: usize, scalars: &[f32]) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_scalars(&self.gl, scalars)?;
+        Ok(())
+    }
+
+    pub fn clear_graph3d_scalars(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.clear_scalars();
+        Ok(())
+    }
+
+    // Draws iso-lines on the surface at each height in `levels`, and again on
+    // the floor of its box if `project_to_floor` is set
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_graph3d_contour_levels(&mut self, id: usize, levels: &[f32], r: f32, g: f32, b: f32, a: f32, project_to_floor: bool) -> Result<(), JsValue> {
+        let contours = ContourOverlay::new(&self.gl, ShaderType::LineShader, ContourLevels::Explicit(levels.to_vec()), [r, g, b, a], project_to_floor)?;
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_contours(Some(contours));
+        Ok(())
+    }
+
+    // Like `set_graph3d_contour_levels`, with `count` levels spread evenly over
+    // the surface's current height range
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_graph3d_contour_count(&mut self, id: usize, count: usize, r: f32, g: f32, b: f32, a: f32, project_to_floor: bool) -> Result<(), JsValue> {
+        let contours = ContourOverlay::new(&self.gl, ShaderType::LineShader, ContourLevels::Count(count), [r, g, b, a], project_to_floor)?;
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_contours(Some(contours));
+        Ok(())
+    }
+
+    pub fn clear_graph3d_contours(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.set_contours(None);
+        Ok(())
+    }
+
+    // Adds axes that follow the Graph3d with index `graph_id` and returns their index
+    pub fn add_graph3d_axes(&mut self, graph_id: usize) -> Result<usize, JsValue> {
+        let bounds = Self::get_entity_mut::<Graph3d>(&mut self.entities, graph_id)?.bounds();
+        let axes = Axes::new(&self.gl, ShaderType::LineShader, bounds)?;
+        self.entities.push(Box::new(axes));
+        Ok(self.entities.len() - 1)
+    }
+
+    // `y` titles the height axis. Empty titles are left out.
+    pub fn set_axes_titles(&mut self, id: usize, x: &str, y: &str, z: &str) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Axes>(&mut self.entities, id)?.set_titles([x.to_string(), y.to_string(), z.to_string()]);
+        Ok(())
+    }
+
+    // Draws the axes' tick labels and titles at `size` pixels with the font of
+    // the text entity `font_id`, as returned by `load_font`
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_axes_font(&mut self, id: usize, font_id: usize, size: f32, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        let text = Self::get_entity_mut::<Text>(&mut self.entities, font_id)?.share_font(&self.gl)?;
+        Self::get_entity_mut::<Axes>(&mut self.entities, id)?.set_font(text, size, [r, g, b, a])?;
+        Ok(())
+    }
+
+    // Returns `{ text, axis, title, x, y }` for each tick label and title, where
+    // axis is "x", "y" or "z" and x/y are canvas pixels from the top left. For
+    // pages drawing their own labels rather than using `set_axes_font`.
+    pub fn get_axes_labels(&mut self, id: usize) -> Result<js_sys::Array, JsValue> {
+        let (position, rotation, scale) = Self::scene_transform();
+        let labels = Self::get_entity_mut::<Axes>(&mut self.entities, id)?.labels(&position, &rotation, &scale);
+
+        let result = js_sys::Array::new();
+        for label in labels {
+            let object = js_sys::Object::new();
+            js_sys::Reflect::set(&object, &JsValue::from_str("text"), &JsValue::from_str(&label.text))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("axis"), &JsValue::from_str(["x", "y", "z"][label.axis]))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("title"), &JsValue::from_bool(label.is_title))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("x"), &JsValue::from_f64(label.x as f64))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("y"), &JsValue::from_f64(label.y as f64))?;
+            result.push(&object);
+        }
+        Ok(result)
+    }
+
+    // Adds a colourbar showing the colourmap and colour range of the Graph3d
+    // with index `graph_id`, and returns its index
+    pub fn add_graph3d_colourbar(&mut self, graph_id: usize) -> Result<usize, JsValue> {
+        let graph = Self::get_entity_mut::<Graph3d>(&mut self.entities, graph_id)?;
+        let colourbar = Colourbar::new(&self.gl, ShaderType::ColourbarShader, graph.bounds(), graph.colourmap_texture())?;
+        self.entities.push(Box::new(colourbar));
+        Ok(self.entities.len() - 1)
+    }
+
+    pub fn set_colourbar_corner(&mut self, id: usize, corner: Corner) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Colourbar>(&mut self.entities, id)?.set_corner(corner);
+        Ok(())
+    }
+
+    // Pixels between the bar and the canvas edges
+    pub fn set_colourbar_margin(&mut self, id: usize, margin: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Colourbar>(&mut self.entities, id)?.set_margin(margin)?;
+        Ok(())
+    }
+
+    // Size of the gradient in pixels
+    pub fn set_colourbar_size(&mut self, id: usize, width: f32, height: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Colourbar>(&mut self.entities, id)?.set_size(width, height)?;
+        Ok(())
+    }
+
+    // Placed above the bar. An empty title is left out.
+    pub fn set_colourbar_title(&mut self, id: usize, title: &str) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Colourbar>(&mut self.entities, id)?.set_title(title.to_string());
+        Ok(())
+    }
+
+    pub fn set_colourbar_frame_colour(&mut self, id: usize, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Colourbar>(&mut self.entities, id)?.set_frame_colour([r, g, b, a]);
+        Ok(())
+    }
+
+    // Draws the colourbar's labels and title at `size` pixels with the font of
+    // the text entity `font_id`, as returned by `load_font`
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_colourbar_font(&mut self, id: usize, font_id: usize, size: f32, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        let text = Self::get_entity_mut::<Text>(&mut self.entities, font_id)?.share_font(&self.gl)?;
+        Self::get_entity_mut::<Colourbar>(&mut self.entities, id)?.set_font(text, size, [r, g, b, a])?;
+        Ok(())
+    }
+
+    // Returns `{ text, title, x, y, align, verticalAlign }` for the min, max
+    // and tick labels and the title, where x/y are canvas pixels from the top
+    // left and the alignments are TextAlign and VerticalAlign values, ready to
+    // pass to `add_text`. For pages drawing their own labels rather than using
+    // `set_colourbar_font`.
+    pub fn get_colourbar_labels(&mut self, id: usize) -> Result<js_sys::Array, JsValue> {
+        let labels = Self::get_entity_mut::<Colourbar>(&mut self.entities, id)?.labels();
+
+        let result = js_sys::Array::new();
+        for label in labels {
+            let object = js_sys::Object::new();
+            js_sys::Reflect::set(&object, &JsValue::from_str("text"), &JsValue::from_str(&label.text))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("title"), &JsValue::from_bool(label.is_title))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("x"), &JsValue::from_f64(label.x as f64))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("y"), &JsValue::from_f64(label.y as f64))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("align"), &JsValue::from_f64(label.align as i32 as f64))?;
+            js_sys::Reflect::set(&object, &JsValue::from_str("verticalAlign"), &JsValue::from_f64(label.vertical_align as i32 as f64))?;
+            result.push(&object);
+        }
+        Ok(result)
+    }
+
+    // The surface as drawn, as Wavefront OBJ text with vertex normals, for
+    // saving or loading into other tools. Positions are in the graph's model
+    // space, where x and z span [-1, 1] along the longer side of the domain.
+    pub fn export_graph3d_obj(&mut self, id: usize) -> Result<Blob, JsValue> {
+        let (positions, normals, indices) = Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.export_mesh();
+        let obj = write_obj(&positions, Some(&normals), &indices);
+        let options = BlobPropertyBag::new();
+        options.set_type("model/obj");
+        Blob::new_with_str_sequence_and_options(&js_sys::Array::of1(&JsValue::from_str(&obj)), &options)
+    }
+
+    // Like `export_graph3d_obj`, as binary STL for slicers
+    pub fn export_graph3d_stl(&mut self, id: usize) -> Result<Blob, JsValue> {
+        let (positions, _, indices) = Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.export_mesh();
+        let stl = write_stl(&positions, &indices);
+        let options = BlobPropertyBag::new();
+        options.set_type("model/stl");
+        Blob::new_with_u8_array_sequence_and_options(&js_sys::Array::of1(&js_sys::Uint8Array::from(&stl[..])), &options)
+    }
+
+    // Overwrites whole rows of the data set with `set_graph3d_data`
+    pub fn update_graph3d_data(&mut self, id: usize, start_row: usize, heights: &[f32]) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Graph3d>(&mut self.entities, id)?.update_data_rows(&self.gl, start_row, heights)?;
+        Ok(())
+    }
+
+    // Loads a distance field font from its BMFont JSON metrics and PNG atlas,
+    // as a new text entity that labels are added to. Returns its index.
+    pub fn load_font(&mut self, json: &str, atlas_png: &[u8]) -> Result<usize, JsValue> {
+        let font = Font::parse_bmfont_json(json)?;
+        let atlas = decode_png_rgba(atlas_png)?;
+        let text = Text::new(&self.gl, ShaderType::TextShader, font, &atlas)?;
+        self.entities.push(Box::new(text));
+        Ok(self.entities.len() - 1)
+    }
+
+    // Screen text is placed at (x, y) canvas pixels from the top left and
+    // `size` is in pixels. Billboards are placed at (x, y, z) in the scene and
+    // `size` is in scene units. Returns the label's index within the entity.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_text(
+        &mut self,
+        id: usize,
+        text: &str,
+        space: TextSpace,
+        x: f32,
+        y: f32,
+        z: f32,
+        size: f32,
+        align: TextAlign,
+        vertical_align: VerticalAlign,
+        r: f32,
+        g: f32,
+        b: f32,
+        a: f32,
+    ) -> Result<usize, JsValue> {
+        let label = TextLabel {
+            text: text.to_string(),
+            space,
+            position: [x, y, z],
+            colour: [r, g, b, a],
+            options: TextLayoutOptions {
+                size,
+                align,
+                vertical_align,
+                ..Default::default()
+            },
+        };
+        Ok(Self::get_entity_mut::<Text>(&mut self.entities, id)?.add_label(&self.gl, label))
+    }
+
+    pub fn set_text(&mut self, id: usize, label: usize, text: &str) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Text>(&mut self.entities, id)?.set_label_text(&self.gl, label, text)?;
+        Ok(())
+    }
+
+    pub fn set_text_position(&mut self, id: usize, label: usize, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Text>(&mut self.entities, id)?.set_label_position(&self.gl, label, [x, y, z])?;
+        Ok(())
+    }
+
+    pub fn remove_text(&mut self, id: usize, label: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Text>(&mut self.entities, id)?.remove_label(&self.gl, label)?;
+        Ok(())
+    }
+
+    pub fn clear_text(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Text>(&mut self.entities, id)?.clear(&self.gl);
+        Ok(())
+    }
+
+    // Returns [width, height] of `text` at `size`, in the same units as `size`
+    pub fn measure_text(&mut self, id: usize, text: &str, size: f32) -> Result<Vec<f32>, JsValue> {
+        let options = TextLayoutOptions { size, ..Default::default() };
+        let (width, height) = Self::get_entity_mut::<Text>(&mut self.entities, id)?.measure(text, &options);
+        Ok(vec![width, height])
+    }
+
+    // Adds an empty 2D line plot, drawn over the whole canvas until given a
+    // viewport, and returns its index
+    pub fn add_line_plot(&mut self) -> usize {
+        self.entities.push(Box::new(LinePlot::new(ShaderType::LinePlotShader)));
+        self.entities.len() - 1
+    }
+
+    // Adds a series holding up to `capacity` samples, dropping the oldest as new
+    // ones arrive, and returns its index within the plot
+    pub fn add_line_plot_series(&mut self, id: usize, capacity: usize, r: f32, g: f32, b: f32, a: f32) -> Result<usize, JsValue> {
+        Ok(Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.add_series(&self.gl, capacity, [r, g, b, a])?)
+    }
+
+    // `xs` are f64 so that timestamps keep their precision
+    pub fn append_line_plot_samples(&mut self, id: usize, series: usize, xs: &[f64], ys: &[f32]) -> Result<(), JsValue> {
+        Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.append(&self.gl, series, xs, ys)?;
+        Ok(())
+    }
+
+    pub fn clear_line_plot_series(&mut self, id: usize, series: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.clear_series(series)?;
+        Ok(())
+    }
+
+    // Pins the x axis instead of fitting it to the samples
+    pub fn set_line_plot_x_range(&mut self, id: usize, min: f64, max: f64) -> Result<(), JsValue> {
+        if !min.is_finite() || !max.is_finite() || min == max {
+            return Err(JsValue::from("Axis range needs two different finite values"));
+        }
+        Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.set_x_range(Some((min, max)));
+        Ok(())
+    }
+
+    pub fn clear_line_plot_x_range(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.set_x_range(None);
+        Ok(())
+    }
+
+    pub fn set_line_plot_y_range(&mut self, id: usize, min: f32, max: f32) -> Result<(), JsValue> {
+        if !min.is_finite() || !max.is_finite() || min == max {
+            return Err(JsValue::from("Axis range needs two different finite values"));
+        }
+        Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.set_y_range(Some((min, max)));
+        Ok(())
+    }
+
+    pub fn clear_line_plot_y_range(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.set_y_range(None);
+        Ok(())
+    }
+
+    // Draws the plot in a rectangle of the canvas, in pixels from the top left
+    pub fn set_line_plot_viewport(&mut self, id: usize, left: f32, top: f32, width: f32, height: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.set_viewport(Some([left, top, width, height]));
+        Ok(())
+    }
+
+    // The plot's current [x_min, x_max, y_min, y_max], pinned or fitted, for
+    // labelling its axes
+    pub fn get_line_plot_ranges(&mut self, id: usize) -> Result<Vec<f64>, JsValue> {
+        Ok(Self::get_entity_mut::<LinePlot>(&mut self.entities, id)?.ranges().to_vec())
+    }
+
+    // Adds a parametric surface with `u_samples` x `v_samples` samples and
+    // returns its index. It starts as a unit sphere.
+    pub fn add_parametric_surface(&mut self, u_samples: usize, v_samples: usize) -> Result<usize, JsValue> {
+        let surface = ParametricSurface::new(&self.gl, ShaderType::MeshShader, u_samples, v_samples)?;
+        self.entities.push(Box::new(surface));
+        Ok(self.entities.len() - 1)
+    }
+
+    // `callback(u, v, t)` returns [x, y, z] for parameters (u, v) at time t seconds
+    pub fn set_parametric_function(&mut self, id: usize, callback: js_sys::Function) -> Result<(), JsValue> {
+        Self::get_entity_mut::<ParametricSurface>(&mut self.entities, id)?.set_function(Box::new(JsParametricFunction::new(callback)));
+        Ok(())
+    }
+
+    // x, y and z as equations in u, v and t, e.g. a torus with
+    // `(R + r * cos(v)) * cos(u)`, `r * sin(v)` and `(R + r * cos(v)) * sin(u)`.
+    // Errors are thrown as for `set_graph3d_expression`. Returns the names of
+    // the parameters, which are shared between the three equations.
+    pub fn set_parametric_expression(&mut self, id: usize, x: &str, y: &str, z: &str) -> Result<js_sys::Array, JsValue> {
+        let expression = ParametricExpression::parse(x, y, z)?;
+        let parameter_names = expression.parameter_names().iter().map(|name| JsValue::from_str(name)).collect();
+        Self::get_entity_mut::<ParametricSurface>(&mut self.entities, id)?.set_function(Box::new(expression));
+        Ok(parameter_names)
+    }
+
+    pub fn set_parametric_parameter(&mut self, id: usize, name: &str, value: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<ParametricSurface>(&mut self.entities, id)?
+            .function_mut()
+            .set_parameter(name, value)?;
+        Ok(())
+    }
+
+    pub fn set_parametric_ranges(&mut self, id: usize, u_min: f32, u_max: f32, v_min: f32, v_max: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<ParametricSurface>(&mut self.entities, id)?.set_ranges([u_min, u_max, v_min, v_max])?;
+        Ok(())
+    }
+
+    // Wrapping a parameter closes the seam where its range starts and ends at
+    // the same place, as u does around a sphere or torus
+    pub fn set_parametric_resolution(&mut self, id: usize, u_samples: usize, v_samples: usize, wrap_u: bool, wrap_v: bool) -> Result<(), JsValue> {
+        Self::get_entity_mut::<ParametricSurface>(&mut self.entities, id)?.set_resolution(&self.gl, u_samples, v_samples, wrap_u, wrap_v)?;
+        Ok(())
+    }
+
+    pub fn set_parametric_colour(&mut self, id: usize, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<ParametricSurface>(&mut self.entities, id)?.set_base_colour([r, g, b, a]);
+        Ok(())
+    }
+
+    // Adds the surface where a 3D scalar field crosses `level` and returns its
+    // index. `values` holds `nx` x `ny` x `nz` samples, x fastest, then y, then
+    // z, spread over [-1, 1] on each axis until `set_isosurface_bounds`.
+    pub fn add_isosurface(&mut self, values: &[f32], nx: usize, ny: usize, nz: usize, level: f32) -> Result<usize, JsValue> {
+        let isosurface = Isosurface::new(&self.gl, ShaderType::MeshShader, values, [nx, ny, nz], level)?;
+        self.entities.push(Box::new(isosurface));
+        Ok(self.entities.len() - 1)
+    }
+
+    pub fn set_isosurface_values(&mut self, id: usize, values: &[f32], nx: usize, ny: usize, nz: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Isosurface>(&mut self.entities, id)?.set_values(&self.gl, values, [nx, ny, nz])?;
+        Ok(())
+    }
+
+    // Samples `callback(x, y, z)` on an `nx` x `ny` x `nz` grid over the current
+    // bounds. Exceptions and non-numeric results leave holes in the surface.
+    pub fn set_isosurface_function(&mut self, id: usize, callback: js_sys::Function, nx: usize, ny: usize, nz: usize) -> Result<(), JsValue> {
+        let isosurface = Self::get_entity_mut::<Isosurface>(&mut self.entities, id)?;
+        let (min, max) = isosurface.bounds();
+        if [nx, ny, nz].iter().any(|&n| n < 2) {
+            return Err(JsValue::from("A scalar field needs at least 2 samples along each axis"));
+        }
+        let function = |x: f32, y: f32, z: f32| {
+            callback
+                .call3(&JsValue::NULL, &JsValue::from_f64(x as f64), &JsValue::from_f64(y as f64), &JsValue::from_f64(z as f64))
+                .ok()
+                .and_then(|value| value.as_f64())
+                .unwrap_or(f64::NAN) as f32
+        };
+        let values = sample_grid(&function, [nx, ny, nz], min, max);
+        isosurface.set_values(&self.gl, &values, [nx, ny, nz])?;
+        Ok(())
+    }
+
+    // Where the first and last samples on each axis sit. Samples already taken
+    // from a function are stretched rather than taken again.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_isosurface_bounds(&mut self, id: usize, x_min: f32, x_max: f32, y_min: f32, y_max: f32, z_min: f32, z_max: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Isosurface>(&mut self.entities, id)?.set_bounds(&self.gl, [x_min, y_min, z_min], [x_max, y_max, z_max])?;
+        Ok(())
+    }
+
+    pub fn set_isosurface_level(&mut self, id: usize, level: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Isosurface>(&mut self.entities, id)?.set_level(&self.gl, level)?;
+        Ok(())
+    }
+
+    pub fn set_isosurface_colour(&mut self, id: usize, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Isosurface>(&mut self.entities, id)?.set_colour([r, g, b, a]);
+        Ok(())
+    }
+
+    // Adds arrows for a 3D vector field, with `positions` and `vectors` both
+    // x, y, z triples
+    pub fn add_quiver(&mut self, positions: &[f32], vectors: &[f32]) -> Result<usize, JsValue> {
+        let quiver = Quiver::new(&self.gl, ShaderType::InstancedShader, positions, vectors)?;
+        self.entities.push(Box::new(quiver));
+        Ok(self.entities.len() - 1)
+    }
+
+    pub fn set_quiver_field(&mut self, id: usize, positions: &[f32], vectors: &[f32]) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_field(&self.gl, positions, vectors)?;
+        Ok(())
+    }
+
+    // A 2D field as x, y pairs, laid on the x-z floor plane
+    pub fn set_quiver_field_2d(&mut self, id: usize, positions: &[f32], vectors: &[f32]) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_field_2d(&self.gl, positions, vectors)?;
+        Ok(())
+    }
+
+    // Samples `callback(x, y, z)`, which returns [vx, vy, vz], on an `nx` x `ny`
+    // x `nz` grid over the quiver's bounds. Exceptions and non-numeric
+    // components count as zero.
+    pub fn set_quiver_function(&mut self, id: usize, callback: js_sys::Function, nx: usize, ny: usize, nz: usize) -> Result<(), JsValue> {
+        let function = JsParametricFunction::new(callback);
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_function(&self.gl, &|x, y, z| function.point(x, y, z), [nx, ny, nz])?;
+        Ok(())
+    }
+
+    // Samples `callback(x, y)`, which returns [vx, vy], on an `nx` x `ny` grid
+    // over the x and z bounds, laid on the floor plane like set_quiver_field_2d
+    pub fn set_quiver_function_2d(&mut self, id: usize, callback: js_sys::Function, nx: usize, ny: usize) -> Result<(), JsValue> {
+        let function = JsParametricFunction::new(callback);
+        let planar = |x: f32, _: f32, z: f32| {
+            let [vx, vz, _] = function.point(x, z, 0.0);
+            [vx, 0.0, vz]
+        };
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_function(&self.gl, &planar, [nx, 1, ny])?;
+        Ok(())
+    }
+
+    // Where the first and last grid points sit for set_quiver_function, [-1, 1]
+    // on every axis by default. Fields already sampled are left as they are.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_quiver_bounds(&mut self, id: usize, x_min: f32, x_max: f32, y_min: f32, y_max: f32, z_min: f32, z_max: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_bounds([x_min, y_min, z_min], [x_max, y_max, z_max])?;
+        Ok(())
+    }
+
+    // Draws every arrow at the same length, leaving magnitude to the colour
+    pub fn set_quiver_normalize(&mut self, id: usize, normalize: bool) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_normalize(&self.gl, normalize)?;
+        Ok(())
+    }
+
+    // Length of the longest arrow, or of every arrow when normalised
+    pub fn set_quiver_arrow_length(&mut self, id: usize, length: f32) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_arrow_length(&self.gl, length)?;
+        Ok(())
+    }
+
+    pub fn set_quiver_colourmap(&mut self, id: usize, preset: ColourmapPreset) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_colourmap(&self.gl, Colourmap::preset(preset))?;
+        Ok(())
+    }
+
+    // Magnitudes mapped to either end of the colourmap
+    pub fn set_quiver_colour_range(&mut self, id: usize, min: f32, max: f32) -> Result<(), JsValue> {
+        if !min.is_finite() || !max.is_finite() || min == max {
+            return Err(JsValue::from("Colour range needs two different finite values"));
+        }
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_colour_range(&self.gl, Some((min, max)))?;
+        Ok(())
+    }
+
+    // Goes back to fitting the colour range to the magnitudes
+    pub fn clear_quiver_colour_range(&mut self, id: usize) -> Result<(), JsValue> {
+        Self::get_entity_mut::<Quiver>(&mut self.entities, id)?.set_colour_range(&self.gl, None)?;
+        Ok(())
+    }
+
+    // Adds a mesh that is drawn once per instance set with `set_instances`
+    pub fn add_instanced_mesh(&mut self, positions: &[f32], indices: &[u32]) -> Result<usize, JsValue> {
+        let instanced_mesh = InstancedMesh::new(&self.gl, ShaderType::InstancedShader, positions, None, indices)?;
+        self.entities.push(Box::new(instanced_mesh));
+        Ok(self.entities.len() - 1)
+    }
+
+    // 20 floats per instance: a column-major 4x4 transform then an RGBA colour
+    pub fn set_instances(&mut self, id: usize, instances: &[f32]) -> Result<(), JsValue> {
+        Self::get_entity_mut::<InstancedMesh>(&mut self.entities, id)?.set_instances(&self.gl, instances)?;
+        Ok(())
+    }
+}
+
+impl Default for WebGlClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WebGlClient {
+    // Position, rotation and scale every entity is drawn with
+    fn scene_transform() -> (glm::Vec3, glm::Vec3, glm::Vec3) {
+        (glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 1.0, 1.0))
+    }
+
+    // Takes the entity list rather than `self` so callers can still borrow `gl`
+    fn get_entity_mut<T: 'static>(entities: &mut [Box<dyn Entity>], id: usize) -> Result<&mut T, JsValue> {
+        entities
+            .get_mut(id)
+            .and_then(|e| e.as_any_mut().downcast_mut::<T>())
+            .ok_or_else(|| JsValue::from(format!("Entity {} is not a {}", id, std::any::type_name::<T>())))
+    }
+
     pub fn init_webgl_context() -> Result<WebGlRenderingContext, JsValue> {
         let document = web_sys::window().unwrap().document().unwrap();
         let canvas = document.get_element_by_id("wasmCanvas").unwrap();
@@ -0,0 +1,7 @@
+pub mod font;
+pub mod gltf;
+pub mod heightmap;
+pub mod image;
+pub mod ply;
+pub mod points;
+pub mod xyz;
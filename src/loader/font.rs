@@ -0,0 +1,161 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DistanceField {
+    // One distance in every channel
+    Sdf,
+    // Three channels whose median is the distance, which keeps corners sharp
+    Msdf,
+}
+
+// Glyph metrics in atlas pixels, with y pointing down as in the atlas image
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Glyph {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+    pub x_advance: f32,
+}
+
+pub struct Font {
+    pub glyphs: HashMap<char, Glyph>,
+    pub kernings: HashMap<(char, char), f32>,
+    // The size the atlas was generated at, which layout sizes are relative to
+    pub size: f32,
+    pub line_height: f32,
+    pub atlas_width: f32,
+    pub atlas_height: f32,
+    pub distance_field: DistanceField,
+    // How many atlas pixels the distance field spans
+    pub distance_range: f32,
+}
+
+impl Font {
+    // Reads the BMFont style JSON written by msdf-bmfont-xml and similar atlas
+    // generators. Only single page atlases are supported.
+    pub fn parse_bmfont_json(json: &str) -> Result<Self, String> {
+        let json: Value = serde_json::from_str(json).map_err(|e| format!("Invalid font JSON: {}", e))?;
+        let common = json.get("common").ok_or("Font has no common block")?;
+        let line_height = number(common, "lineHeight").ok_or("Font has no lineHeight")?;
+        let atlas_width = number(common, "scaleW").ok_or("Font has no scaleW")?;
+        let atlas_height = number(common, "scaleH").ok_or("Font has no scaleH")?;
+        if number(common, "pages").unwrap_or(1.0) > 1.0 {
+            return Err(String::from("Fonts with more than one atlas page are not supported"));
+        }
+        let size = json.get("info").and_then(|info| number(info, "size")).map(f32::abs).unwrap_or(line_height);
+
+        let (distance_field, distance_range) = match json.get("distanceField") {
+            Some(field) => {
+                let distance_field = match field.get("fieldType").and_then(Value::as_str).unwrap_or("msdf") {
+                    "msdf" | "mtsdf" => DistanceField::Msdf,
+                    "sdf" | "psdf" => DistanceField::Sdf,
+                    other => return Err(format!("Unsupported distance field type '{}'", other)),
+                };
+                (distance_field, number(field, "distanceRange").unwrap_or(4.0))
+            }
+            None => (DistanceField::Msdf, 4.0),
+        };
+
+        let mut glyphs = HashMap::new();
+        for glyph in json.get("chars").and_then(Value::as_array).ok_or("Font has no chars")? {
+            let id = number(glyph, "id").ok_or("Glyph has no id")? as u32;
+            let character = match std::char::from_u32(id) {
+                Some(character) => character,
+                None => continue,
+            };
+            let field = |name: &str| number(glyph, name).ok_or_else(|| format!("Glyph {} has no {}", id, name));
+            glyphs.insert(
+                character,
+                Glyph {
+                    x: field("x")?,
+                    y: field("y")?,
+                    width: field("width")?,
+                    height: field("height")?,
+                    x_offset: field("xoffset")?,
+                    y_offset: field("yoffset")?,
+                    x_advance: field("xadvance")?,
+                },
+            );
+        }
+
+        let mut kernings = HashMap::new();
+        if let Some(pairs) = json.get("kernings").and_then(Value::as_array) {
+            for pair in pairs {
+                let first = number(pair, "first").and_then(|id| std::char::from_u32(id as u32));
+                let second = number(pair, "second").and_then(|id| std::char::from_u32(id as u32));
+                if let (Some(first), Some(second), Some(amount)) = (first, second, number(pair, "amount")) {
+                    kernings.insert((first, second), amount);
+                }
+            }
+        }
+
+        Ok(Self {
+            glyphs,
+            kernings,
+            size,
+            line_height,
+            atlas_width,
+            atlas_height,
+            distance_field,
+            distance_range,
+        })
+    }
+
+    // Falls back to '?' for characters the atlas doesn't have
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs.get(&character).or_else(|| self.glyphs.get(&'?'))
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        self.kernings.get(&(first, second)).cloned().unwrap_or(0.0)
+    }
+}
+
+fn number(value: &Value, key: &str) -> Option<f32> {
+    value.get(key).and_then(Value::as_f64).map(|n| n as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT_JSON: &str = r#"{
+        "info": { "size": -32 },
+        "common": { "lineHeight": 40, "scaleW": 256, "scaleH": 128, "pages": 1 },
+        "distanceField": { "fieldType": "sdf", "distanceRange": 6 },
+        "chars": [
+            { "id": 65, "x": 0, "y": 0, "width": 20, "height": 30, "xoffset": 1, "yoffset": 2, "xadvance": 22 },
+            { "id": 86, "x": 32, "y": 0, "width": 20, "height": 30, "xoffset": 1, "yoffset": 2, "xadvance": 22 }
+        ],
+        "kernings": [ { "first": 65, "second": 86, "amount": -4 } ]
+    }"#;
+
+    #[test]
+    fn reads_metrics_glyphs_and_kernings() {
+        let font = Font::parse_bmfont_json(FONT_JSON).unwrap();
+        // Negative sizes mean the generator matched character height
+        assert_eq!(font.size, 32.0);
+        assert_eq!(font.line_height, 40.0);
+        assert_eq!((font.atlas_width, font.atlas_height), (256.0, 128.0));
+        assert_eq!(font.distance_field, DistanceField::Sdf);
+        assert_eq!(font.distance_range, 6.0);
+
+        assert_eq!(font.glyph('V').unwrap().x, 32.0);
+        assert_eq!(font.kerning('A', 'V'), -4.0);
+        assert_eq!(font.kerning('V', 'A'), 0.0);
+        // No '?' to fall back to
+        assert!(font.glyph('x').is_none());
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_draw() {
+        assert!(Font::parse_bmfont_json(&FONT_JSON.replace("\"pages\": 1", "\"pages\": 2")).is_err());
+        assert!(Font::parse_bmfont_json(&FONT_JSON.replace("\"sdf\"", "\"bitmap\"")).is_err());
+        assert!(Font::parse_bmfont_json(&FONT_JSON.replace("\"xadvance\": 22 }", "}")).is_err());
+        assert!(Font::parse_bmfont_json("{").is_err());
+    }
+}
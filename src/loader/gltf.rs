@@ -0,0 +1,740 @@
+use super::image::{decode_jpeg_rgba, decode_png_rgba, is_jpeg, is_png, Image};
+use nalgebra_glm as glm;
+use serde_json::Value;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+// Accessors without a buffer view start out as zeros, so nothing in the file
+// bounds their size. This caps them at 64 MB of values.
+const MAX_UNBACKED_VALUES: usize = 1 << 24;
+
+const MODE_TRIANGLES: u64 = 4;
+const MODE_TRIANGLE_STRIP: u64 = 5;
+const MODE_TRIANGLE_FAN: u64 = 6;
+
+pub struct Gltf {
+    pub nodes: Vec<Node>,
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub images: Vec<Option<Image>>,
+    pub root_nodes: Vec<usize>,
+}
+
+pub struct Node {
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+    pub transform: glm::Mat4,
+}
+
+pub struct Mesh {
+    pub primitives: Vec<Primitive>,
+}
+
+pub struct Primitive {
+    pub positions: Vec<f32>,
+    pub normals: Option<Vec<f32>>,
+    pub tex_coords: Option<Vec<f32>>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+}
+
+pub struct Material {
+    pub base_colour: [f32; 4],
+    pub base_colour_image: Option<usize>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_colour: [1.0, 1.0, 1.0, 1.0],
+            base_colour_image: None,
+        }
+    }
+}
+
+impl Gltf {
+    // Accepts either a binary .glb container or a .gltf JSON document. Buffers
+    // and images referenced by a relative URI are fetched through `resolve_uri`;
+    // base64 data URIs are decoded directly.
+    pub fn parse(data: &[u8], resolve_uri: &dyn Fn(&str) -> Result<Vec<u8>, String>) -> Result<Self, String> {
+        let (json, bin_chunk) = if data.len() >= 4 && read_u32(data, 0) == GLB_MAGIC {
+            split_glb(data)?
+        } else {
+            (data, None)
+        };
+        let json: Value = serde_json::from_slice(json).map_err(|e| format!("Invalid glTF JSON: {}", e))?;
+
+        let buffers = array(&json, "buffers")
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| match buffer.get("uri").and_then(Value::as_str) {
+                Some(uri) => load_uri(uri, resolve_uri),
+                None if i == 0 => bin_chunk
+                    .map(|b| b.to_vec())
+                    .ok_or_else(|| String::from("Buffer 0 has no URI and there is no GLB binary chunk")),
+                None => Err(format!("Buffer {} has no URI", i)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let document = Document { json: &json, buffers };
+
+        let images = array(&json, "images")
+            .iter()
+            .enumerate()
+            .map(|(i, image)| document.load_image(i, image, resolve_uri))
+            .collect::<Result<Vec<_>, _>>()?;
+        let materials = array(&json, "materials").iter().map(|material| document.read_material(material)).collect();
+        let meshes = array(&json, "meshes").iter().map(|mesh| document.read_mesh(mesh)).collect::<Result<Vec<_>, _>>()?;
+        let nodes = array(&json, "nodes").iter().map(read_node).collect::<Result<Vec<_>, _>>()?;
+
+        let scene_index = json.get("scene").and_then(Value::as_u64).unwrap_or(0) as usize;
+        let root_nodes = match array(&json, "scenes").get(scene_index) {
+            Some(scene) => indices(scene, "nodes"),
+            None => {
+                // Without a scene every node that isn't somebody's child is a root
+                let children: Vec<usize> = nodes.iter().flat_map(|n: &Node| n.children.clone()).collect();
+                (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+            }
+        };
+
+        Ok(Self {
+            nodes,
+            meshes,
+            materials,
+            images,
+            root_nodes,
+        })
+    }
+
+    // Flattens the node hierarchy into (world transform, mesh index) pairs
+    pub fn mesh_instances(&self) -> Vec<(glm::Mat4, usize)> {
+        let mut instances = Vec::new();
+        let mut visited = vec![false; self.nodes.len()];
+        for &root in self.root_nodes.iter() {
+            self.collect_instances(root, &glm::Mat4::identity(), &mut visited, &mut instances);
+        }
+        instances
+    }
+
+    fn collect_instances(&self, node_index: usize, parent: &glm::Mat4, visited: &mut Vec<bool>, instances: &mut Vec<(glm::Mat4, usize)>) {
+        if node_index >= self.nodes.len() || visited[node_index] {
+            return;
+        }
+        visited[node_index] = true;
+
+        let node = &self.nodes[node_index];
+        let world = parent * node.transform;
+        if let Some(mesh) = node.mesh {
+            if mesh < self.meshes.len() {
+                instances.push((world, mesh));
+            }
+        }
+        for &child in node.children.iter() {
+            self.collect_instances(child, &world, visited, instances);
+        }
+    }
+}
+
+struct Document<'a> {
+    json: &'a Value,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl<'a> Document<'a> {
+    fn buffer_view(&self, index: usize) -> Result<&[u8], String> {
+        let view = array(self.json, "bufferViews").get(index).ok_or_else(|| format!("Missing buffer view {}", index))?;
+        let buffer = uint(view, "buffer").ok_or("Buffer view has no buffer")?;
+        let offset = uint(view, "byteOffset").unwrap_or(0);
+        let length = uint(view, "byteLength").ok_or("Buffer view has no byteLength")?;
+        let data = self.buffers.get(buffer).ok_or_else(|| format!("Missing buffer {}", buffer))?;
+        offset
+            .checked_add(length)
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(|| format!("Buffer view {} is out of range", index))
+    }
+
+    fn buffer_view_stride(&self, index: usize) -> Option<usize> {
+        array(self.json, "bufferViews").get(index).and_then(|view| uint(view, "byteStride"))
+    }
+
+    // Decodes an accessor into f32 components, returning the values and the
+    // number of components per element
+    fn read_accessor(&self, index: usize) -> Result<(Vec<f32>, usize), String> {
+        let accessor = array(self.json, "accessors").get(index).ok_or_else(|| format!("Missing accessor {}", index))?;
+        let count = uint(accessor, "count").ok_or("Accessor has no count")?;
+        let component_type = uint(accessor, "componentType").ok_or("Accessor has no componentType")?;
+        let normalized = accessor.get("normalized").and_then(Value::as_bool).unwrap_or(false);
+        let components = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            other => return Err(format!("Unsupported accessor type {:?}", other)),
+        };
+        let component_size = component_size(component_type)?;
+
+        // Checked against what backs it before anything is allocated
+        let view = match uint(accessor, "bufferView") {
+            Some(view_index) => {
+                let view = self.buffer_view(view_index)?;
+                let offset = uint(accessor, "byteOffset").unwrap_or(0);
+                let stride = self.buffer_view_stride(view_index).unwrap_or(components * component_size);
+                check_accessor_range(view.len(), offset, count, stride, components * component_size)?;
+                Some((view, offset, stride))
+            }
+            None if count.checked_mul(components).is_some_and(|values| values <= MAX_UNBACKED_VALUES) => None,
+            None => return Err(format!("Accessor {} is too large", index)),
+        };
+
+        let mut values = vec![0.0; count * components];
+        if let Some((view, offset, stride)) = view {
+            for (element, chunk) in values.chunks_mut(components).enumerate() {
+                for (component, value) in chunk.iter_mut().enumerate() {
+                    let position = offset + element * stride + component * component_size;
+                    *value = read_component(view, position, component_type, normalized)?;
+                }
+            }
+        }
+
+        if let Some(sparse) = accessor.get("sparse") {
+            self.apply_sparse(sparse, &mut values, components, component_type, normalized)?;
+        }
+
+        Ok((values, components))
+    }
+
+    // Index accessors are read as integers so that large meshes don't lose
+    // precision going through f32
+    fn read_indices(&self, index: usize) -> Result<Vec<u32>, String> {
+        let accessor = array(self.json, "accessors").get(index).ok_or_else(|| format!("Missing accessor {}", index))?;
+        let count = uint(accessor, "count").ok_or("Accessor has no count")?;
+        let component_type = uint(accessor, "componentType").ok_or("Accessor has no componentType")?;
+        let view_index = match uint(accessor, "bufferView") {
+            Some(view_index) if accessor.get("sparse").is_none() => view_index,
+            _ => return Ok(self.read_accessor(index)?.0.iter().map(|&i| i as u32).collect()),
+        };
+        let view = self.buffer_view(view_index)?;
+        let offset = uint(accessor, "byteOffset").unwrap_or(0);
+        let component_size = component_size_of_index(component_type)?;
+        let stride = self.buffer_view_stride(view_index).unwrap_or(component_size);
+        check_accessor_range(view.len(), offset, count, stride, component_size)?;
+
+        (0..count).map(|i| read_index(view, offset + i * stride, component_type)).collect()
+    }
+
+    fn apply_sparse(&self, sparse: &Value, values: &mut [f32], components: usize, component_type: usize, normalized: bool) -> Result<(), String> {
+        let count = uint(sparse, "count").ok_or("Sparse accessor has no count")?;
+        let sparse_indices = sparse.get("indices").ok_or("Sparse accessor has no indices")?;
+        let sparse_values = sparse.get("values").ok_or("Sparse accessor has no values")?;
+
+        let index_view = self.buffer_view(uint(sparse_indices, "bufferView").ok_or("Sparse indices have no bufferView")?)?;
+        let index_offset = uint(sparse_indices, "byteOffset").unwrap_or(0);
+        let index_type = uint(sparse_indices, "componentType").ok_or("Sparse indices have no componentType")?;
+        let value_view = self.buffer_view(uint(sparse_values, "bufferView").ok_or("Sparse values have no bufferView")?)?;
+        let value_offset = uint(sparse_values, "byteOffset").unwrap_or(0);
+        let component_size = component_size(component_type)?;
+        let index_size = component_size_of_index(index_type)?;
+        check_accessor_range(index_view.len(), index_offset, count, index_size, index_size)?;
+        check_accessor_range(value_view.len(), value_offset, count, components * component_size, components * component_size)?;
+
+        for i in 0..count {
+            let element = read_index(index_view, index_offset + i * index_size, index_type)? as usize;
+            for component in 0..components {
+                let position = value_offset + (i * components + component) * component_size;
+                let value = read_component(value_view, position, component_type, normalized)?;
+                *values.get_mut(element * components + component).ok_or("Sparse index out of range")? = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_mesh(&self, mesh: &Value) -> Result<Mesh, String> {
+        let mut primitives = Vec::new();
+        for primitive in array(mesh, "primitives") {
+            let mode = primitive.get("mode").and_then(Value::as_u64).unwrap_or(MODE_TRIANGLES);
+            if mode != MODE_TRIANGLES && mode != MODE_TRIANGLE_STRIP && mode != MODE_TRIANGLE_FAN {
+                // Points and lines have no surface to shade
+                continue;
+            }
+
+            let attributes = primitive.get("attributes").ok_or("Primitive has no attributes")?;
+            let positions = match uint(attributes, "POSITION") {
+                Some(accessor) => self.read_accessor(accessor)?.0,
+                None => continue,
+            };
+            let normals = match uint(attributes, "NORMAL") {
+                Some(accessor) => Some(self.read_accessor(accessor)?.0),
+                None => None,
+            };
+            let tex_coords = match uint(attributes, "TEXCOORD_0") {
+                Some(accessor) => Some(self.read_accessor(accessor)?.0),
+                None => None,
+            };
+
+            let vertex_count = positions.len() / 3;
+            let indices: Vec<u32> = match uint(primitive, "indices") {
+                Some(accessor) => self.read_indices(accessor)?,
+                None => (0..vertex_count as u32).collect(),
+            };
+            if indices.iter().any(|&i| i as usize >= vertex_count) {
+                return Err(String::from("Primitive index out of range"));
+            }
+
+            primitives.push(Primitive {
+                positions,
+                normals,
+                tex_coords,
+                indices: triangulate(&indices, mode),
+                material: uint(primitive, "material"),
+            });
+        }
+
+        Ok(Mesh { primitives })
+    }
+
+    fn read_material(&self, material: &Value) -> Material {
+        let mut result = Material::default();
+        if let Some(pbr) = material.get("pbrMetallicRoughness") {
+            if let Some(factor) = pbr.get("baseColorFactor").and_then(Value::as_array) {
+                for (i, value) in factor.iter().take(4).enumerate() {
+                    result.base_colour[i] = value.as_f64().unwrap_or(1.0) as f32;
+                }
+            }
+            result.base_colour_image = pbr
+                .get("baseColorTexture")
+                .and_then(|texture| uint(texture, "index"))
+                .and_then(|texture| array(self.json, "textures").get(texture))
+                .and_then(|texture| uint(texture, "source"));
+        }
+        result
+    }
+
+    // PNG and JPEG are the formats core glTF allows. Anything else, such as
+    // the WebP or KTX2 of texture extensions, is an error naming the image.
+    fn load_image(&self, index: usize, image: &Value, resolve_uri: &dyn Fn(&str) -> Result<Vec<u8>, String>) -> Result<Option<Image>, String> {
+        let data = match (uint(image, "bufferView"), image.get("uri").and_then(Value::as_str)) {
+            (Some(view), _) => self.buffer_view(view)?.to_vec(),
+            (None, Some(uri)) => load_uri(uri, resolve_uri)?,
+            (None, None) => return Ok(None),
+        };
+        let decoded = if is_png(&data) {
+            decode_png_rgba(&data)
+        } else if is_jpeg(&data) {
+            decode_jpeg_rgba(&data)
+        } else {
+            // Data URIs would bury the message, so they're named by MIME type
+            let name = ["name", "uri", "mimeType"]
+                .iter()
+                .filter_map(|key| image.get(*key).and_then(Value::as_str))
+                .find(|name| !name.starts_with("data:"))
+                .unwrap_or("unnamed");
+            Err(format!("'{}' is not a PNG or JPEG", name))
+        };
+        decoded.map(Some).map_err(|e| format!("glTF image {}: {}", index, e))
+    }
+}
+
+fn read_node(node: &Value) -> Result<Node, String> {
+    let transform = match node.get("matrix").and_then(Value::as_array) {
+        Some(matrix) if matrix.len() == 16 => glm::make_mat4(&floats(matrix)),
+        Some(_) => return Err(String::from("Node matrix must have 16 elements")),
+        None => {
+            let translation = node.get("translation").and_then(Value::as_array).map(|t| floats(t)).unwrap_or_else(|| vec![0.0; 3]);
+            let rotation = node
+                .get("rotation")
+                .and_then(Value::as_array)
+                .map(|r| floats(r))
+                .unwrap_or_else(|| vec![0.0, 0.0, 0.0, 1.0]);
+            let scale = node.get("scale").and_then(Value::as_array).map(|s| floats(s)).unwrap_or_else(|| vec![1.0; 3]);
+            if translation.len() != 3 || rotation.len() != 4 || scale.len() != 3 {
+                return Err(String::from("Node has a malformed translation, rotation or scale"));
+            }
+            glm::translation(&glm::make_vec3(&translation))
+                * glm::quat_to_mat4(&glm::quat(rotation[0], rotation[1], rotation[2], rotation[3]))
+                * glm::scaling(&glm::make_vec3(&scale))
+        }
+    };
+
+    Ok(Node {
+        mesh: uint(node, "mesh"),
+        children: indices(node, "children"),
+        transform,
+    })
+}
+
+fn split_glb(data: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    if data.len() < 20 {
+        return Err(String::from("GLB file is truncated"));
+    }
+    let version = read_u32(data, 4);
+    if version != 2 {
+        return Err(format!("Unsupported GLB version {}", version));
+    }
+    let length = (read_u32(data, 8) as usize).min(data.len());
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32(data, offset) as usize;
+        let chunk_type = read_u32(data, offset + 4);
+        let end = (offset + 8).checked_add(chunk_length).ok_or("GLB chunk is out of range")?;
+        let chunk = data.get(offset + 8..end).ok_or("GLB chunk is out of range")?;
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(chunk),
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(chunk),
+            _ => {}
+        }
+        // Chunks are padded to four byte boundaries. The chunk is in the data,
+        // so this stays within a few bytes of its end.
+        offset = end + (4 - chunk_length % 4) % 4;
+    }
+
+    Ok((json.ok_or("GLB file has no JSON chunk")?, bin))
+}
+
+fn load_uri(uri: &str, resolve_uri: &dyn Fn(&str) -> Result<Vec<u8>, String>) -> Result<Vec<u8>, String> {
+    if uri.starts_with("data:") {
+        let (_, encoded) = uri.split_at(uri.find(";base64,").ok_or("Only base64 data URIs are supported")? + ";base64,".len());
+        base64::decode(encoded).map_err(|e| format!("Invalid base64 data URI: {}", e))
+    } else {
+        resolve_uri(uri)
+    }
+}
+
+fn triangulate(indices: &[u32], mode: u64) -> Vec<u32> {
+    match mode {
+        MODE_TRIANGLE_STRIP => (2..indices.len())
+            .flat_map(|i| {
+                if i % 2 == 0 {
+                    vec![indices[i - 2], indices[i - 1], indices[i]]
+                } else {
+                    vec![indices[i - 1], indices[i - 2], indices[i]]
+                }
+            })
+            .collect(),
+        MODE_TRIANGLE_FAN => (2..indices.len()).flat_map(|i| vec![indices[0], indices[i - 1], indices[i]]).collect(),
+        _ => indices[..indices.len() - indices.len() % 3].to_vec(),
+    }
+}
+
+// Checks that `count` elements of `element_size` bytes, `stride` bytes apart
+// from `offset`, fit in a buffer view `view_length` bytes long
+fn check_accessor_range(view_length: usize, offset: usize, count: usize, stride: usize, element_size: usize) -> Result<(), String> {
+    if count == 0 {
+        return Ok(());
+    }
+    let end = (count - 1)
+        .checked_mul(stride)
+        .and_then(|start| start.checked_add(offset))
+        .and_then(|start| start.checked_add(element_size));
+    match end {
+        Some(end) if end <= view_length => Ok(()),
+        _ => Err(String::from("Accessor reads past the end of its buffer view")),
+    }
+}
+
+fn component_size(component_type: usize) -> Result<usize, String> {
+    match component_type {
+        5120 | 5121 => Ok(1),
+        5122 | 5123 => Ok(2),
+        5125 | 5126 => Ok(4),
+        _ => Err(format!("Unsupported component type {}", component_type)),
+    }
+}
+
+fn component_size_of_index(component_type: usize) -> Result<usize, String> {
+    match component_type {
+        5121 | 5123 | 5125 => component_size(component_type),
+        _ => Err(format!("Unsupported sparse index type {}", component_type)),
+    }
+}
+
+fn read_component(data: &[u8], position: usize, component_type: usize, normalized: bool) -> Result<f32, String> {
+    let bytes = data
+        .get(position..position + component_size(component_type)?)
+        .ok_or("Accessor reads past the end of its buffer view")?;
+    let value = match component_type {
+        5120 if normalized => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+        5120 => bytes[0] as i8 as f32,
+        5121 if normalized => bytes[0] as f32 / 255.0,
+        5121 => bytes[0] as f32,
+        5122 if normalized => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0),
+        5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+        5123 if normalized => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+        5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+        5125 => read_u32(bytes, 0) as f32,
+        _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    };
+    Ok(value)
+}
+
+fn read_index(data: &[u8], position: usize, component_type: usize) -> Result<u32, String> {
+    let bytes = data
+        .get(position..position + component_size(component_type)?)
+        .ok_or("Index accessor reads past the end of its buffer view")?;
+    match component_type {
+        5121 => Ok(bytes[0] as u32),
+        5123 => Ok(u16::from_le_bytes([bytes[0], bytes[1]]) as u32),
+        5125 => Ok(read_u32(bytes, 0)),
+        _ => Err(format!("Unsupported index component type {}", component_type)),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn array<'v>(value: &'v Value, key: &str) -> &'v [Value] {
+    value.get(key).and_then(Value::as_array).map(|a| a.as_slice()).unwrap_or(&[])
+}
+
+fn uint(value: &Value, key: &str) -> Option<usize> {
+    value.get(key).and_then(Value::as_u64).map(|v| v as usize)
+}
+
+fn indices(value: &Value, key: &str) -> Vec<usize> {
+    array(value, key).iter().filter_map(Value::as_u64).map(|v| v as usize).collect()
+}
+
+fn floats(values: &[Value]) -> Vec<f32> {
+    values.iter().map(|v| v.as_f64().unwrap_or(0.0) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_uris(uri: &str) -> Result<Vec<u8>, String> {
+        Err(format!("Unexpected URI {}", uri))
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    // A GLB container around `json` and `bin`, padding each chunk as the
+    // format requires
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize((json.len() + 3) & !3, b' ');
+        let mut bin = bin.to_vec();
+        bin.resize((bin.len() + 3) & !3, 0);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        for (chunk_type, chunk) in [(GLB_CHUNK_JSON, &json), (GLB_CHUNK_BIN, &bin)].iter() {
+            data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            data.extend_from_slice(&chunk_type.to_le_bytes());
+            data.extend_from_slice(chunk);
+        }
+        data
+    }
+
+    fn read(json: &str, buffer: Vec<u8>, accessor: usize) -> Result<(Vec<f32>, usize), String> {
+        let json: Value = serde_json::from_str(json).unwrap();
+        let document = Document {
+            json: &json,
+            buffers: vec![buffer],
+        };
+        document.read_accessor(accessor)
+    }
+
+    #[test]
+    fn glb_chunks_are_split_and_read() {
+        let json = r#"{
+            "buffers": [{ "byteLength": 36 }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+            "nodes": [{ "mesh": 0, "translation": [1, 2, 3] }]
+        }"#;
+        let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let data = glb(json, &f32_bytes(&positions));
+
+        let (json_chunk, bin_chunk) = split_glb(&data).unwrap();
+        assert!(json_chunk.starts_with(b"{"));
+        assert_eq!(bin_chunk.unwrap(), &f32_bytes(&positions)[..]);
+
+        let gltf = Gltf::parse(&data, &no_uris).unwrap();
+        let primitive = &gltf.meshes[0].primitives[0];
+        assert_eq!(primitive.positions, positions);
+        assert_eq!(primitive.indices, [0, 1, 2]);
+        // The only node is the root
+        let instances = gltf.mesh_instances();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].0 * glm::vec4(0.0, 0.0, 0.0, 1.0), glm::vec4(1.0, 2.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn malformed_glb_is_an_error() {
+        let data = glb("{}", &[1, 2, 3, 4]);
+        assert!(split_glb(&data[..16]).is_err());
+
+        let mut wrong_version = data.clone();
+        wrong_version[4] = 1;
+        assert!(split_glb(&wrong_version).is_err());
+
+        // A chunk claiming to run far past the end of the file
+        let mut too_long = data.clone();
+        too_long[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(split_glb(&too_long).is_err());
+
+        let mut no_json = data;
+        no_json[16..20].copy_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+        assert!(split_glb(&no_json).is_err());
+    }
+
+    #[test]
+    fn strided_accessors_skip_interleaved_data() {
+        // Positions interleaved with a 4 byte tag, 16 bytes per vertex
+        let mut buffer = Vec::new();
+        for i in 0..3 {
+            buffer.extend(f32_bytes(&[i as f32, 10.0 + i as f32, 20.0 + i as f32]));
+            buffer.extend_from_slice(&[0xFF; 4]);
+        }
+        let json = r#"{
+            "bufferViews": [{ "buffer": 0, "byteLength": 48, "byteStride": 16 }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }]
+        }"#;
+        let (values, components) = read(json, buffer, 0).unwrap();
+        assert_eq!(components, 3);
+        assert_eq!(values, [0.0, 10.0, 20.0, 1.0, 11.0, 21.0, 2.0, 12.0, 22.0]);
+    }
+
+    #[test]
+    fn normalized_integers_map_to_unit_ranges() {
+        let json = r#"{
+            "bufferViews": [{ "buffer": 0, "byteLength": 8 }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5121, "normalized": true, "count": 1, "type": "VEC3" },
+                { "bufferView": 0, "byteOffset": 3, "componentType": 5120, "normalized": true, "count": 2, "type": "SCALAR" },
+                { "bufferView": 0, "byteOffset": 6, "componentType": 5123, "normalized": true, "count": 1, "type": "SCALAR" }
+            ]
+        }"#;
+        let buffer = vec![0, 255, 51, 0x80, 0x7F, 0, 0xFF, 0xFF];
+        assert_eq!(read(json, buffer.clone(), 0).unwrap().0, [0.0, 1.0, 0.2]);
+        // -128 clamps to -1 like -127
+        assert_eq!(read(json, buffer.clone(), 1).unwrap().0, [-1.0, 1.0]);
+        assert_eq!(read(json, buffer, 2).unwrap().0, [1.0]);
+    }
+
+    #[test]
+    fn sparse_accessors_override_their_base() {
+        // Four zeroed VEC2s with the second and fourth replaced
+        let mut buffer = vec![1, 0, 3, 0];
+        buffer.extend(f32_bytes(&[5.0, 6.0, 7.0, 8.0]));
+        let json = r#"{
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 4 },
+                { "buffer": 0, "byteOffset": 4, "byteLength": 16 }
+            ],
+            "accessors": [{
+                "componentType": 5126, "count": 4, "type": "VEC2",
+                "sparse": {
+                    "count": 2,
+                    "indices": { "bufferView": 0, "componentType": 5123 },
+                    "values": { "bufferView": 1 }
+                }
+            }]
+        }"#;
+        let (values, _) = read(json, buffer.clone(), 0).unwrap();
+        assert_eq!(values, [0.0, 0.0, 5.0, 6.0, 0.0, 0.0, 7.0, 8.0]);
+
+        // Replacing an element past the end
+        let out_of_range = json.replace(r#""count": 4"#, r#""count": 3"#);
+        assert!(read(&out_of_range, buffer, 0).is_err());
+    }
+
+    #[test]
+    fn accessors_are_checked_before_allocating() {
+        let json = r#"{
+            "bufferViews": [{ "buffer": 0, "byteLength": 12 }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 4000000000, "type": "VEC3" },
+                { "componentType": 5126, "count": 4000000000, "type": "MAT4" },
+                { "bufferView": 0, "byteOffset": 4, "componentType": 5126, "count": 1, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5126, "count": 1, "type": "SCALAR" }
+            ]
+        }"#;
+        let buffer = f32_bytes(&[1.0, 2.0, 3.0]);
+        for accessor in 0..4 {
+            assert!(read(json, buffer.clone(), accessor).is_err(), "accessor {}", accessor);
+        }
+
+        // A buffer view whose end overflows
+        let overflowing = json.replace(r#""byteLength": 12 }"#, &format!(r#""byteOffset": {}, "byteLength": 12 }}"#, u64::MAX - 4));
+        assert!(read(&overflowing, buffer, 2).is_err());
+    }
+
+    #[test]
+    fn strips_and_fans_become_triangles() {
+        let indices = [0, 1, 2, 3, 4];
+        // Every other strip triangle swaps its first two corners to keep the
+        // winding
+        assert_eq!(triangulate(&indices, MODE_TRIANGLE_STRIP), [0, 1, 2, 2, 1, 3, 2, 3, 4]);
+        assert_eq!(triangulate(&indices, MODE_TRIANGLE_FAN), [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+        // Leftover indices that don't make a whole triangle are dropped
+        assert_eq!(triangulate(&indices, MODE_TRIANGLES), [0, 1, 2]);
+        assert!(triangulate(&indices[..2], MODE_TRIANGLE_STRIP).is_empty());
+    }
+
+    // An 8x2 baseline JPEG whose one block has no DC or AC coefficients, so
+    // every pixel decodes to mid grey. Both Huffman tables hold a single
+    // one-bit code for zero.
+    fn grey_jpeg() -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        data.extend_from_slice(&[0xFF, 0xDB, 0, 67, 0]);
+        data.extend_from_slice(&[1; 64]);
+        data.extend_from_slice(&[0xFF, 0xC0, 0, 11, 8, 0, 2, 0, 8, 1, 1, 0x11, 0]);
+        for &table in [0x00, 0x10].iter() {
+            data.extend_from_slice(&[0xFF, 0xC4, 0, 20, table, 1]);
+            data.extend_from_slice(&[0; 15]);
+            data.push(0);
+        }
+        data.extend_from_slice(&[0xFF, 0xDA, 0, 8, 1, 1, 0x00, 0, 63, 0]);
+        // DC category 0 then end of block, padded with ones
+        data.extend_from_slice(&[0x3F, 0xFF, 0xD9]);
+        data
+    }
+
+    fn grey_png() -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 1, 1);
+            encoder.set_color(png::ColorType::RGB);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header().unwrap().write_image_data(&[10, 20, 30]).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn png_and_jpeg_images_are_decoded_and_others_named() {
+        let images = |entries: &str| format!(r#"{{ "images": [{}] }}"#, entries);
+        let data_uri = |mime: &str, bytes: &[u8]| format!(r#"{{ "uri": "data:{};base64,{}" }}"#, mime, base64::encode(bytes));
+        let json = images(&format!("{}, {}", data_uri("image/png", &grey_png()), data_uri("image/jpeg", &grey_jpeg())));
+        let gltf = Gltf::parse(json.as_bytes(), &no_uris).unwrap();
+
+        let png = gltf.images[0].as_ref().unwrap();
+        assert_eq!((png.width, png.height), (1, 1));
+        assert_eq!(png.rgba, [10, 20, 30, 255]);
+        let jpeg = gltf.images[1].as_ref().unwrap();
+        assert_eq!((jpeg.width, jpeg.height), (8, 2));
+        assert_eq!(jpeg.rgba.len(), 8 * 2 * 4);
+        assert!(jpeg.rgba.chunks(4).all(|p| p == [128, 128, 128, 255]), "{:?}", jpeg.rgba);
+
+        let webp = b"RIFF\x0c\0\0\0WEBPVP8 ".to_vec();
+        let resolve = |_: &str| Ok(webp.clone());
+        let json = images(r#"{ "uri": "bark.webp" }"#);
+        assert_eq!(Gltf::parse(json.as_bytes(), &resolve).err().unwrap(), "glTF image 0: 'bark.webp' is not a PNG or JPEG");
+        let json = images(&data_uri("image/webp", &webp).replace(" }", r#", "mimeType": "image/webp" }"#));
+        assert_eq!(Gltf::parse(json.as_bytes(), &no_uris).err().unwrap(), "glTF image 0: 'image/webp' is not a PNG or JPEG");
+    }
+}
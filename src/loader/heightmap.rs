@@ -0,0 +1,209 @@
+use png::{BitDepth, ColorType, Decoder, Transformations};
+
+pub struct Heightmap {
+    // Samples along x per row, and rows along z
+    pub width: usize,
+    pub depth: usize,
+    pub heights: Vec<f32>,
+    // x_min, x_max, z_min, z_max in the source image's pixel coordinates
+    pub extent: [f32; 4],
+}
+
+// How a grayscale image becomes heights. A pixel's height is
+// `offset + height_scale * level`, where level runs from 0 for black to 1 for
+// white. A zero width or depth keeps the image's own resolution on that axis.
+pub struct HeightmapOptions {
+    pub height_scale: f32,
+    pub offset: f32,
+    pub width: usize,
+    pub depth: usize,
+}
+
+pub fn load_png_heightmap(data: &[u8], options: &HeightmapOptions) -> Result<Heightmap, String> {
+    let (image_width, image_depth, levels) = decode_png_levels(data)?;
+    if image_width < 2 || image_depth < 2 {
+        return Err(String::from("Heightmap image must be at least 2x2 pixels"));
+    }
+
+    let width = if options.width == 0 { image_width } else { options.width };
+    let depth = if options.depth == 0 { image_depth } else { options.depth };
+    if width < 2 || depth < 2 {
+        return Err(String::from("Heightmap resolution must be at least 2x2"));
+    }
+
+    let heights = resample(&levels, image_width, image_depth, width, depth)
+        .into_iter()
+        .map(|level| options.offset + options.height_scale * level)
+        .collect();
+
+    Ok(Heightmap {
+        width,
+        depth,
+        heights,
+        extent: [0.0, (image_width - 1) as f32, 0.0, (image_depth - 1) as f32],
+    })
+}
+
+// Decodes an 8- or 16-bit grayscale PNG into levels between 0 and 1. Lower
+// bit depths are expanded to 8 bits and any alpha channel is ignored.
+pub fn decode_png_levels(data: &[u8]) -> Result<(usize, usize, Vec<f32>), String> {
+    let mut decoder = Decoder::new(data);
+    decoder.set_transformations(Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info().map_err(|e| format!("Invalid PNG: {}", e))?;
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).map_err(|e| format!("Invalid PNG: {}", e))?;
+
+    let channels = match info.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        color_type => return Err(format!("Heightmap PNG must be grayscale, not {:?}", color_type)),
+    };
+
+    let pixel_count = (info.width * info.height) as usize;
+    let levels = match info.bit_depth {
+        BitDepth::Eight => pixels.chunks(channels).take(pixel_count).map(|p| p[0] as f32 / 255.0).collect(),
+        // 16-bit samples are big-endian
+        BitDepth::Sixteen => pixels
+            .chunks(2 * channels)
+            .take(pixel_count)
+            .map(|p| u16::from_be_bytes([p[0], p[1]]) as f32 / 65535.0)
+            .collect(),
+        bit_depth => return Err(format!("Unsupported PNG bit depth {:?}", bit_depth)),
+    };
+
+    Ok((info.width as usize, info.height as usize, levels))
+}
+
+// Bilinear resampling that keeps the corner samples on the corners
+fn resample(levels: &[f32], source_width: usize, source_depth: usize, width: usize, depth: usize) -> Vec<f32> {
+    if width == source_width && depth == source_depth {
+        return levels.to_vec();
+    }
+
+    let x_ratio = (source_width - 1) as f32 / (width - 1) as f32;
+    let z_ratio = (source_depth - 1) as f32 / (depth - 1) as f32;
+    let mut heights = Vec::with_capacity(width * depth);
+
+    for z in 0..depth {
+        let source_z = z as f32 * z_ratio;
+        let z0 = (source_z.floor() as usize).min(source_depth - 2);
+        let fz = source_z - z0 as f32;
+        for x in 0..width {
+            let source_x = x as f32 * x_ratio;
+            let x0 = (source_x.floor() as usize).min(source_width - 2);
+            let fx = source_x - x0 as f32;
+
+            let top_left = levels[z0 * source_width + x0];
+            let top_right = levels[z0 * source_width + x0 + 1];
+            let bottom_left = levels[(z0 + 1) * source_width + x0];
+            let bottom_right = levels[(z0 + 1) * source_width + x0 + 1];
+
+            let top = top_left + (top_right - top_left) * fx;
+            let bottom = bottom_left + (bottom_right - bottom_left) * fx;
+            heights.push(top + (bottom - top) * fz);
+        }
+    }
+    heights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use png::Encoder;
+
+    fn encode_png(width: u32, height: u32, color_type: ColorType, bit_depth: BitDepth, pixels: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut data, width, height);
+            encoder.set_color(color_type);
+            encoder.set_depth(bit_depth);
+            encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+        }
+        data
+    }
+
+    fn options(width: usize, depth: usize) -> HeightmapOptions {
+        HeightmapOptions {
+            height_scale: 10.0,
+            offset: -1.0,
+            width,
+            depth,
+        }
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-5, "{:?} vs {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn eight_bit_levels_are_scaled_and_offset() {
+        let png = encode_png(3, 2, ColorType::Grayscale, BitDepth::Eight, &[0, 255, 51, 102, 153, 204]);
+        let heightmap = load_png_heightmap(&png, &options(0, 0)).unwrap();
+        assert_eq!((heightmap.width, heightmap.depth), (3, 2));
+        assert_close(&heightmap.heights, &[-1.0, 9.0, 1.0, 3.0, 5.0, 7.0]);
+        assert_eq!(heightmap.extent, [0.0, 2.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn sixteen_bit_samples_are_big_endian() {
+        // 0x0100 would read as 1 if the bytes were swapped
+        let pixels = [0x01, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x80, 0x00];
+        let png = encode_png(2, 2, ColorType::Grayscale, BitDepth::Sixteen, &pixels);
+        let (width, depth, levels) = decode_png_levels(&png).unwrap();
+        assert_eq!((width, depth), (2, 2));
+        assert_close(&levels, &[256.0 / 65535.0, 1.0, 0.0, 32768.0 / 65535.0]);
+    }
+
+    #[test]
+    fn alpha_is_ignored_and_colour_is_rejected() {
+        let png = encode_png(2, 2, ColorType::GrayscaleAlpha, BitDepth::Eight, &[255, 0, 0, 255, 51, 10, 0, 0]);
+        assert_close(&decode_png_levels(&png).unwrap().2, &[1.0, 0.0, 0.2, 0.0]);
+
+        let png = encode_png(
+            2,
+            2,
+            ColorType::GrayscaleAlpha,
+            BitDepth::Sixteen,
+            &[0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0],
+        );
+        assert_close(&decode_png_levels(&png).unwrap().2, &[1.0, 0.0, 0.0, 0.0]);
+
+        let rgb = encode_png(2, 2, ColorType::RGB, BitDepth::Eight, &[0; 12]);
+        assert!(decode_png_levels(&rgb).is_err());
+        assert!(decode_png_levels(b"not a png").is_err());
+    }
+
+    #[test]
+    fn resampling_keeps_the_corners() {
+        // Upsampling interpolates between the four pixels
+        let png = encode_png(2, 2, ColorType::Grayscale, BitDepth::Eight, &[0, 255, 51, 102]);
+        let heightmap = load_png_heightmap(&png, &options(3, 3)).unwrap();
+        assert_eq!((heightmap.width, heightmap.depth), (3, 3));
+        assert_close(&heightmap.heights, &[-1.0, 4.0, 9.0, 0.0, 3.0, 6.0, 1.0, 2.0, 3.0]);
+        // The extent stays in source pixels
+        assert_eq!(heightmap.extent, [0.0, 1.0, 0.0, 1.0]);
+
+        // Downsampling lands exactly on the source's corners
+        let pixels: Vec<u8> = (0..20).map(|i| i * 10).collect();
+        let png = encode_png(5, 4, ColorType::Grayscale, BitDepth::Sixteen, &pixels.iter().flat_map(|&p| vec![p, 0]).collect::<Vec<u8>>());
+        let heightmap = load_png_heightmap(&png, &options(2, 2)).unwrap();
+        let level = |i: usize| -1.0 + 10.0 * (pixels[i] as f32 * 256.0) / 65535.0;
+        assert_close(&heightmap.heights, &[level(0), level(4), level(15), level(19)]);
+
+        // Only one axis resampled
+        let heightmap = load_png_heightmap(&png, &options(0, 7)).unwrap();
+        assert_eq!((heightmap.width, heightmap.depth), (5, 7));
+        assert_close(&heightmap.heights[30..], &[level(15), level(16), level(17), level(18), level(19)]);
+    }
+
+    #[test]
+    fn grids_must_be_at_least_two_by_two() {
+        let strip = encode_png(3, 1, ColorType::Grayscale, BitDepth::Eight, &[0, 1, 2]);
+        assert!(load_png_heightmap(&strip, &options(0, 0)).is_err());
+        let square = encode_png(2, 2, ColorType::Grayscale, BitDepth::Eight, &[0; 4]);
+        assert!(load_png_heightmap(&square, &options(1, 4)).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use jpeg_decoder::PixelFormat;
+use png::{BitDepth, ColorType, Decoder, Transformations};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+// Start of image, then the first segment's marker prefix
+const JPEG_SIGNATURE: [u8; 3] = [0xFF, 0xD8, 0xFF];
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+pub fn is_png(data: &[u8]) -> bool {
+    data.len() >= PNG_SIGNATURE.len() && data[..PNG_SIGNATURE.len()] == PNG_SIGNATURE
+}
+
+pub fn is_jpeg(data: &[u8]) -> bool {
+    data.len() >= JPEG_SIGNATURE.len() && data[..JPEG_SIGNATURE.len()] == JPEG_SIGNATURE
+}
+
+// Decodes any PNG colour type into tightly packed 8-bit RGBA, which is what
+// the textures uploaded by the entities expect.
+pub fn decode_png_rgba(data: &[u8]) -> Result<Image, String> {
+    let mut decoder = Decoder::new(data);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(|e| format!("Invalid PNG: {}", e))?;
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).map_err(|e| format!("Invalid PNG: {}", e))?;
+
+    if info.bit_depth != BitDepth::Eight {
+        return Err(format!("Unsupported PNG bit depth {:?}", info.bit_depth));
+    }
+
+    let pixel_count = (info.width * info.height) as usize;
+    let rgba = match info.color_type {
+        ColorType::RGBA => pixels,
+        ColorType::RGB => pixels.chunks(3).take(pixel_count).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        ColorType::GrayscaleAlpha => pixels.chunks(2).take(pixel_count).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+        ColorType::Grayscale => pixels.iter().take(pixel_count).flat_map(|&p| vec![p, p, p, 255]).collect(),
+        ColorType::Indexed => return Err(String::from("Indexed PNG was not expanded")),
+    };
+
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        rgba,
+    })
+}
+
+// Decodes a grayscale or RGB JPEG into 8-bit RGBA like `decode_png_rgba`
+pub fn decode_jpeg_rgba(data: &[u8]) -> Result<Image, String> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder.decode().map_err(|e| format!("Invalid JPEG: {}", e))?;
+    let info = decoder.info().ok_or("Invalid JPEG: no frame header")?;
+
+    let pixel_count = info.width as usize * info.height as usize;
+    let rgba = match info.pixel_format {
+        PixelFormat::RGB24 => pixels.chunks(3).take(pixel_count).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        PixelFormat::L8 => pixels.iter().take(pixel_count).flat_map(|&p| vec![p, p, p, 255]).collect(),
+        PixelFormat::CMYK32 => return Err(String::from("Unsupported CMYK JPEG")),
+    };
+
+    Ok(Image {
+        width: info.width as u32,
+        height: info.height as u32,
+        rgba,
+    })
+}
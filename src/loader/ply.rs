@@ -0,0 +1,326 @@
+use super::points::PointCloudData;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+enum PropertyType {
+    Scalar(ScalarType),
+    List(ScalarType, ScalarType),
+}
+
+struct Property {
+    name: String,
+    property_type: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Reads the vertex element of an ASCII or binary little-endian PLY file.
+// Other elements (faces, edges, ...) are skipped.
+pub fn parse_ply(data: &[u8]) -> Result<PointCloudData, String> {
+    let (format, elements, body_start) = parse_header(data)?;
+    let mut reader = BodyReader::new(format, &data[body_start..]);
+
+    let mut positions = Vec::new();
+    let mut colours = Vec::new();
+    let mut has_colours = false;
+
+    for element in elements.iter() {
+        if element.name != "vertex" {
+            for _ in 0..element.count {
+                for property in element.properties.iter() {
+                    reader.skip_property(&property.property_type)?;
+                }
+            }
+            continue;
+        }
+
+        let find = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let (x, y, z) = match (find("x"), find("y"), find("z")) {
+            (Some(x), Some(y), Some(z)) => (x, y, z),
+            _ => return Err(String::from("PLY vertex element has no x, y and z properties")),
+        };
+        let colour_properties = match (find("red").or_else(|| find("r")), find("green").or_else(|| find("g")), find("blue").or_else(|| find("b"))) {
+            (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+            _ => None,
+        };
+        let colour_scales = match colour_properties {
+            Some(colour_properties) => {
+                let mut scales = [1.0; 3];
+                for (scale, &property) in scales.iter_mut().zip(colour_properties.iter()) {
+                    *scale = colour_scale(&element.properties[property])?;
+                }
+                Some(scales)
+            }
+            None => None,
+        };
+        has_colours = colour_properties.is_some();
+
+        // The count comes from the header, so it's only trusted as far as the
+        // body could hold it: every vertex takes at least a byte per coordinate
+        positions.reserve(3 * element.count.min(reader.remaining() / 3));
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            for (value, property) in values.iter_mut().zip(element.properties.iter()) {
+                *value = match property.property_type {
+                    PropertyType::Scalar(scalar_type) => reader.read_scalar(scalar_type)?,
+                    ref list => {
+                        reader.skip_property(list)?;
+                        0.0
+                    }
+                };
+            }
+            positions.extend_from_slice(&[values[x] as f32, values[y] as f32, values[z] as f32]);
+            if let (Some(colour_properties), Some(colour_scales)) = (colour_properties, colour_scales) {
+                for (&property, &scale) in colour_properties.iter().zip(colour_scales.iter()) {
+                    colours.push((values[property] / scale).clamp(0.0, 1.0) as f32);
+                }
+            }
+        }
+    }
+
+    Ok(PointCloudData {
+        positions,
+        colours: if has_colours { Some(colours) } else { None },
+    })
+}
+
+// What a colour property is divided by to bring it into 0..1. Floats are
+// taken to be in 0..1 already; other integer widths have no agreed range.
+fn colour_scale(property: &Property) -> Result<f64, String> {
+    match property.property_type {
+        PropertyType::Scalar(ScalarType::UInt8) => Ok(255.0),
+        PropertyType::Scalar(ScalarType::UInt16) => Ok(65535.0),
+        PropertyType::Scalar(ScalarType::Float32) | PropertyType::Scalar(ScalarType::Float64) => Ok(1.0),
+        PropertyType::Scalar(other) => Err(format!("Unsupported PLY colour type {:?} for '{}'", other, property.name)),
+        PropertyType::List(..) => Err(format!("PLY colour '{}' is a list", property.name)),
+    }
+}
+
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), String> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+    let mut first_line = true;
+
+    loop {
+        let line_end = data[offset..].iter().position(|&b| b == b'\n').ok_or("PLY header has no end_header")?;
+        let line = std::str::from_utf8(&data[offset..offset + line_end]).map_err(|_| "PLY header is not valid text")?.trim();
+        offset += line_end + 1;
+
+        if first_line {
+            if line != "ply" {
+                return Err(String::from("Not a PLY file"));
+            }
+            first_line = false;
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            ["format", other, _] => return Err(format!("Unsupported PLY format '{}'", other)),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| format!("Invalid PLY element count '{}'", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => elements.last_mut().ok_or("PLY property outside of an element")?.properties.push(Property {
+                name: name.to_string(),
+                property_type: PropertyType::List(parse_scalar_type(count_type)?, parse_scalar_type(item_type)?),
+            }),
+            ["property", scalar_type, name] => elements.last_mut().ok_or("PLY property outside of an element")?.properties.push(Property {
+                name: name.to_string(),
+                property_type: PropertyType::Scalar(parse_scalar_type(scalar_type)?),
+            }),
+            ["end_header"] => break,
+            _ => {} // comments, obj_info and blank lines
+        }
+    }
+
+    Ok((format.ok_or("PLY header has no format")?, elements, offset))
+}
+
+fn parse_scalar_type(name: &str) -> Result<ScalarType, String> {
+    match name {
+        "char" | "int8" => Ok(ScalarType::Int8),
+        "uchar" | "uint8" => Ok(ScalarType::UInt8),
+        "short" | "int16" => Ok(ScalarType::Int16),
+        "ushort" | "uint16" => Ok(ScalarType::UInt16),
+        "int" | "int32" => Ok(ScalarType::Int32),
+        "uint" | "uint32" => Ok(ScalarType::UInt32),
+        "float" | "float32" => Ok(ScalarType::Float32),
+        "double" | "float64" => Ok(ScalarType::Float64),
+        _ => Err(format!("Unknown PLY property type '{}'", name)),
+    }
+}
+
+impl ScalarType {
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+}
+
+struct BodyReader<'a> {
+    format: Format,
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BodyReader<'a> {
+    fn new(format: Format, data: &'a [u8]) -> Self {
+        Self { format, data, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    fn read_scalar(&mut self, scalar_type: ScalarType) -> Result<f64, String> {
+        match self.format {
+            Format::Ascii => {
+                let word = self.next_word().ok_or("PLY body ended early")?;
+                word.parse::<f64>().map_err(|_| format!("Invalid PLY value '{}'", word))
+            }
+            Format::BinaryLittleEndian => {
+                let size = scalar_type.size();
+                let bytes = self.data.get(self.offset..self.offset + size).ok_or("PLY body ended early")?;
+                self.offset += size;
+                let mut buffer = [0; 8];
+                buffer[..size].copy_from_slice(bytes);
+                Ok(match scalar_type {
+                    ScalarType::Int8 => buffer[0] as i8 as f64,
+                    ScalarType::UInt8 => buffer[0] as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+
+    fn skip_property(&mut self, property_type: &PropertyType) -> Result<(), String> {
+        match *property_type {
+            PropertyType::Scalar(scalar_type) => {
+                self.read_scalar(scalar_type)?;
+            }
+            PropertyType::List(count_type, item_type) => {
+                let count = self.read_scalar(count_type)? as usize;
+                for _ in 0..count {
+                    self.read_scalar(item_type)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn next_word(&mut self) -> Option<&'a str> {
+        while self.offset < self.data.len() && self.data[self.offset].is_ascii_whitespace() {
+            self.offset += 1;
+        }
+        let start = self.offset;
+        while self.offset < self.data.len() && !self.data[self.offset].is_ascii_whitespace() {
+            self.offset += 1;
+        }
+        if start == self.offset {
+            None
+        } else {
+            std::str::from_utf8(&self.data[start..self.offset]).ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary_ply(header: &str, body: &[u8]) -> Vec<u8> {
+        let mut data = format!("ply\nformat binary_little_endian 1.0\n{}end_header\n", header).into_bytes();
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn reads_ascii_vertices_and_skips_faces() {
+        let data = b"ply\nformat ascii 1.0\ncomment test\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+element face 1\nproperty list uchar int vertex_indices\nend_header\n0 1 2\n3 4 5\n3 0 1 1\n";
+        let cloud = parse_ply(data).unwrap();
+        assert_eq!(cloud.positions, [0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert!(cloud.colours.is_none());
+    }
+
+    #[test]
+    fn colours_are_normalised_by_type() {
+        let data = b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+property uchar red\nproperty ushort green\nproperty float blue\nend_header\n0 0 0 255 65535 0.5\n0 0 0 51 0 1.5\n";
+        let colours = parse_ply(data).unwrap().colours.unwrap();
+        assert_eq!(colours, [1.0, 1.0, 0.5, 0.2, 0.0, 1.0]);
+
+        let mut body = Vec::new();
+        for value in [1.0f64, 2.0, 3.0, 0.25].iter() {
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        body.extend_from_slice(&(-0.5f32).to_le_bytes());
+        body.extend_from_slice(&0.75f32.to_le_bytes());
+        let data = binary_ply(
+            "element vertex 1\nproperty double x\nproperty double y\nproperty double z\nproperty double r\nproperty float g\nproperty float b\n",
+            &body,
+        );
+        let cloud = parse_ply(&data).unwrap();
+        assert_eq!(cloud.positions, [1.0, 2.0, 3.0]);
+        assert_eq!(cloud.colours.unwrap(), [0.25, 0.0, 0.75]);
+    }
+
+    #[test]
+    fn other_colour_types_are_rejected() {
+        for colour_type in ["char", "short", "int", "uint"].iter() {
+            let data = format!(
+                "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\n\
+property {0} red\nproperty {0} green\nproperty {0} blue\nend_header\n0 0 0 1 1 1\n",
+                colour_type
+            );
+            assert!(parse_ply(data.as_bytes()).err().unwrap().contains("colour"), "{}", colour_type);
+        }
+    }
+
+    #[test]
+    fn bogus_counts_fail_on_the_body() {
+        let header = "element vertex 4000000000\nproperty float x\nproperty float y\nproperty float z\n";
+        let data = binary_ply(header, &[0; 12]);
+        assert_eq!(parse_ply(&data).err().unwrap(), "PLY body ended early");
+
+        let data = format!("ply\nformat ascii 1.0\n{}end_header\n0 0 0\n", header);
+        assert_eq!(parse_ply(data.as_bytes()).err().unwrap(), "PLY body ended early");
+
+        let data = binary_ply("element vertex 18446744073709551615\nproperty float x\nproperty float y\nproperty float z\n", &[]);
+        assert!(parse_ply(&data).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub struct PointCloudData {
+    pub positions: Vec<f32>,
+    pub colours: Option<Vec<f32>>,
+}
@@ -0,0 +1,84 @@
+use super::points::PointCloudData;
+
+// Plain text point lists: one `x y z [r g b]` record per line, separated by
+// whitespace, commas or semicolons. Lines starting with '#' or '//' are comments. Colours
+// are treated as 0-255 when any component in the file is above 1.
+pub fn parse_xyz(text: &str) -> Result<PointCloudData, String> {
+    let mut positions = Vec::new();
+    let mut colours = Vec::new();
+    let mut all_coloured = true;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        let values = line
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|word| !word.is_empty())
+            .map(|word| word.parse::<f32>().map_err(|_| format!("Invalid number '{}' on line {}", word, line_number + 1)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if values.len() < 3 {
+            return Err(format!("Expected at least 3 values on line {}", line_number + 1));
+        }
+        positions.extend_from_slice(&values[..3]);
+        if values.len() >= 6 {
+            colours.extend_from_slice(&values[3..6]);
+        } else {
+            all_coloured = false;
+        }
+    }
+
+    let colours = if all_coloured && !positions.is_empty() {
+        if colours.iter().any(|&c| c > 1.0) {
+            colours.iter_mut().for_each(|c| *c /= 255.0);
+        }
+        Some(colours)
+    } else {
+        None
+    };
+
+    Ok(PointCloudData { positions, colours })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separators_comments_and_blank_lines() {
+        let cloud = parse_xyz("# x y z\n\n1 2 3\n  // more\n4,5,6\n7;8; 9\n\t10 , 11 ,12  \n").unwrap();
+        assert_eq!(cloud.positions, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0]);
+        assert!(cloud.colours.is_none());
+        assert!(parse_xyz("").unwrap().positions.is_empty());
+    }
+
+    #[test]
+    fn colours_above_one_are_bytes() {
+        let bytes = parse_xyz("0 0 0 255 0 51\n1 1 1 0 102 0\n").unwrap();
+        assert_eq!(bytes.colours.unwrap(), [1.0, 0.0, 0.2, 0.0, 0.4, 0.0]);
+
+        // Nothing above 1 anywhere in the file, so every colour is a fraction
+        let fractions = parse_xyz("0 0 0 1 0 0.5\n1 1 1 0.25 1 0\n").unwrap();
+        assert_eq!(fractions.colours.unwrap(), [1.0, 0.0, 0.5, 0.25, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn colours_need_every_line_coloured() {
+        let mixed = parse_xyz("0 0 0 255 0 0\n1 1 1\n").unwrap();
+        assert_eq!(mixed.positions, [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+        assert!(mixed.colours.is_none());
+
+        // Anything after the colour, such as an intensity, is ignored
+        let extra = parse_xyz("0 0 0 1 1 1 0.5\n").unwrap();
+        assert_eq!(extra.colours.unwrap(), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn bad_lines_are_named() {
+        assert!(parse_xyz("1 2 3\n1 2\n").err().unwrap().contains("line 2"));
+        assert!(parse_xyz("1 2 x\n").err().unwrap().contains("'x' on line 1"));
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+
+// Marching cubes over a 3D grid of samples, stored x fastest, then y, then z.
+// Samples at or above the level count as inside.
+//
+// Rather than the usual hand-written table, each cube case is triangulated by
+// linking the crossed edges around the cube's faces into loops. Faces with
+// two inside corners diagonally opposite always keep those corners apart, and
+// as that only depends on the face itself, neighbouring cubes agree on it and
+// the surface has no cracks. The links are directed so that every loop, and
+// every triangle fanned from one, winds anticlockwise seen from outside the
+// surface, where the samples are below the level.
+
+// Corner i of a cube is offset by (i & 1, (i >> 1) & 1, (i >> 2) & 1)
+const CORNERS: usize = 8;
+const EDGES: usize = 12;
+
+lazy_static! {
+    // For each of the 256 corner cases, the loops of crossed edges
+    static ref CASE_LOOPS: Vec<Vec<Vec<usize>>> = (0..256).map(|case| case_loops(case as u8)).collect();
+}
+
+// Edge i as (corner, axis), running from that corner one step along the axis
+fn edges() -> [(usize, usize); EDGES] {
+    let mut edges = [(0, 0); EDGES];
+    let mut i = 0;
+    for axis in 0..3 {
+        for corner in (0..CORNERS).filter(|corner| corner & (1 << axis) == 0) {
+            edges[i] = (corner, axis);
+            i += 1;
+        }
+    }
+    edges
+}
+
+fn edge_between(a: usize, b: usize) -> usize {
+    let (corner, axis) = (a.min(b), (a ^ b).trailing_zeros() as usize);
+    edges().iter().position(|&edge| edge == (corner, axis)).unwrap()
+}
+
+fn case_loops(case: u8) -> Vec<Vec<usize>> {
+    let inside = |corner: usize| case & (1 << corner) != 0;
+    let mut next: [Option<usize>; EDGES] = [None; EDGES];
+
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for side in 0..2 {
+            let base = side << axis;
+            // Corners round the face, anticlockwise seen from outside the
+            // cube, and the edge from each to the next
+            let mut corners = [base, base | (1 << u), base | (1 << u) | (1 << v), base | (1 << v)];
+            if side == 0 {
+                corners.reverse();
+            }
+            let face_edges: Vec<usize> = (0..4).map(|i| edge_between(corners[i], corners[(i + 1) % 4])).collect();
+            let leaves = |i: usize| inside(corners[i]) && !inside(corners[(i + 1) % 4]);
+            let enters = |i: usize| !inside(corners[i]) && inside(corners[(i + 1) % 4]);
+
+            // Cut from where the walk round the face last came in to the
+            // inside corners to where it leaves them. With two inside corners
+            // diagonally opposite this cuts around each of them.
+            for i in (0..4).filter(|&i| leaves(i)) {
+                let entered = (1..4).map(|back| (i + 4 - back) % 4).find(|&j| enters(j)).unwrap();
+                next[face_edges[entered]] = Some(face_edges[i]);
+            }
+        }
+    }
+
+    // Every crossed edge is on two faces, which run along it in opposite
+    // directions, so it's left on one and entered on the other. The links
+    // form closed loops.
+    let mut visited = [false; EDGES];
+    let mut loops = Vec::new();
+    for start in 0..EDGES {
+        if visited[start] || next[start].is_none() {
+            continue;
+        }
+        let mut edge_loop = Vec::new();
+        let mut current = start;
+        loop {
+            edge_loop.push(current);
+            visited[current] = true;
+            current = next[current].unwrap();
+            if current == start {
+                break;
+            }
+        }
+        loops.push(edge_loop);
+    }
+    loops
+}
+
+pub struct IsoMesh {
+    pub positions: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+// The surface where the samples cross `level`, with the grid spread from `min`
+// to `max`. Vertices are shared between neighbouring cubes, and normals come
+// from the field's gradient, pointing from inside to outside. Cubes with a
+// non-finite sample are skipped.
+pub fn extract_isosurface(values: &[f32], size: [usize; 3], min: [f32; 3], max: [f32; 3], level: f32) -> IsoMesh {
+    let [nx, ny, nz] = size;
+    let index = |x: usize, y: usize, z: usize| x + nx * (y + ny * z);
+    let step: Vec<f32> = (0..3).map(|axis| (max[axis] - min[axis]) / (size[axis] - 1) as f32).collect();
+    let edges = edges();
+
+    let mut mesh = IsoMesh {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        indices: Vec::new(),
+    };
+    // Keyed by the grid point an edge starts from and its axis
+    let mut edge_vertices: HashMap<(usize, usize), u32> = HashMap::new();
+
+    for z in 0..nz.saturating_sub(1) {
+        for y in 0..ny.saturating_sub(1) {
+            for x in 0..nx.saturating_sub(1) {
+                let corner_point = |corner: usize| [x + (corner & 1), y + ((corner >> 1) & 1), z + ((corner >> 2) & 1)];
+                let corner_values: Vec<f32> = (0..CORNERS)
+                    .map(|corner| {
+                        let [cx, cy, cz] = corner_point(corner);
+                        values[index(cx, cy, cz)]
+                    })
+                    .collect();
+                if corner_values.iter().any(|value| !value.is_finite()) {
+                    continue;
+                }
+                let case = corner_values
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |case, (i, &value)| if value >= level { case | (1 << i) } else { case });
+
+                for edge_loop in CASE_LOOPS[case as usize].iter() {
+                    let loop_vertices: Vec<u32> = edge_loop
+                        .iter()
+                        .map(|&edge| {
+                            let (corner, axis) = edges[edge];
+                            let start = corner_point(corner);
+                            *edge_vertices.entry((index(start[0], start[1], start[2]), axis)).or_insert_with(|| {
+                                let (a, b) = (corner_values[corner], corner_values[corner | (1 << axis)]);
+                                let t = (level - a) / (b - a);
+                                let mut end = start;
+                                end[axis] += 1;
+
+                                let gradient_start = gradient(values, size, &step, start);
+                                let gradient_end = gradient(values, size, &step, end);
+                                let mut normal = [0.0; 3];
+                                for i in 0..3 {
+                                    let position = min[i] + (start[i] as f32 + if i == axis { t } else { 0.0 }) * step[i];
+                                    mesh.positions.push(position);
+                                    normal[i] = -(gradient_start[i] + t * (gradient_end[i] - gradient_start[i]));
+                                }
+                                let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+                                if length > 0.0 {
+                                    mesh.normals.extend(normal.iter().map(|n| n / length));
+                                } else {
+                                    mesh.normals.extend_from_slice(&[0.0, 1.0, 0.0]);
+                                }
+                                (mesh.positions.len() / 3 - 1) as u32
+                            })
+                        })
+                        .collect();
+
+                    for i in 1..loop_vertices.len() - 1 {
+                        mesh.indices.extend_from_slice(&[loop_vertices[0], loop_vertices[i], loop_vertices[i + 1]]);
+                    }
+                }
+            }
+        }
+    }
+    mesh
+}
+
+// Samples `function(x, y, z)` on a grid spread from `min` to `max`, in the
+// order extract_isosurface reads them
+pub fn sample_grid(function: &dyn Fn(f32, f32, f32) -> f32, size: [usize; 3], min: [f32; 3], max: [f32; 3]) -> Vec<f32> {
+    let step: Vec<f32> = (0..3).map(|axis| (max[axis] - min[axis]) / (size[axis] - 1) as f32).collect();
+    let mut values = Vec::with_capacity(size[0] * size[1] * size[2]);
+    for z in 0..size[2] {
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                values.push(function(min[0] + x as f32 * step[0], min[1] + y as f32 * step[1], min[2] + z as f32 * step[2]));
+            }
+        }
+    }
+    values
+}
+
+// Central differences, or one-sided along the grid's faces
+fn gradient(values: &[f32], size: [usize; 3], step: &[f32], point: [usize; 3]) -> [f32; 3] {
+    let index = |p: [usize; 3]| p[0] + size[0] * (p[1] + size[1] * p[2]);
+    let mut gradient = [0.0; 3];
+    for axis in 0..3 {
+        let (mut before, mut after) = (point, point);
+        before[axis] = point[axis].saturating_sub(1);
+        after[axis] = (point[axis] + 1).min(size[axis] - 1);
+        gradient[axis] = (values[index(after)] - values[index(before)]) / ((after[axis] - before[axis]) as f32 * step[axis]);
+    }
+    gradient
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn vertex(mesh: &IsoMesh, i: u32) -> [f32; 3] {
+        let i = 3 * i as usize;
+        [mesh.positions[i], mesh.positions[i + 1], mesh.positions[i + 2]]
+    }
+
+    fn facet_normal(mesh: &IsoMesh, triangle: &[u32]) -> [f32; 3] {
+        let [a, b, c] = [vertex(mesh, triangle[0]), vertex(mesh, triangle[1]), vertex(mesh, triangle[2])];
+        let (ab, ac) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+        [ab[1] * ac[2] - ab[2] * ac[1], ab[2] * ac[0] - ab[0] * ac[2], ab[0] * ac[1] - ab[1] * ac[0]]
+    }
+
+    fn dot(a: [f32; 3], b: &[f32]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    // Each edge runs one way in one triangle and the other way in the next,
+    // so neighbouring triangles face the same side
+    fn consistently_wound(mesh: &IsoMesh) -> bool {
+        let mut directed = HashSet::new();
+        mesh.indices
+            .chunks(3)
+            .flat_map(|t| vec![(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .all(|edge| directed.insert(edge))
+    }
+
+    #[test]
+    fn every_case_closes_its_loops() {
+        for (case, loops) in CASE_LOOPS.iter().enumerate() {
+            let crossed = (0..EDGES)
+                .filter(|&edge| {
+                    let (corner, axis) = edges()[edge];
+                    (case >> corner) & 1 != (case >> (corner | (1 << axis))) & 1
+                })
+                .count();
+            assert_eq!(loops.iter().map(|l| l.len()).sum::<usize>(), crossed, "case {}", case);
+            assert!(loops.iter().all(|l| l.len() >= 3), "case {}", case);
+        }
+        assert!(CASE_LOOPS[0].is_empty());
+        assert!(CASE_LOOPS[255].is_empty());
+    }
+
+    #[test]
+    fn single_corner_gives_one_triangle() {
+        let mut values = vec![0.0; 8];
+        values[0] = 1.0;
+        let mesh = extract_isosurface(&values, [2, 2, 2], [0.0; 3], [1.0; 3], 0.5);
+        assert_eq!(mesh.indices.len(), 3);
+        let mut points: Vec<[f32; 3]> = mesh.indices.iter().map(|&i| vertex(&mesh, i)).collect();
+        // Anticlockwise seen from outside, away from the inside corner
+        assert!(dot(facet_normal(&mesh, &mesh.indices), &[1.0, 1.0, 1.0]) > 0.0);
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(points, vec![[0.0, 0.0, 0.5], [0.0, 0.5, 0.0], [0.5, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn triangles_face_the_way_of_the_field() {
+        // Scrambled values turn up plenty of cases with ambiguous faces
+        let size = [7, 6, 5];
+        let mut seed = 12345u32;
+        let values: Vec<f32> = (0..size[0] * size[1] * size[2])
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as f32 / 65536.0
+            })
+            .collect();
+        let mesh = extract_isosurface(&values, size, [0.0; 3], [1.0; 3], 0.5);
+        assert!(consistently_wound(&mesh));
+
+        // And on a smooth field they turn the same way as the normals
+        let size = [16, 16, 16];
+        let values = sample_grid(&|x, y, z| (3.0 * x).sin() + (3.0 * y).cos() * (2.0 * z).sin(), size, [-2.0; 3], [2.0; 3]);
+        let mesh = extract_isosurface(&values, size, [-2.0; 3], [2.0; 3], 0.3);
+        assert!(consistently_wound(&mesh));
+        for triangle in mesh.indices.chunks(3) {
+            let normal = facet_normal(&mesh, triangle);
+            let agree = triangle.iter().filter(|&&i| dot(normal, &mesh.normals[3 * i as usize..]) > 0.0).count();
+            assert!(agree >= 2, "{:?}", triangle);
+        }
+    }
+
+    #[test]
+    fn plane_lies_on_the_level() {
+        let size = [5, 4, 3];
+        let values = sample_grid(&|x, _, _| x, size, [-1.0; 3], [1.0; 3]);
+        let mesh = extract_isosurface(&values, size, [-1.0; 3], [1.0; 3], 0.25);
+        assert!(!mesh.indices.is_empty());
+        for (i, normal) in mesh.normals.chunks(3).enumerate() {
+            assert!((mesh.positions[3 * i] - 0.25).abs() < 1e-5);
+            assert!((normal[0] + 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn sphere_is_closed_and_round() {
+        let size = [12, 12, 12];
+        let values = sample_grid(&|x, y, z| 1.0 - (x * x + y * y + z * z), size, [-1.0; 3], [1.0; 3]);
+        let mesh = extract_isosurface(&values, size, [-1.0; 3], [1.0; 3], 0.75);
+
+        // Every edge is shared by exactly two triangles
+        let mut edge_counts: HashMap<(u32, u32), usize> = HashMap::new();
+        for triangle in mesh.indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                *edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        assert!(edge_counts.values().all(|&count| count == 2));
+        assert!(consistently_wound(&mesh));
+        for triangle in mesh.indices.chunks(3) {
+            let centre = vertex(&mesh, triangle[0]);
+            assert!(dot(facet_normal(&mesh, triangle), &centre) > 0.0);
+        }
+
+        for (i, normal) in mesh.normals.chunks(3).enumerate() {
+            let p = vertex(&mesh, i as u32);
+            let radius = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt();
+            assert!((radius - 0.5).abs() < 0.05, "radius {}", radius);
+            // Outwards, away from the high values in the middle
+            let outward = (p[0] * normal[0] + p[1] * normal[1] + p[2] * normal[2]) / radius;
+            assert!(outward > 0.9, "normal {:?} at {:?}", normal, p);
+        }
+    }
+}
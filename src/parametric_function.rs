@@ -0,0 +1,53 @@
+use wasm_bindgen::JsValue;
+
+// Supplies the points of a ParametricSurface. `u` and `v` are positions in
+// the surface's parameter ranges and `t` is the time in seconds.
+pub trait ParametricFunction {
+    fn point(&self, u: f32, v: f32, t: f32) -> [f32; 3];
+
+    // Named inputs such as the user parameters of a ParametricExpression
+    fn set_parameter(&mut self, name: &str, _value: f32) -> Result<(), String> {
+        Err(format!("Parametric function has no parameter '{}'", name))
+    }
+}
+
+impl<F: Fn(f32, f32, f32) -> [f32; 3]> ParametricFunction for F {
+    fn point(&self, u: f32, v: f32, t: f32) -> [f32; 3] {
+        self(u, v, t)
+    }
+}
+
+// A unit sphere, with u the longitude over [0, 2pi] and v the angle from the
+// north pole over [0, pi]
+pub fn sphere(u: f32, v: f32, _t: f32) -> [f32; 3] {
+    [v.sin() * u.cos(), v.cos(), v.sin() * u.sin()]
+}
+
+// A JS function called as `callback(u, v, t)` that returns [x, y, z].
+// Exceptions and non-numeric coordinates are drawn as zero.
+pub struct JsParametricFunction {
+    callback: js_sys::Function,
+}
+
+impl JsParametricFunction {
+    pub fn new(callback: js_sys::Function) -> Self {
+        Self { callback }
+    }
+}
+
+impl ParametricFunction for JsParametricFunction {
+    fn point(&self, u: f32, v: f32, t: f32) -> [f32; 3] {
+        let point = self
+            .callback
+            .call3(&JsValue::NULL, &JsValue::from_f64(u as f64), &JsValue::from_f64(v as f64), &JsValue::from_f64(t as f64))
+            .unwrap_or(JsValue::UNDEFINED);
+        let coordinate = |index: u32| {
+            js_sys::Reflect::get_u32(&point, index)
+                .ok()
+                .and_then(|value| value.as_f64())
+                .filter(|value| value.is_finite())
+                .unwrap_or(0.0) as f32
+        };
+        [coordinate(0), coordinate(1), coordinate(2)]
+    }
+}
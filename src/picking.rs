@@ -0,0 +1,227 @@
+use nalgebra_glm as glm;
+
+// Ray casting against a height field, for finding what's under the cursor.
+// Height fields are in grid units here: points are (column, height, row), with
+// columns running from 0 to width - 1 and rows from 0 to depth - 1, and each
+// cell is split into two triangles the same way grid_indices splits it.
+
+// Hits this close outside a triangle still count, so that rays down a shared
+// edge can't slip between the two triangles
+const EDGE_TOLERANCE: f32 = 1e-5;
+
+pub struct Ray {
+    pub origin: glm::Vec3,
+    // Not normalised: the ray runs from `origin` at t = 0 to `origin +
+    // direction` at t = 1, and nothing beyond is hit
+    pub direction: glm::Vec3,
+}
+
+impl Ray {
+    // The segment under a point in normalised device coordinates, from the near
+    // plane to the far plane, in the space `inverse_model_view_projection`
+    // maps back into
+    pub fn from_screen(inverse_model_view_projection: &glm::Mat4, ndc_x: f32, ndc_y: f32) -> Option<Self> {
+        let unproject = |ndc_z: f32| {
+            let point = inverse_model_view_projection * glm::vec4(ndc_x, ndc_y, ndc_z, 1.0);
+            if point.w.abs() < f32::EPSILON {
+                None
+            } else {
+                Some(glm::vec3(point.x, point.y, point.z) / point.w)
+            }
+        };
+        let near = unproject(-1.0)?;
+        let far = unproject(1.0)?;
+        Some(Self {
+            origin: near,
+            direction: far - near,
+        })
+    }
+
+    pub fn at(&self, t: f32) -> glm::Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+// The nearest point where `ray` hits a row-major height field, `width` samples
+// per row and `depth` rows. Cells are visited in the order the ray crosses
+// them, so the first hit is the nearest. Triangles with a non-finite corner
+// are holes.
+pub fn intersect_height_field(ray: &Ray, heights: &[f32], width: usize, depth: usize) -> Option<glm::Vec3> {
+    if width < 2 || depth < 2 {
+        return None;
+    }
+    let last_cell = [width as isize - 2, depth as isize - 2];
+
+    // The part of the ray over the grid's columns and rows
+    let (mut t_enter, mut t_exit) = (0.0f32, 1.0f32);
+    for &(axis, max) in [(0, (width - 1) as f32), (2, (depth - 1) as f32)].iter() {
+        let (origin, direction) = (ray.origin[axis], ray.direction[axis]);
+        if direction == 0.0 {
+            if origin < 0.0 || origin > max {
+                return None;
+            }
+        } else {
+            let (a, b) = (-origin / direction, (max - origin) / direction);
+            t_enter = t_enter.max(a.min(b));
+            t_exit = t_exit.min(a.max(b));
+        }
+    }
+    if t_enter > t_exit {
+        return None;
+    }
+
+    // Walk the cells under the ray, one column or row boundary at a time
+    let start = ray.at(t_enter);
+    let mut cell = [0isize; 2];
+    let mut step = [0isize; 2];
+    let mut t_next = [f32::INFINITY; 2];
+    let mut t_delta = [f32::INFINITY; 2];
+    for (i, &axis) in [0, 2].iter().enumerate() {
+        cell[i] = (start[axis].floor() as isize).clamp(0, last_cell[i]);
+        let direction = ray.direction[axis];
+        if direction != 0.0 {
+            let boundary = if direction > 0.0 { cell[i] + 1 } else { cell[i] };
+            step[i] = if direction > 0.0 { 1 } else { -1 };
+            t_next[i] = (boundary as f32 - ray.origin[axis]) / direction;
+            t_delta[i] = (1.0 / direction).abs();
+        }
+    }
+
+    loop {
+        if let Some(t) = intersect_cell(ray, heights, width, cell[0] as usize, cell[1] as usize) {
+            return Some(ray.at(t));
+        }
+        let i = if t_next[0] < t_next[1] { 0 } else { 1 };
+        if t_next[i] > t_exit {
+            return None;
+        }
+        cell[i] += step[i];
+        t_next[i] += t_delta[i];
+        if cell[i] < 0 || cell[i] > last_cell[i] {
+            return None;
+        }
+    }
+}
+
+// The nearest hit on the two triangles of a cell
+fn intersect_cell(ray: &Ray, heights: &[f32], width: usize, column: usize, row: usize) -> Option<f32> {
+    let corner = |column: usize, row: usize| glm::vec3(column as f32, heights[row * width + column], row as f32);
+    let top_left = corner(column, row);
+    let bottom_left = corner(column, row + 1);
+    let bottom_right = corner(column + 1, row + 1);
+    let top_right = corner(column + 1, row);
+
+    let first = intersect_triangle(ray, &top_left, &bottom_left, &bottom_right);
+    let second = intersect_triangle(ray, &top_left, &bottom_right, &top_right);
+    match (first, second) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// Möller-Trumbore, hitting either side
+fn intersect_triangle(ray: &Ray, a: &glm::Vec3, b: &glm::Vec3, c: &glm::Vec3) -> Option<f32> {
+    if [a.y, b.y, c.y].iter().any(|height| !height.is_finite()) {
+        return None;
+    }
+    let (edge_ab, edge_ac) = (b - a, c - a);
+    let p = glm::cross(&ray.direction, &edge_ac);
+    let determinant = glm::dot(&edge_ab, &p);
+    if determinant == 0.0 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = ray.origin - a;
+    let u = glm::dot(&s, &p) * inverse;
+    if !(-EDGE_TOLERANCE..=1.0 + EDGE_TOLERANCE).contains(&u) {
+        return None;
+    }
+    let q = glm::cross(&s, &edge_ab);
+    let v = glm::dot(&ray.direction, &q) * inverse;
+    if v < -EDGE_TOLERANCE || u + v > 1.0 + EDGE_TOLERANCE {
+        return None;
+    }
+    let t = glm::dot(&edge_ac, &q) * inverse;
+    if (0.0..=1.0).contains(&t) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: glm::Vec3, b: glm::Vec3) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: glm::vec3(origin[0], origin[1], origin[2]),
+            direction: glm::vec3(direction[0], direction[1], direction[2]),
+        }
+    }
+
+    #[test]
+    fn straight_down_onto_a_flat_grid() {
+        let heights = vec![0.5; 16];
+        let hit = intersect_height_field(&ray([1.25, 10.0, 2.5], [0.0, -20.0, 0.0]), &heights, 4, 4).unwrap();
+        assert!(close(hit, glm::vec3(1.25, 0.5, 2.5)), "{:?}", hit);
+    }
+
+    #[test]
+    fn slanted_ray_onto_a_slope() {
+        // Height equals the column, so the plane is y = x
+        let (width, depth) = (5, 3);
+        let heights: Vec<f32> = (0..width * depth).map(|i| (i % width) as f32).collect();
+        // Crosses y = x at x = 3
+        let hit = intersect_height_field(&ray([0.0, 6.0, 1.0], [8.0, -8.0, 0.0]), &heights, width, depth).unwrap();
+        assert!(close(hit, glm::vec3(3.0, 3.0, 1.0)), "{:?}", hit);
+    }
+
+    #[test]
+    fn nearest_hit_wins() {
+        // A wall of height 2 along column 3 in front of a floor at 0
+        let (width, depth) = (6, 2);
+        let heights: Vec<f32> = (0..width * depth).map(|i| if i % width == 3 { 2.0 } else { 0.0 }).collect();
+        let hit = intersect_height_field(&ray([0.0, 1.0, 0.5], [5.0, -1.0, 0.0]), &heights, width, depth).unwrap();
+        // The ray falls 0.2 per column and meets the rising side of the wall
+        // before it could reach the floor at column 5
+        assert!(hit.x > 2.0 && hit.x < 3.0, "{:?}", hit);
+        assert!((hit.y - (1.0 - hit.x / 5.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rays_that_miss() {
+        let heights = vec![0.0; 9];
+        // Passes over the grid
+        assert!(intersect_height_field(&ray([-1.0, 1.0, 1.0], [4.0, 0.0, 0.0]), &heights, 3, 3).is_none());
+        // Points away from it
+        assert!(intersect_height_field(&ray([1.0, 1.0, 1.0], [0.0, 1.0, 0.0]), &heights, 3, 3).is_none());
+        // Stops short of it
+        assert!(intersect_height_field(&ray([1.0, 1.0, 1.0], [0.0, -0.5, 0.0]), &heights, 3, 3).is_none());
+        // Lands outside it
+        assert!(intersect_height_field(&ray([5.0, 1.0, 1.0], [0.0, -2.0, 0.0]), &heights, 3, 3).is_none());
+    }
+
+    #[test]
+    fn holes_are_not_hit() {
+        let mut heights = vec![0.0; 9];
+        heights[4] = f32::NAN;
+        assert!(intersect_height_field(&ray([0.5, 1.0, 0.5], [0.0, -2.0, 0.0]), &heights, 3, 3).is_none());
+    }
+
+    #[test]
+    fn screen_rays_pass_through_what_they_were_projected_from() {
+        let projection = glm::perspective(1.5, 0.8, 0.1, 100.0) * glm::look_at(&glm::vec3(0.0, 4.0, 3.0), &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        let point = glm::vec3(0.3, -0.2, 0.5);
+        let clip = projection * glm::vec4(point.x, point.y, point.z, 1.0);
+        let ray = Ray::from_screen(&projection.try_inverse().unwrap(), clip.x / clip.w, clip.y / clip.w).unwrap();
+
+        // The closest point on the ray to `point` is `point` itself
+        let t = glm::dot(&(point - ray.origin), &ray.direction) / glm::dot(&ray.direction, &ray.direction);
+        assert!(close(ray.at(t), point), "{:?}", ray.at(t));
+    }
+}
@@ -5,20 +5,28 @@ use web_sys::*;
 
 pub struct Shader {
     program: WebGlProgram,
-    uniforms: RefCell<HashMap<String, WebGlUniformLocation>>,
+    uniforms: RefCell<HashMap<String, Option<WebGlUniformLocation>>>,
 }
 
 impl Shader {
     pub fn new(gl: &WebGlRenderingContext, vertex_code: &str, fragment_code: &str) -> Result<Self, String> {
         let program = gl.create_program().ok_or_else(|| String::from("Error creating program")).unwrap();
-        let vertex_shader = Self::compile_shader(gl, GL::VERTEX_SHADER, vertex_code).unwrap();
+        let vertex_shader = Self::compile_shader(gl, GL::VERTEX_SHADER, vertex_code)?;
         gl.attach_shader(&program, &vertex_shader);
-        let fragment_shader = Self::compile_shader(gl, GL::FRAGMENT_SHADER, fragment_code).unwrap();
+        let fragment_shader = Self::compile_shader(gl, GL::FRAGMENT_SHADER, fragment_code)?;
         gl.attach_shader(&program, &fragment_shader);
+
+        // Entities bake attribute locations into their vertex arrays, so pin
+        // them to declaration order rather than leaving it to the linker
+        let mut location = 0;
+        for (name, slots) in Self::attribute_declarations(vertex_code) {
+            gl.bind_attrib_location(&program, location, name);
+            location += slots;
+        }
         gl.link_program(&program);
         if gl.get_program_parameter(&program, WebGlRenderingContext::LINK_STATUS).as_bool().unwrap_or(false) {
             Ok(Self {
-                program: program,
+                program,
                 uniforms: RefCell::new(HashMap::new()),
             })
         } else {
@@ -31,15 +39,40 @@ impl Shader {
     }
 
     pub fn get_uniform_location(&self, gl: &WebGlRenderingContext, uniform_name: &str) -> Option<WebGlUniformLocation> {
+        Some(
+            self.find_uniform_location(gl, uniform_name)
+                .unwrap_or_else(|| panic!("Uniform '{}' not found", uniform_name)),
+        )
+    }
+
+    // For uniforms that may have been compiled out, as in generated shaders
+    // where the code using them depends on the input
+    pub fn find_uniform_location(&self, gl: &WebGlRenderingContext, uniform_name: &str) -> Option<WebGlUniformLocation> {
         let mut uniforms = self.uniforms.borrow_mut();
-        if uniforms.get(uniform_name).is_none() {
-            uniforms.insert(
-                uniform_name.to_string(),
-                gl.get_uniform_location(&self.program, uniform_name)
-                    .expect(&format!("Uniform '{}' not found", uniform_name)),
-            );
+        if !uniforms.contains_key(uniform_name) {
+            uniforms.insert(uniform_name.to_string(), gl.get_uniform_location(&self.program, uniform_name));
         }
-        Some(uniforms.get(uniform_name).expect("loc").clone())
+        uniforms.get(uniform_name).expect("loc").clone()
+    }
+
+    // Attribute names with the number of locations each one occupies, as
+    // matrix attributes take one location per column
+    fn attribute_declarations(vertex_code: &str) -> Vec<(&str, u32)> {
+        vertex_code
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| line.starts_with("attribute "))
+            .filter_map(|line| {
+                let words: Vec<&str> = line.trim_end_matches(';').split_whitespace().collect();
+                let slots = match words.get(words.len().saturating_sub(2)) {
+                    Some(&"mat2") => 2,
+                    Some(&"mat3") => 3,
+                    Some(&"mat4") => 4,
+                    _ => 1,
+                };
+                words.last().map(|name| (*name, slots))
+            })
+            .collect()
     }
 
     fn compile_shader(gl: &WebGlRenderingContext, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
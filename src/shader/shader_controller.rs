@@ -3,35 +3,88 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use web_sys::*;
 
-static BASIC_SHADER_VERTEX: &'static str = include_str!("../assets/shaders/basic_vertex.glsl");
-static BASIC_SHADER_FRAGMENT: &'static str = include_str!("../assets/shaders/basic_fragment.glsl");
+static BASIC_SHADER_VERTEX: &str = include_str!("../assets/shaders/basic_vertex.glsl");
+static BASIC_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/basic_fragment.glsl");
 
-static GRAPH3D_SHADER_VERTEX: &'static str = include_str!("../assets/shaders/graph3d_vertex.glsl");
-static GRAPH3D_SHADER_FRAGMENT: &'static str = include_str!("../assets/shaders/graph3d_fragment.glsl");
+static GRAPH3D_SHADER_VERTEX: &str = include_str!("../assets/shaders/graph3d_vertex.glsl");
+static GRAPH3D_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/graph3d_fragment.glsl");
+static GRAPH3D_GPU_SHADER_VERTEX: &str = include_str!("../assets/shaders/graph3d_gpu_vertex.glsl");
+
+static MESH_SHADER_VERTEX: &str = include_str!("../assets/shaders/mesh_vertex.glsl");
+static MESH_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/mesh_fragment.glsl");
+
+static POINT_SHADER_VERTEX: &str = include_str!("../assets/shaders/point_vertex.glsl");
+static POINT_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/point_fragment.glsl");
+
+static SCATTER_SHADER_VERTEX: &str = include_str!("../assets/shaders/scatter_vertex.glsl");
+static SCATTER_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/scatter_fragment.glsl");
+
+static INSTANCED_SHADER_VERTEX: &str = include_str!("../assets/shaders/instanced_vertex.glsl");
+
+static LINE_SHADER_VERTEX: &str = include_str!("../assets/shaders/line_vertex.glsl");
+
+static LINE_PLOT_SHADER_VERTEX: &str = include_str!("../assets/shaders/line_plot_vertex.glsl");
+
+static TEXT_SHADER_VERTEX: &str = include_str!("../assets/shaders/text_vertex.glsl");
+static TEXT_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/text_fragment.glsl");
+
+static COLOURBAR_SHADER_VERTEX: &str = include_str!("../assets/shaders/colourbar_vertex.glsl");
+static COLOURBAR_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/colourbar_fragment.glsl");
+
+static VERTEX_COLOUR_SHADER_FRAGMENT: &str = include_str!("../assets/shaders/vertex_colour_fragment.glsl");
 
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum ShaderType {
     BasicShader,
     Graph3dShader,
+    MeshShader,
+    PointShader,
+    ScatterShader,
+    InstancedShader,
+    LineShader,
+    LinePlotShader,
+    TextShader,
+    ColourbarShader,
 }
 
 pub struct ShaderController {
     shaders: HashMap<ShaderType, Shader>,
-    active_shader: RefCell<ShaderType>,
+    // None while a shader owned by an entity is in use
+    active_shader: RefCell<Option<ShaderType>>,
 }
 
 impl ShaderController {
     pub fn new(gl: &WebGlRenderingContext) -> Self {
         let mut shaders = HashMap::new();
 
-        let basic_shader = Shader::new(&gl, BASIC_SHADER_VERTEX, BASIC_SHADER_FRAGMENT).unwrap();
-        let graph3d_shader = Shader::new(&gl, GRAPH3D_SHADER_VERTEX, GRAPH3D_SHADER_FRAGMENT).unwrap();
+        // Enables fwidth() for the graph's anti-aliased grid lines and text edges where available
+        let _ = gl.get_extension("OES_standard_derivatives");
 
-        let active_shader = RefCell::new(ShaderType::BasicShader);
-        gl.use_program(Some(&basic_shader.get_program()));
+        let basic_shader = Shader::new(gl, BASIC_SHADER_VERTEX, BASIC_SHADER_FRAGMENT).unwrap();
+        let graph3d_shader = Shader::new(gl, GRAPH3D_SHADER_VERTEX, GRAPH3D_SHADER_FRAGMENT).unwrap();
+        let mesh_shader = Shader::new(gl, MESH_SHADER_VERTEX, MESH_SHADER_FRAGMENT).unwrap();
+        let point_shader = Shader::new(gl, POINT_SHADER_VERTEX, POINT_SHADER_FRAGMENT).unwrap();
+        let scatter_shader = Shader::new(gl, SCATTER_SHADER_VERTEX, SCATTER_SHADER_FRAGMENT).unwrap();
+        let instanced_shader = Shader::new(gl, INSTANCED_SHADER_VERTEX, VERTEX_COLOUR_SHADER_FRAGMENT).unwrap();
+        let line_shader = Shader::new(gl, LINE_SHADER_VERTEX, VERTEX_COLOUR_SHADER_FRAGMENT).unwrap();
+        let line_plot_shader = Shader::new(gl, LINE_PLOT_SHADER_VERTEX, VERTEX_COLOUR_SHADER_FRAGMENT).unwrap();
+        let text_shader = Shader::new(gl, TEXT_SHADER_VERTEX, TEXT_SHADER_FRAGMENT).unwrap();
+        let colourbar_shader = Shader::new(gl, COLOURBAR_SHADER_VERTEX, COLOURBAR_SHADER_FRAGMENT).unwrap();
+
+        let active_shader = RefCell::new(Some(ShaderType::BasicShader));
+        gl.use_program(Some(basic_shader.get_program()));
 
         shaders.insert(ShaderType::BasicShader, basic_shader);
         shaders.insert(ShaderType::Graph3dShader, graph3d_shader);
+        shaders.insert(ShaderType::MeshShader, mesh_shader);
+        shaders.insert(ShaderType::PointShader, point_shader);
+        shaders.insert(ShaderType::ScatterShader, scatter_shader);
+        shaders.insert(ShaderType::InstancedShader, instanced_shader);
+        shaders.insert(ShaderType::LineShader, line_shader);
+        shaders.insert(ShaderType::LinePlotShader, line_plot_shader);
+        shaders.insert(ShaderType::TextShader, text_shader);
+        shaders.insert(ShaderType::ColourbarShader, colourbar_shader);
 
         Self { shaders, active_shader }
     }
@@ -41,11 +94,24 @@ impl ShaderController {
     }
 
     pub fn use_shader(&self, gl: &WebGlRenderingContext, shader_type: ShaderType) {
-        if *self.active_shader.borrow() == shader_type {
+        if *self.active_shader.borrow() == Some(shader_type) {
             return;
         }
 
-        gl.use_program(Some(&self.shaders.get(&shader_type).unwrap().get_program()));
-        *self.active_shader.borrow_mut() = shader_type;
+        gl.use_program(Some(self.shaders.get(&shader_type).unwrap().get_program()));
+        *self.active_shader.borrow_mut() = Some(shader_type);
     }
+
+    // Switches to a shader that isn't one of the shared ones, so that the next
+    // use_shader call always switches back
+    pub fn use_custom_shader(&self, gl: &WebGlRenderingContext, shader: &Shader) {
+        gl.use_program(Some(shader.get_program()));
+        *self.active_shader.borrow_mut() = None;
+    }
+}
+
+// Graph3d's shader with a height function compiled into it, given as a GLSL
+// expression, so that heights and normals are found on the GPU
+pub fn create_graph3d_gpu_shader(gl: &WebGlRenderingContext, height_glsl: &str) -> Result<Shader, String> {
+    Shader::new(gl, &GRAPH3D_GPU_SHADER_VERTEX.replace("HEIGHT_EXPRESSION", height_glsl), GRAPH3D_SHADER_FRAGMENT)
 }
@@ -0,0 +1,264 @@
+use crate::loader::font::Font;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TextAlign {
+    Left = 0,
+    Centre = 1,
+    Right = 2,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum VerticalAlign {
+    Top = 0,
+    Middle = 1,
+    Bottom = 2,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct TextLayoutOptions {
+    // Height of the font's em square in output units
+    pub size: f32,
+    pub align: TextAlign,
+    pub vertical_align: VerticalAlign,
+    // Multiplies the font's line height
+    pub line_spacing: f32,
+}
+
+impl Default for TextLayoutOptions {
+    fn default() -> Self {
+        Self {
+            size: 16.0,
+            align: TextAlign::Left,
+            vertical_align: VerticalAlign::Top,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+// One glyph's rectangle relative to the text's anchor, with y pointing up, and
+// the matching atlas rectangle in texture coordinates
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct GlyphQuad {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub tex_coords: [f32; 4],
+}
+
+pub struct TextLayout {
+    pub quads: Vec<GlyphQuad>,
+    pub width: f32,
+    pub height: f32,
+}
+
+// Lays `text` out around an anchor at the origin. Each line is aligned on its
+// own, and the block as a whole is placed by `vertical_align`.
+pub fn layout_text(font: &Font, text: &str, options: &TextLayoutOptions) -> TextLayout {
+    let scale = options.size / font.size;
+    let line_advance = font.line_height * options.line_spacing;
+
+    let mut quads = Vec::new();
+    let mut width: f32 = 0.0;
+    let lines: Vec<&str> = text.split('\n').collect();
+
+    // Laid out in atlas pixels with y down from the top of the first line,
+    // then aligned, scaled and flipped
+    for (line_index, line) in lines.iter().enumerate() {
+        let line_top = line_index as f32 * line_advance;
+        let first_quad = quads.len();
+        let mut pen_x = 0.0;
+        let mut previous: Option<char> = None;
+
+        for character in line.trim_end_matches('\r').chars() {
+            let glyph = match font.glyph(character) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            if let Some(previous) = previous {
+                pen_x += font.kerning(previous, character);
+            }
+            if glyph.width > 0.0 && glyph.height > 0.0 {
+                quads.push(GlyphQuad {
+                    left: pen_x + glyph.x_offset,
+                    top: line_top + glyph.y_offset,
+                    right: pen_x + glyph.x_offset + glyph.width,
+                    bottom: line_top + glyph.y_offset + glyph.height,
+                    tex_coords: [
+                        glyph.x / font.atlas_width,
+                        glyph.y / font.atlas_height,
+                        (glyph.x + glyph.width) / font.atlas_width,
+                        (glyph.y + glyph.height) / font.atlas_height,
+                    ],
+                });
+            }
+            pen_x += glyph.x_advance;
+            previous = Some(character);
+        }
+
+        let shift = match options.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Centre => -pen_x * 0.5,
+            TextAlign::Right => -pen_x,
+        };
+        for quad in quads[first_quad..].iter_mut() {
+            quad.left += shift;
+            quad.right += shift;
+        }
+        width = width.max(pen_x);
+    }
+
+    let height = (lines.len() - 1) as f32 * line_advance + font.line_height;
+    let vertical_shift = match options.vertical_align {
+        VerticalAlign::Top => 0.0,
+        VerticalAlign::Middle => -height * 0.5,
+        VerticalAlign::Bottom => -height,
+    };
+    for quad in quads.iter_mut() {
+        quad.left *= scale;
+        quad.right *= scale;
+        quad.top = -(quad.top + vertical_shift) * scale;
+        quad.bottom = -(quad.bottom + vertical_shift) * scale;
+    }
+
+    TextLayout {
+        quads,
+        width: width * scale,
+        height: height * scale,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::font::{DistanceField, Glyph};
+    use std::collections::HashMap;
+
+    // Generated at 32 pixels with a 40 pixel line height. 'A' and 'V' are 22
+    // wide with their ink 1 in from the left and 2 down from the line's top,
+    // and 'V' after 'A' is kerned in by 4.
+    fn font() -> Font {
+        let letter = |x: f32| Glyph {
+            x,
+            y: 0.0,
+            width: 20.0,
+            height: 30.0,
+            x_offset: 1.0,
+            y_offset: 2.0,
+            x_advance: 22.0,
+        };
+        let mut glyphs = HashMap::new();
+        glyphs.insert('A', letter(0.0));
+        glyphs.insert('V', letter(32.0));
+        glyphs.insert(
+            ' ',
+            Glyph {
+                x: 0.0,
+                y: 0.0,
+                width: 0.0,
+                height: 0.0,
+                x_offset: 0.0,
+                y_offset: 0.0,
+                x_advance: 10.0,
+            },
+        );
+        let mut kernings = HashMap::new();
+        kernings.insert(('A', 'V'), -4.0);
+        Font {
+            glyphs,
+            kernings,
+            size: 32.0,
+            line_height: 40.0,
+            atlas_width: 256.0,
+            atlas_height: 256.0,
+            distance_field: DistanceField::Msdf,
+            distance_range: 4.0,
+        }
+    }
+
+    fn options(align: TextAlign, vertical_align: VerticalAlign) -> TextLayoutOptions {
+        TextLayoutOptions {
+            size: 32.0,
+            align,
+            vertical_align,
+            line_spacing: 1.0,
+        }
+    }
+
+    #[test]
+    fn kerning_pulls_pairs_together() {
+        let font = font();
+        let kerned = layout_text(&font, "AV", &options(TextAlign::Left, VerticalAlign::Top));
+        assert_eq!(kerned.quads[1].left, 22.0 - 4.0 + 1.0);
+        assert_eq!(kerned.width, 40.0);
+
+        // Only the listed order is kerned
+        let unkerned = layout_text(&font, "VA", &options(TextAlign::Left, VerticalAlign::Top));
+        assert_eq!(unkerned.quads[1].left, 23.0);
+        assert_eq!(unkerned.width, 44.0);
+    }
+
+    #[test]
+    fn lines_align_around_the_anchor() {
+        let font = font();
+        let left = layout_text(&font, "AV", &options(TextAlign::Left, VerticalAlign::Top));
+        let centre = layout_text(&font, "AV", &options(TextAlign::Centre, VerticalAlign::Top));
+        let right = layout_text(&font, "AV", &options(TextAlign::Right, VerticalAlign::Top));
+        assert_eq!(left.quads[0].left, 1.0);
+        assert_eq!(centre.quads[0].left, 1.0 - 20.0);
+        assert_eq!(right.quads[0].left, 1.0 - 40.0);
+        assert_eq!(right.quads[1].right, 39.0 - 40.0);
+
+        // y points up, so glyphs hang below a top anchor and straddle a middle one
+        assert_eq!(left.quads[0].top, -2.0);
+        assert_eq!(left.quads[0].bottom, -32.0);
+        let middle = layout_text(&font, "AV", &options(TextAlign::Left, VerticalAlign::Middle));
+        assert_eq!(middle.quads[0].top, 18.0);
+    }
+
+    #[test]
+    fn lines_advance_and_align_on_their_own() {
+        let font = font();
+        let mut spaced = options(TextAlign::Right, VerticalAlign::Top);
+        spaced.line_spacing = 1.5;
+        let layout = layout_text(&font, "AV\nA", &spaced);
+
+        assert_eq!(layout.quads.len(), 3);
+        assert_eq!(layout.quads[2].top, -(60.0 + 2.0));
+        assert_eq!(layout.quads[2].left, 1.0 - 22.0);
+        assert_eq!(layout.width, 40.0);
+        assert_eq!(layout.height, 60.0 + 40.0);
+    }
+
+    #[test]
+    fn sizes_scale_the_layout() {
+        let font = font();
+        let mut small = options(TextAlign::Left, VerticalAlign::Top);
+        small.size = 16.0;
+        let layout = layout_text(&font, "A V", &small);
+        // The space advances without a quad
+        assert_eq!(layout.quads.len(), 2);
+        assert_eq!(layout.quads[1].left, (22.0 + 10.0 + 1.0) * 0.5);
+        assert_eq!(layout.width, 27.0);
+        assert_eq!(layout.height, 20.0);
+    }
+
+    #[test]
+    fn missing_glyphs_fall_back_to_a_question_mark_or_are_skipped() {
+        let mut font = font();
+        let skipped = layout_text(&font, "AxV", &options(TextAlign::Left, VerticalAlign::Top));
+        assert_eq!(skipped.quads.len(), 2);
+        // The skipped character doesn't break the kerning pair around it
+        assert_eq!(skipped.width, 40.0);
+
+        let question_mark = font.glyphs[&'A'];
+        font.glyphs.insert('?', Glyph { x: 64.0, ..question_mark });
+        let replaced = layout_text(&font, "AxV", &options(TextAlign::Left, VerticalAlign::Top));
+        assert_eq!(replaced.quads.len(), 3);
+        assert_eq!(replaced.quads[1].tex_coords[0], 64.0 / 256.0);
+        assert_eq!(replaced.width, 66.0);
+    }
+}